@@ -0,0 +1,218 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
+use crate::FILE_MASKS;
+use crate::square::Square;
+
+//A set of squares, bit n standing for square n (a1 = 0, h8 = 63)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[repr(transparent)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY : Bitboard = Bitboard(0);
+    pub const FULL : Bitboard = Bitboard(!0);
+
+    #[inline(always)]
+    pub fn from_square(square : Square) -> Bitboard {
+        Bitboard(square.bit())
+    }
+    #[inline(always)]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    #[inline(always)]
+    pub fn contains(self, square : Square) -> bool {
+        self.0 & square.bit() != 0
+    }
+    #[inline(always)]
+    pub fn popcount(self) -> u32 {
+        self.0.count_ones()
+    }
+    //Lowest square of the set, None when empty
+    #[inline(always)]
+    pub fn lsb(self) -> Option<Square> {
+        if self.0 == 0 { None } else { Some(Square::new(self.0.trailing_zeros() as u8)) }
+    }
+    //Highest square of the set, None when empty
+    #[inline(always)]
+    pub fn msb(self) -> Option<Square> {
+        if self.0 == 0 { None } else { Some(Square::new(63 - self.0.leading_zeros() as u8)) }
+    }
+    //Remove and return the lowest square of the set
+    #[inline(always)]
+    pub fn pop_lsb(&mut self) -> Option<Square> {
+        let square = self.lsb()?;
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+
+    #[inline(always)]
+    pub fn north(self) -> Bitboard {
+        Bitboard(self.0 << 8)
+    }
+    #[inline(always)]
+    pub fn south(self) -> Bitboard {
+        Bitboard(self.0 >> 8)
+    }
+    #[inline(always)]
+    pub fn east(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[7]) << 1)
+    }
+    #[inline(always)]
+    pub fn west(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[0]) >> 1)
+    }
+    #[inline(always)]
+    pub fn north_east(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[7]) << 9)
+    }
+    #[inline(always)]
+    pub fn north_west(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[0]) << 7)
+    }
+    #[inline(always)]
+    pub fn south_east(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[7]) >> 7)
+    }
+    #[inline(always)]
+    pub fn south_west(self) -> Bitboard {
+        Bitboard((self.0 & !FILE_MASKS[0]) >> 9)
+    }
+}
+
+//Iterating a bitboard yields its squares from a1 to h8
+impl Iterator for Bitboard {
+    type Item = Square;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Square> {
+        self.pop_lsb()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.popcount() as usize, Some(self.popcount() as usize))
+    }
+}
+
+impl ExactSizeIterator for Bitboard {}
+
+impl From<u64> for Bitboard {
+    #[inline(always)]
+    fn from(bits : u64) -> Bitboard {
+        Bitboard(bits)
+    }
+}
+
+impl From<Bitboard> for u64 {
+    #[inline(always)]
+    fn from(bitboard : Bitboard) -> u64 {
+        bitboard.0
+    }
+}
+
+macro_rules! bitboard_operator {
+    ($trait : ident, $method : ident, $assign_trait : ident, $assign_method : ident, $op : tt) => {
+        impl $trait for Bitboard {
+            type Output = Bitboard;
+            #[inline(always)]
+            fn $method(self, rhs : Bitboard) -> Bitboard {
+                Bitboard(self.0 $op rhs.0)
+            }
+        }
+        impl $trait<u64> for Bitboard {
+            type Output = Bitboard;
+            #[inline(always)]
+            fn $method(self, rhs : u64) -> Bitboard {
+                Bitboard(self.0 $op rhs)
+            }
+        }
+        impl $assign_trait for Bitboard {
+            #[inline(always)]
+            fn $assign_method(&mut self, rhs : Bitboard) {
+                self.0 = self.0 $op rhs.0;
+            }
+        }
+        impl $assign_trait<u64> for Bitboard {
+            #[inline(always)]
+            fn $assign_method(&mut self, rhs : u64) {
+                self.0 = self.0 $op rhs;
+            }
+        }
+    };
+}
+
+bitboard_operator!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+bitboard_operator!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+bitboard_operator!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+    #[inline(always)]
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+impl Shl<u32> for Bitboard {
+    type Output = Bitboard;
+    #[inline(always)]
+    fn shl(self, rhs : u32) -> Bitboard {
+        Bitboard(self.0 << rhs)
+    }
+}
+
+impl Shr<u32> for Bitboard {
+    type Output = Bitboard;
+    #[inline(always)]
+    fn shr(self, rhs : u32) -> Bitboard {
+        Bitboard(self.0 >> rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_do_not_wrap() {
+        let e4 = Bitboard::from_square(Square::E4);
+        let shifts = [
+            (e4.north(), Square::E5), (e4.south(), Square::E3), (e4.east(), Square::F4), (e4.west(), Square::D4),
+            (e4.north_east(), Square::F5), (e4.north_west(), Square::D5), (e4.south_east(), Square::F3), (e4.south_west(), Square::D3),
+        ];
+        for (shifted, square) in shifts {
+            assert_eq!(shifted, Bitboard::from_square(square));
+        }
+        //Off the h-file, the a-file and the back ranks
+        let h = Bitboard(FILE_MASKS[7]);
+        let a = Bitboard(FILE_MASKS[0]);
+        for shifted in [h.east(), h.north_east(), h.south_east(), a.west(), a.north_west(), a.south_west()] {
+            assert_eq!(shifted, Bitboard::EMPTY);
+        }
+        assert_eq!(Bitboard::from_square(Square::H8).north(), Bitboard::EMPTY);
+        assert_eq!(Bitboard::from_square(Square::A1).south(), Bitboard::EMPTY);
+        assert_eq!(Bitboard::FULL.east().popcount(), 56);
+        assert_eq!(Bitboard::FULL.north_west().popcount(), 49);
+    }
+
+    #[test]
+    fn iteration_order() {
+        assert_eq!(Bitboard::EMPTY.count(), 0);
+        assert_eq!(Bitboard::FULL.len(), 64);
+        assert!(Bitboard::FULL.eq(Square::all()));
+        let squares = Bitboard::from_square(Square::H8) | Square::A1.bit() | Square::E4.bit();
+        assert_eq!(squares.collect::<Vec<_>>(), [Square::A1, Square::E4, Square::H8]);
+    }
+
+    #[test]
+    fn lowest_and_highest() {
+        for square in [Square::A1, Square::H8] {
+            let bitboard = Bitboard::from_square(square);
+            assert_eq!((bitboard.lsb(), bitboard.msb()), (Some(square), Some(square)));
+        }
+        assert_eq!((Bitboard::FULL.lsb(), Bitboard::FULL.msb()), (Some(Square::A1), Some(Square::H8)));
+        assert_eq!((Bitboard::EMPTY.lsb(), Bitboard::EMPTY.msb()), (None, None));
+        let mut bitboard = Bitboard::from_square(Square::A1) | Square::H8.bit();
+        assert_eq!(bitboard.pop_lsb(), Some(Square::A1));
+        assert_eq!(bitboard.pop_lsb(), Some(Square::H8));
+        assert_eq!((bitboard.pop_lsb(), bitboard), (None, Bitboard::EMPTY));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::{Game, Color, Piece, legal_moves};
+use crate::moves::Move;
+use crate::pgn::PgnGame;
+use crate::square::Square;
+use crate::zobrist::polyglot_key;
+
+//One 16 bytes record of a Polyglot book, stored big endian and sorted by key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key : u64,
+    pub raw_move : u16,
+    pub weight : u16,
+    pub learn : u32,
+}
+
+impl BookEntry {
+    pub const SIZE : u64 = 16;
+
+    fn from_bytes(bytes : &[u8;16]) -> BookEntry {
+        BookEntry {
+            key : u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            raw_move : u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight : u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn : u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8;16] {
+        let mut bytes = [0;16];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+//Polyglot move : to file, to rank, from file, from rank and promotion on 3 bits each.
+//Castling is written as the king taking its rook, like the moves of the Game
+pub fn decode_move(raw_move : u16) -> Move {
+    let square = |bits : u16| Square::from_coords((bits & 7) as u8, ((bits >> 3) & 7) as u8);
+    let promotion = match (raw_move >> 12) & 7 {
+        1 => Piece::KNIGHT,
+        2 => Piece::BISHOP,
+        3 => Piece::ROOK,
+        4 => Piece::QUEEN,
+        _ => Piece::NONE,
+    };
+    Move::new(square(raw_move >> 6), square(raw_move), promotion)
+}
+
+pub fn encode_move(chessmove : Move) -> u16 {
+    let square = |square : Square| u16::from(square.rank()) << 3 | u16::from(square.file());
+    let promotion = match chessmove.promotion {
+        Piece::KNIGHT => 1,
+        Piece::BISHOP => 2,
+        Piece::ROOK => 3,
+        Piece::QUEEN => 4,
+        _ => 0,
+    };
+    promotion << 12 | square(chessmove.from) << 6 | square(chessmove.to)
+}
+
+pub enum BookSelection {
+    //Always the move with the highest weight
+    Best,
+    //A move drawn with a probability proportional to its weight
+    Weighted,
+}
+
+//A Polyglot book read on demand from its file, nothing is loaded in memory
+pub struct Book {
+    file : File,
+    len : u64,
+}
+
+impl Book {
+    pub fn open(path : &str) -> io::Result<Book> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() / BookEntry::SIZE;
+        Ok(Book { file, len })
+    }
+
+    fn entry(&self, index : u64) -> io::Result<BookEntry> {
+        let mut bytes = [0;16];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(index * BookEntry::SIZE))?;
+        file.read_exact(&mut bytes)?;
+        Ok(BookEntry::from_bytes(&bytes))
+    }
+
+    //Every entry of `key`, found by a binary search on the first one
+    pub fn entries(&self, key : u64) -> io::Result<Vec<BookEntry>> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.entry(middle)?.key < key { low = middle + 1; } else { high = middle; }
+        }
+        let mut entries = Vec::new();
+        for index in low..self.len {
+            let entry = self.entry(index)?;
+            if entry.key != key {
+                break;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    //The legal book moves of the position with their weights, unknown or illegal entries are skipped
+    pub fn moves(&self, game : &Game) -> io::Result<Vec<(Move, u16)>> {
+        let legal = legal_moves(game);
+        let mut moves = Vec::new();
+        for entry in self.entries(polyglot_key(game))? {
+            let book_move = decode_move(entry.raw_move);
+            if legal.iter().any(|&(m, _)| m == book_move) {
+                moves.push((book_move, entry.weight));
+            }
+        }
+        Ok(moves)
+    }
+
+    pub fn pick(&self, game : &Game, selection : BookSelection) -> Option<Move> {
+        let moves = self.moves(game).ok()?;
+        match selection {
+            BookSelection::Best => moves.iter().max_by_key(|(_, weight)| *weight).map(|&(m, _)| m),
+            BookSelection::Weighted => {
+                let total : u64 = moves.iter().map(|&(_, weight)| u64::from(weight)).sum();
+                if total == 0 {
+                    return moves.first().map(|&(m, _)| m);
+                }
+                let mut draw = random_u64() % total;
+                for (chessmove, weight) in moves {
+                    if draw < u64::from(weight) {
+                        return Some(chessmove);
+                    }
+                    draw -= u64::from(weight);
+                }
+                None
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct MoveStats {
+    games : u32,
+    //2 points for a win of the side playing the move, 1 for a draw
+    points : u32,
+}
+
+pub struct BookReport {
+    pub entries : Vec<BookEntry>,
+    pub games : usize,
+    pub skipped : usize,
+}
+
+//Entries of the first `plies` moves of `games` played at least `min_count` times, weighted by their results
+pub fn build_book(games : &[PgnGame], plies : usize, min_count : u32) -> BookReport {
+    let mut stats : HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let mut skipped = 0;
+    for game in games {
+        let white_points = match game.result.as_str() {
+            "1-0" => 2,
+            "1/2-1/2" => 1,
+            "0-1" => 0,
+            _ => {
+                skipped += 1;
+                continue;
+            },
+        };
+        let Ok(played) = game.replay(plies) else {
+            skipped += 1;
+            continue;
+        };
+        for (position, chessmove) in played {
+            let entry = stats.entry((polyglot_key(&position), encode_move(chessmove))).or_default();
+            entry.games += 1;
+            entry.points += if position.side_to_move == Color::WHITE { white_points } else { 2 - white_points };
+        }
+    }
+    let mut kept : Vec<(u64, u16, u32)> = stats.into_iter()
+        .filter(|(_, stats)| stats.games >= min_count)
+        .map(|((key, raw_move), stats)| (key, raw_move, stats.points))
+        .collect();
+    kept.sort_by_key(|&(key, _, points)| (key, std::cmp::Reverse(points)));
+    let mut entries = Vec::with_capacity(kept.len());
+    //Weights are 16 bits, the moves of a position are scaled down together when their points do not fit
+    for position in kept.chunk_by(|a, b| a.0 == b.0) {
+        let max = u64::from(position[0].2.max(1));
+        let scale = |points : u32| if max > u64::from(u16::MAX) { u64::from(points) * u64::from(u16::MAX) / max } else { u64::from(points) };
+        for &(key, raw_move, points) in position {
+            entries.push(BookEntry { key, raw_move, weight : scale(points) as u16, learn : 0 });
+        }
+    }
+    BookReport { entries, games : games.len() - skipped, skipped }
+}
+
+pub fn write_book(path : &str, entries : &[BookEntry]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    for entry in entries {
+        file.write_all(&entry.to_bytes())?;
+    }
+    file.flush()
+}
+
+//Good enough randomness for picking book moves, seeded by the clock
+fn random_u64() -> u64 {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+    //splitmix64
+    let mut z = nanos.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_pgn;
+
+    fn uci(moves : &[(Move, u16)], game : &Game) -> Vec<(String, u16)> {
+        moves.iter().map(|&(m, weight)| (m.to_uci(game), weight)).collect()
+    }
+
+    #[test]
+    fn small_book() {
+        let book = Book::open(concat!(env!("CARGO_MANIFEST_DIR"), "/data/small.bin")).unwrap();
+        let mut game = Game::default();
+        let expected = [("e2e4", 100), ("d2d4", 80), ("g1f3", 20), ("c2c4", 20)].map(|(m, w)| (m.to_string(), w));
+        assert_eq!(uci(&book.moves(&game).unwrap(), &game), expected);
+        assert_eq!(book.pick(&game, BookSelection::Best).map(|m| m.to_uci(&game)), Some("e2e4".to_string()));
+        game.play("e2e4".parse().unwrap());
+        let expected = [("c7c5", 60), ("e7e5", 50), ("d7d5", 5)].map(|(m, w)| (m.to_string(), w));
+        assert_eq!(uci(&book.moves(&game).unwrap(), &game), expected);
+        game.play("a7a6".parse().unwrap());
+        assert!(book.moves(&game).unwrap().is_empty());
+        assert!(book.pick(&game, BookSelection::Weighted).is_none());
+    }
+
+    #[test]
+    fn built_book_reads_back() {
+        let pgn = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0\n\n\
+            [Result \"1/2-1/2\"]\n\n1. e4 c5 2. Nf3 1/2-1/2\n\n\
+            [Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+            [Result \"*\"]\n\n1. c4 *\n";
+        let games = parse_pgn(pgn);
+        let report = build_book(&games, 3, 1);
+        assert_eq!((report.games, report.skipped), (3, 1));
+        let path = std::env::temp_dir().join(format!("bitboard-test-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_book(path, &report.entries).unwrap();
+        let book = Book::open(path);
+        std::fs::remove_file(path).ok();
+        let book = book.unwrap();
+        let mut game = Game::default();
+        //e4 : a win and a draw, d4 : a loss
+        assert_eq!(uci(&book.moves(&game).unwrap(), &game), [("e2e4".to_string(), 3), ("d2d4".to_string(), 0)]);
+        game.play("e2e4".parse().unwrap());
+        assert_eq!(uci(&book.moves(&game).unwrap(), &game), [("c7c5".to_string(), 1), ("e7e5".to_string(), 0)]);
+        game.play("e7e5".parse().unwrap());
+        //Only the first 3 plies enter the book
+        game.play("g1f3".parse().unwrap());
+        assert!(book.moves(&game).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{Game, Color, ColoredPiece};
+use crate::fen::MAX_FULLMOVE;
+use crate::square::Square;
+use crate::validate::PositionError;
+
+//Set up a position piece by piece, the result is only handed out once it passes validation
+#[derive(Clone, Copy)]
+pub struct PositionBuilder {
+    game : Game,
+    fullmove : u16,
+}
+
+impl PositionBuilder {
+    pub fn new() -> PositionBuilder {
+        PositionBuilder { game : Game::empty(), fullmove : 1 }
+    }
+
+    pub fn from_game(game : &Game) -> PositionBuilder {
+        PositionBuilder { game : *game, fullmove : game.nb_coups / 2 + 1 }
+    }
+
+    //Replace whatever stands on `square`
+    pub fn put(&mut self, square : Square, piece : ColoredPiece) -> &mut PositionBuilder {
+        self.game.remove_piece(square);
+        self.game.put_piece(square, piece);
+        self
+    }
+
+    pub fn remove(&mut self, square : Square) -> &mut PositionBuilder {
+        self.game.remove_piece(square);
+        self
+    }
+
+    //Empty the board, with no castling right nor en passant square left
+    pub fn clear(&mut self) -> &mut PositionBuilder {
+        for square in Square::all() {
+            self.game.remove_piece(square);
+        }
+        self.game.set_castling_rights((false, false, false, false));
+        self.game.en_passant = 0;
+        self
+    }
+
+    pub fn side_to_move(&mut self, color : Color) -> &mut PositionBuilder {
+        self.game.side_to_move = color;
+        self
+    }
+
+    //(white king side, white queen side, black king side, black queen side) as in Game::castling_rights
+    pub fn castling(&mut self, rights : (bool, bool, bool, bool)) -> &mut PositionBuilder {
+        self.game.set_castling_rights(rights);
+        self
+    }
+
+    pub fn en_passant(&mut self, square : Option<Square>) -> &mut PositionBuilder {
+        self.game.en_passant = square.map_or(0, Square::bit);
+        self
+    }
+
+    pub fn halfmove(&mut self, halfmove : u16) -> &mut PositionBuilder {
+        self.game.halfmove = halfmove;
+        self
+    }
+
+    pub fn fullmove(&mut self, fullmove : u16) -> &mut PositionBuilder {
+        self.fullmove = fullmove.max(1);
+        self
+    }
+
+    //The position as it is now, valid or not
+    pub fn board(&self) -> &Game {
+        &self.game
+    }
+
+    //Every reason why the position is refused, not only the first one
+    pub fn build(&self) -> Result<Game, Vec<PositionError>> {
+        let mut game = self.game;
+        let mut errors = Vec::new();
+        if self.fullmove > MAX_FULLMOVE {
+            errors.push(PositionError::FullmoveOutOfRange(self.fullmove));
+        }
+        game.nb_coups = self.fullmove.min(MAX_FULLMOVE) * 2 - 2 + u16::from(game.side_to_move == Color::BLACK);
+        errors.extend(game.validate().err().unwrap_or_default());
+        if errors.is_empty() { Ok(game) } else { Err(errors) }
+    }
+}
+
+impl Default for PositionBuilder {
+    fn default() -> Self {
+        PositionBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Piece;
+
+    #[test]
+    fn build_reports_every_error() {
+        let mut builder = PositionBuilder::new();
+        builder.put(Square::E1, ColoredPiece::new(Color::WHITE, Piece::KING))
+            .put(Square::A8, ColoredPiece::new(Color::WHITE, Piece::PAWN))
+            .fullmove(40000);
+        let errors = builder.build().err().unwrap_or_default();
+        assert_eq!(errors, vec![
+            PositionError::FullmoveOutOfRange(40000),
+            PositionError::PawnOnBackRank(Square::A8),
+            PositionError::KingCount(Color::BLACK, 0),
+        ]);
+        builder.remove(Square::A8).put(Square::E8, ColoredPiece::new(Color::BLACK, Piece::KING)).fullmove(12);
+        assert_eq!(builder.build().map(|game| game.to_fen()), Ok("4k3/8/8/8/8/8/8/4K3 w - - 0 12".to_string()));
+    }
+}
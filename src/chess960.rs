@@ -0,0 +1,43 @@
+use crate::{Game, array_to_bitboard};
+
+//Files of the two knights among the five squares left after the bishops and the queen
+static KNIGHTS : [(usize, usize);10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+//Back rank of the Chess960 starting position number `index` in the Scharnagl numbering, 518 is the standard one
+pub fn back_rank(index : u16) -> Option<[char;8]> {
+    if index >= 960 {
+        return None;
+    }
+    let mut rank = [' ';8];
+    let mut n = index as usize;
+    rank[2 * (n % 4) + 1] = 'B';
+    n /= 4;
+    rank[2 * (n % 4)] = 'B';
+    n /= 4;
+    //The queen, then the knights, go on the n-th empty square
+    let mut place = |piece : char, nth : usize| {
+        let file = (0..8).filter(|&f| rank[f] == ' ').nth(nth).unwrap();
+        rank[file] = piece;
+    };
+    place('Q', n % 6);
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    place('N', second);
+    place('N', first);
+    //Rook, king and rook on the three squares left
+    for piece in ['R', 'K', 'R'] {
+        place(piece, 0);
+    }
+    Some(rank)
+}
+
+pub fn chess960_position(index : u16) -> Option<Game> {
+    let white = back_rank(index)?;
+    let black = white.map(|c| c.to_ascii_lowercase());
+    let empty = [' ';8];
+    let mut game = Game::empty();
+    array_to_bitboard([black, ['p';8], empty, empty, empty, empty, ['P';8], white], &mut game);
+    game.set_castling_rights((true, true, true, true));
+    game.chess960 = true;
+    Some(game)
+}
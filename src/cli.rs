@@ -0,0 +1,303 @@
+use crate::{Game, Instant, perft};
+use crate::book::{build_book, write_book};
+use crate::endgame::{Endgames, SIGNATURES};
+use crate::engine::EngineProcess;
+use crate::mcts::{Mcts, MctsOptions};
+use crate::pgn::parse_pgn;
+use crate::san::move_to_san;
+use crate::search::{bench, uci_score, SearchLimits, SearchOptions, TT_MEGABYTES};
+use crate::selfplay::{load_openings, parse_engine_options, run_match, Adjudication, EngineConfig, MatchSettings, Sprt};
+
+const USAGE : &str = "usage :
+  bitboard                                  interactive mode, 'uci' switches to the UCI protocol
+  bitboard makebook [options] <pgn>...      build a Polyglot book from PGN files
+      --output <file>     book to write (default book.bin)
+      --plies <n>         plies of each game entering the book (default 20)
+      --min-count <n>     games needed to keep a move (default 1)
+  bitboard endgame [KQK|KRK|KPK|KBNK]...    solve small endgames by retrograde analysis and print their statistics
+  bitboard bench [options]                  search the bench positions, print the nodes and the speed
+      --depth <n>         depth of each search (default 5)
+      --threads <n>       threads of each search (default 1)
+      --without <names>   search features to turn off, separated by commas, among
+                          MoveOrdering, NullMove, LMR, ReverseFutility, Futility, Razoring, CheckExtensions
+      --no-ordering       same as --without MoveOrdering
+  bitboard perft [options]                  time the move generator on standard perft positions
+      --runs <n>          runs of each position, the fastest counts (default 5)
+      --shallow           one ply less everywhere
+  bitboard mcts [options] [fen]             search a position (default the start) with Monte Carlo tree search
+      --iterations <n>    playouts (default 10000)
+      --exploration <c>   weight of exploration in UCT (default 1.4)
+      --playout <n>       plies of a playout before the evaluation decides (default 16)
+      --seed <n>          seed of the playouts (default 1)
+      --hash <mb>         memory of the tree, the iterations go on without new nodes once full (default 16)
+      --random            uniform playouts, captures are favoured by default
+  bitboard uci                              speak UCI from the start
+  bitboard selfplay [options]               play a match between two configurations of the engine
+      --games <n>         games to play, the most with --sprt (default 100)
+      --concurrency <n>   games played at once (default 1)
+      --tc <base+inc>     time control in seconds (default 10+0.1)
+      --openings <file>   EPD or PGN openings, each one played with both colours
+      --opening-plies <n> plies of the PGN games used as openings (default 8)
+      --first <options>   UCI options of the first configuration, as Name=Value,Name=Value
+      --second <options>  UCI options of the second configuration
+      --pgn <file>        append the games to a PGN file
+      --sprt <e0,e1,a,b>  stop once elo0 or elo1 is accepted with the error rates alpha and beta, e.g. 0,5,0.05,0.05
+      --draw <m,n,cp>     draw from move m when both sides see at most cp for n moves (default 40,8,10, n = 0 disables)
+      --resign <n,cp>     win when both sides see one ahead by cp for n moves (default 3,600, n = 0 disables)
+      --max-plies <n>     draw past this length (default 400)
+  bitboard match [options]                  play a match against an external UCI engine, takes the options of selfplay
+      --engine <command>  the engine to run
+      --args <arguments>  its arguments, separated by spaces
+      --ours <options>    UCI options of this engine, as Name=Value,Name=Value
+      --options <options> UCI options of the external engine";
+
+//Value following `--name`, parsed, or `default`
+fn option<T : std::str::FromStr>(args : &mut Vec<String>, name : &str, default : T) -> Result<T, String> {
+    let Some(i) = args.iter().position(|a| a == name) else {
+        return Ok(default);
+    };
+    if i + 1 >= args.len() {
+        return Err(format!("missing value after {name}"));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    value.parse().map_err(|_| format!("invalid value '{value}' for {name}"))
+}
+
+fn make_book(mut args : Vec<String>) -> Result<(), String> {
+    let output : String = option(&mut args, "--output", "book.bin".to_string())?;
+    let plies : usize = option(&mut args, "--plies", 20)?;
+    let min_count : u32 = option(&mut args, "--min-count", 1)?;
+    if args.is_empty() {
+        return Err("no PGN file given".to_string());
+    }
+    let now = Instant::now();
+    let mut games = Vec::new();
+    for path in &args {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path} : {e}"))?;
+        games.extend(parse_pgn(&text));
+    }
+    let report = build_book(&games, plies, min_count);
+    write_book(&output, &report.entries).map_err(|e| format!("cannot write {output} : {e}"))?;
+    println!("{} games used, {} skipped, {} entries written to {output} in {} ms",
+        report.games, report.skipped, report.entries.len(), now.elapsed().as_millis());
+    Ok(())
+}
+
+fn endgame(args : Vec<String>) -> Result<(), String> {
+    let names : Vec<&str> = if args.is_empty() { SIGNATURES.to_vec() } else { args.iter().map(String::as_str).collect() };
+    let now = Instant::now();
+    let endgames = Endgames::solve(&names)?;
+    println!("solved in {} ms", now.elapsed().as_millis());
+    for table in endgames.tables() {
+        let stats = table.stats();
+        println!("{}", table.name);
+        for (side, name) in ["white", "black"].iter().enumerate() {
+            println!("  {name} to move : {} wins, {} draws, {} losses", stats.wins[side], stats.draws[side], stats.losses[side]);
+        }
+        if let Some((plies, game)) = stats.longest {
+            println!("  longest mate : {} moves ({plies} plies), {}", plies.div_ceil(2), game.to_fen());
+        }
+    }
+    Ok(())
+}
+
+fn run_bench(mut args : Vec<String>) -> Result<(), String> {
+    let depth : u32 = option(&mut args, "--depth", 5)?;
+    let threads : usize = option(&mut args, "--threads", 1)?;
+    let without : String = option(&mut args, "--without", String::new())?;
+    let no_ordering = args.iter().position(|a| a == "--no-ordering").map(|i| args.remove(i)).is_some();
+    let mut options = SearchOptions { threads, ..SearchOptions::default() };
+    for name in without.split(',').filter(|name| !name.is_empty()).chain(no_ordering.then_some("MoveOrdering")) {
+        options.set(name, false)?;
+    }
+    if let Some(unknown) = args.first() {
+        return Err(format!("unknown option '{unknown}'"));
+    }
+    let report = bench(depth, &options)?;
+    println!("{} nodes in {} ms, {} nps", report.nodes, report.milliseconds, report.nodes as u128 * 1000 / report.milliseconds.max(1));
+    Ok(())
+}
+
+//Perft positions of the Chess Programming Wiki with their node counts
+pub const PERFT_POSITIONS : [(&str, u32, u64);4] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 5, 4865609),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4, 4085603),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6, 11030083),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 5, 15833292),
+];
+
+fn run_perft(mut args : Vec<String>) -> Result<(), String> {
+    let runs : u32 = option(&mut args, "--runs", 5)?;
+    let shallow = args.iter().position(|a| a == "--shallow").map(|i| args.remove(i)).is_some();
+    if let Some(unknown) = args.first() {
+        return Err(format!("unknown option '{unknown}'"));
+    }
+    let (mut total_nodes, mut total_micros) = (0, 0);
+    for (fen, depth, expected) in PERFT_POSITIONS {
+        let game = Game::from_fen(fen)?;
+        let depth = if shallow { depth - 1 } else { depth };
+        let mut best = u128::MAX;
+        let mut nodes = 0;
+        for _ in 0..runs.max(1) {
+            let now = Instant::now();
+            nodes = perft(&game, depth);
+            best = best.min(now.elapsed().as_micros());
+        }
+        if !shallow && nodes != expected {
+            return Err(format!("perft {depth} of {fen} gives {nodes} nodes instead of {expected}"));
+        }
+        println!("perft {depth} : {nodes:>9} nodes in {:>5} ms, {fen}", best / 1000);
+        total_nodes += nodes;
+        total_micros += best;
+    }
+    println!("{total_nodes} nodes in {} ms, {} nps", total_micros / 1000, u128::from(total_nodes) * 1_000_000 / total_micros.max(1));
+    Ok(())
+}
+
+fn run_mcts(mut args : Vec<String>) -> Result<(), String> {
+    let defaults = MctsOptions::default();
+    let iterations = option(&mut args, "--iterations", defaults.iterations)?;
+    let exploration = option(&mut args, "--exploration", defaults.exploration)?;
+    let playout_plies = option(&mut args, "--playout", defaults.playout_plies)?;
+    let seed = option(&mut args, "--seed", 1)?;
+    let megabytes : usize = option(&mut args, "--hash", TT_MEGABYTES)?;
+    let random = args.iter().position(|a| a == "--random").map(|i| args.remove(i)).is_some();
+    let options = MctsOptions { iterations, exploration, biased : !random, playout_plies, max_nodes : Mcts::nodes_in(megabytes.max(1)) };
+    let game = if args.is_empty() { Game::default() } else { Game::from_fen(&args.join(" "))? };
+    let now = Instant::now();
+    let result = Mcts::new(seed).search(&game, &options, &SearchLimits::depth(1), &mut |_| {});
+    let milliseconds = now.elapsed().as_millis();
+    let mut pv_game = game;
+    let pv : Vec<String> = result.pv.iter().map(|&m| {
+        let san = move_to_san(&pv_game, m);
+        pv_game.play(m);
+        san
+    }).collect();
+    println!("score {}, pv {}", uci_score(result.score), pv.join(" "));
+    println!("{} iterations in {milliseconds} ms, {} per second", result.nodes, result.nodes as u128 * 1000 / milliseconds.max(1));
+    Ok(())
+}
+
+//Comma separated numbers after `--name`
+fn numbers<T : std::str::FromStr, const N : usize>(args : &mut Vec<String>, name : &str, default : [T;N]) -> Result<[T;N], String> {
+    let text : String = option(args, name, String::new())?;
+    if text.is_empty() {
+        return Ok(default);
+    }
+    let values : Vec<T> = text.split(',').map(|v| v.trim().parse()).collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid value '{text}' for {name}"))?;
+    values.try_into().map_err(|_| format!("{name} takes {N} values separated by commas"))
+}
+
+//The options shared by selfplay and match
+fn match_settings(args : &mut Vec<String>, engines : [EngineConfig;2]) -> Result<MatchSettings, String> {
+    let games : usize = option(args, "--games", 100)?;
+    let concurrency : usize = option(args, "--concurrency", 1)?;
+    let time_control = option(args, "--tc", "10+0.1".to_string())?.parse()?;
+    let openings : String = option(args, "--openings", String::new())?;
+    let opening_plies : usize = option(args, "--opening-plies", 8)?;
+    let pgn : String = option(args, "--pgn", String::new())?;
+    let sprt : String = option(args, "--sprt", String::new())?;
+    let defaults = Adjudication::default();
+    let [draw_after, draw_count, draw_score] = numbers(args, "--draw", [defaults.draw_after, defaults.draw_count, defaults.draw_score as u32])?;
+    let [resign_count, resign_score] = numbers(args, "--resign", [defaults.resign_count, defaults.resign_score as u32])?;
+    let max_plies = option(args, "--max-plies", defaults.max_plies)?;
+    Ok(MatchSettings {
+        engines,
+        openings : if openings.is_empty() { Vec::new() } else { load_openings(&openings, opening_plies)? },
+        games,
+        concurrency,
+        time_control,
+        adjudication : Adjudication { draw_after, draw_count, draw_score : draw_score as i32, resign_count, resign_score : resign_score as i32, max_plies },
+        pgn : Some(pgn).filter(|pgn| !pgn.is_empty()),
+        sprt : if sprt.is_empty() { None } else { Some(sprt.parse::<Sprt>()?) },
+    })
+}
+
+//This executable speaking UCI, with the options given as Name=Value,Name=Value
+fn this_engine(name : &str, options : &str) -> Result<EngineConfig, String> {
+    let command = std::env::current_exe().map_err(|e| format!("cannot find the engine : {e}"))?.to_string_lossy().into_owned();
+    let name = if options.is_empty() { name.to_string() } else { format!("{name} ({options})") };
+    Ok(EngineConfig { name, command, args : vec!["uci".to_string()], options : parse_engine_options(options)? })
+}
+
+//Play the match, print every game then the score of the first engine
+fn play_match(settings : &MatchSettings) -> Result<(), String> {
+    let now = Instant::now();
+    let first = &settings.engines[0].name;
+    let report = run_match(settings, &|record, score| {
+        let sprt = settings.sprt.map(|sprt| {
+            let (lower, upper) = sprt.bounds();
+            format!(", LLR {:.2} [{lower:.2}, {upper:.2}]", sprt.llr(score))
+        }).unwrap_or_default();
+        println!("game {} : {} - {} {}, {} ; {}-{}-{} ({:.1}%){sprt}", record.round,
+            record.pgn.tag("White").unwrap_or("?"), record.pgn.tag("Black").unwrap_or("?"), record.result.to_pgn(), record.reason,
+            score.wins, score.losses, score.draws, 100.0 * score.points());
+    })?;
+    let score = report.score;
+    println!("{} games in {} s : {} wins, {} losses, {} draws for {first}, {:.1}%", score.games(), now.elapsed().as_secs(),
+        score.wins, score.losses, score.draws, 100.0 * score.points());
+    match score.elo() {
+        Some((elo, margin)) => println!("Elo difference : {elo:.1} +/- {margin:.1}"),
+        None => println!("Elo difference : unbounded, every game was won by the same engine"),
+    }
+    match report.sprt {
+        Some(true) => println!("SPRT : H1 accepted, {first} is stronger"),
+        Some(false) => println!("SPRT : H0 accepted"),
+        None if settings.sprt.is_some() => println!("SPRT : undecided"),
+        None => {},
+    }
+    Ok(())
+}
+
+fn selfplay(mut args : Vec<String>) -> Result<(), String> {
+    let first : String = option(&mut args, "--first", String::new())?;
+    let second : String = option(&mut args, "--second", String::new())?;
+    let engines = [this_engine("bitboard A", &first)?, this_engine("bitboard B", &second)?];
+    let settings = match_settings(&mut args, engines)?;
+    if let Some(unknown) = args.first() {
+        return Err(format!("unknown option '{unknown}'"));
+    }
+    play_match(&settings)
+}
+
+fn external_match(mut args : Vec<String>) -> Result<(), String> {
+    let command : String = option(&mut args, "--engine", String::new())?;
+    let engine_args : String = option(&mut args, "--args", String::new())?;
+    let ours : String = option(&mut args, "--ours", String::new())?;
+    let theirs : String = option(&mut args, "--options", String::new())?;
+    if command.is_empty() {
+        return Err("no engine given, see --engine".to_string());
+    }
+    let engine_args : Vec<String> = engine_args.split_whitespace().map(str::to_string).collect();
+    //Started once to check that it speaks UCI and to learn its name
+    let name = EngineProcess::start(&command, &engine_args)?.name.clone();
+    let engines = [this_engine("bitboard", &ours)?, EngineConfig { name, command, args : engine_args, options : parse_engine_options(&theirs)? }];
+    let settings = match_settings(&mut args, engines)?;
+    if let Some(unknown) = args.first() {
+        return Err(format!("unknown option '{unknown}'"));
+    }
+    play_match(&settings)
+}
+
+//Run the subcommand named by the first argument, return false if there is none
+pub fn run(mut args : Vec<String>) -> Result<bool, String> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let command = args.remove(0);
+    match command.as_str() {
+        "makebook" => make_book(args)?,
+        "endgame" => endgame(args)?,
+        "bench" => run_bench(args)?,
+        "perft" => run_perft(args)?,
+        "mcts" => run_mcts(args)?,
+        "uci" => crate::uci::run(""),
+        "selfplay" => selfplay(args)?,
+        "match" => external_match(args)?,
+        "help" | "--help" | "-h" => println!("{USAGE}"),
+        _ => return Err(format!("unknown subcommand '{command}'\n{USAGE}")),
+    }
+    Ok(true)
+}
@@ -0,0 +1,428 @@
+use crate::{Game, Color, Piece, ColoredPiece, legal_moves, is_attacked, piece_attacks};
+use crate::bitboard::Bitboard;
+use crate::square::Square;
+
+//Exact distance to mate of small endgames, solved in memory by retrograde analysis.
+//The strong side is stored as white, a position is indexed by the side to move, the two
+//kings and the other pieces, 64 squares each.
+
+//Distance to mate in plies for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtm {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+//Values of the table : plies to mate + 1, or one of these
+const UNKNOWN : u8 = 0;
+const DRAW : u8 = 254;
+const ILLEGAL : u8 = 255;
+
+pub const SIGNATURES : [&str;4] = ["KQK", "KRK", "KPK", "KBNK"];
+
+fn signature_pieces(name : &str) -> Option<Vec<Piece>> {
+    match name {
+        "KQK" => Some(vec![Piece::QUEEN]),
+        "KRK" => Some(vec![Piece::ROOK]),
+        "KPK" => Some(vec![Piece::PAWN]),
+        "KBNK" => Some(vec![Piece::BISHOP, Piece::KNIGHT]),
+        _ => None,
+    }
+}
+
+pub struct EndgameTable {
+    pub name : &'static str,
+    //White pieces besides the king
+    pieces : Vec<Piece>,
+    values : Vec<u8>,
+}
+
+pub struct TableStats {
+    //[white to move, black to move]
+    pub wins : [u64;2],
+    pub draws : [u64;2],
+    pub losses : [u64;2],
+    pub longest : Option<(u32, Game)>,
+}
+
+impl EndgameTable {
+    fn squares(&self, mut index : usize) -> (Color, Vec<Square>) {
+        let count = 2 + self.pieces.len();
+        let mut squares = vec![Square::A1;count];
+        for square in squares.iter_mut().rev() {
+            *square = Square::new((index % 64) as u8);
+            index /= 64;
+        }
+        (if index == 0 { Color::WHITE } else { Color::BLACK }, squares)
+    }
+
+    fn index(side_to_move : Color, squares : &[Square]) -> usize {
+        squares.iter().fold(side_to_move.index(), |index, square| index * 64 + square.index())
+    }
+
+    //The legal position of an index, white king, black king then the pieces
+    fn position(&self, index : usize) -> Option<Game> {
+        let (side_to_move, squares) = self.squares(index);
+        let mut game = Game::empty();
+        for (i, &square) in squares.iter().enumerate() {
+            let piece = match i {
+                0 => ColoredPiece::new(Color::WHITE, Piece::KING),
+                1 => ColoredPiece::new(Color::BLACK, Piece::KING),
+                _ => ColoredPiece::new(Color::WHITE, self.pieces[i - 2]),
+            };
+            if game.piece_at(square).is_some() || (piece.piece == Piece::PAWN && (square.rank() == 0 || square.rank() == 7)) {
+                return None;
+            }
+            game.put_piece(square, piece);
+        }
+        game.side_to_move = side_to_move;
+        if is_attacked(!side_to_move, &game) {
+            return None;
+        }
+        Some(game)
+    }
+
+    //Positions from which the side that just moved reached `index`
+    fn predecessors(&self, index : usize) -> Vec<usize> {
+        let (side_to_move, squares) = self.squares(index);
+        let mover = !side_to_move;
+        let occupied = squares.iter().fold(0, |bits, square| bits | square.bit());
+        let mut predecessors = Vec::new();
+        for (i, &square) in squares.iter().enumerate() {
+            let piece = match i {
+                0 if mover == Color::WHITE => Piece::KING,
+                1 if mover == Color::BLACK => Piece::KING,
+                _ if i >= 2 && mover == Color::WHITE => self.pieces[i - 2],
+                _ => continue,
+            };
+            let sources = if piece == Piece::PAWN {
+                let mut sources = 0;
+                if square.rank() >= 2 && occupied & (square.bit() >> 8) == 0 {
+                    sources |= square.bit() >> 8;
+                    if square.rank() == 3 && occupied & (square.bit() >> 16) == 0 {
+                        sources |= square.bit() >> 16;
+                    }
+                }
+                sources
+            }
+            else {
+                piece_attacks(piece, square, occupied) & !occupied
+            };
+            let mut before = squares.clone();
+            for source in Bitboard(sources) {
+                before[i] = source;
+                predecessors.push(Self::index(mover, &before));
+            }
+        }
+        predecessors
+    }
+
+    //Solve the table, the tables reached by a promotion must be in `solved`
+    pub fn solve(name : &'static str, solved : &[EndgameTable]) -> Option<EndgameTable> {
+        let pieces = signature_pieces(name)?;
+        let size = 2 * 64usize.pow(2 + pieces.len() as u32);
+        let mut table = EndgameTable { name, pieces, values : vec![UNKNOWN;size] };
+        //Legal moves left to refute for black, and white wins found through a promotion
+        let mut counters = vec![0u8;size / 2];
+        let mut promotions = Vec::new();
+        for index in 0..size {
+            let Some(game) = table.position(index) else {
+                table.values[index] = ILLEGAL;
+                continue;
+            };
+            if game.side_to_move == Color::WHITE {
+                if !table.pieces.contains(&Piece::PAWN) {
+                    continue;
+                }
+                //Only promotions leave the table, the lone king cannot mate
+                let mut best : Option<u32> = None;
+                for (chessmove, _) in legal_moves(&game).into_iter().filter(|(m, _)| m.promotion != Piece::NONE) {
+                    let mut next = game;
+                    next.play(chessmove);
+                    let result = if next.occupied().count_ones() == 3 && matches!(chessmove.promotion, Piece::BISHOP | Piece::KNIGHT) {
+                        Some(Dtm::Draw)
+                    }
+                    else {
+                        solved.iter().find_map(|table| table.probe(&next))
+                    };
+                    if let Some(Dtm::Loss(plies)) = result {
+                        best = Some(best.map_or(plies + 1, |best| best.min(plies + 1)));
+                    }
+                }
+                if let Some(plies) = best {
+                    promotions.push((index, plies));
+                }
+                continue;
+            }
+            let moves = legal_moves(&game);
+            if moves.is_empty() {
+                table.values[index] = if is_attacked(Color::BLACK, &game) { 1 } else { DRAW };
+            }
+            else if moves.iter().any(|(m, _)| game.side(Color::WHITE) & m.to.bit() != 0) {
+                //Taking a piece leaves a draw, or at least a smaller table we do not look at
+                table.values[index] = DRAW;
+            }
+            else {
+                counters[index - size / 2] = moves.len() as u8;
+            }
+        }
+        //Level by level : black losses in d plies give white wins in d + 1, and black positions
+        //lose in d + 1 once every move is refuted by a win in d
+        let mut plies = 0;
+        loop {
+            let mut found = false;
+            for &(index, win) in &promotions {
+                if win == plies && table.values[index] == UNKNOWN {
+                    table.values[index] = plies as u8 + 1;
+                }
+            }
+            for index in 0..size {
+                if table.values[index] != plies as u8 + 1 {
+                    continue;
+                }
+                found = true;
+                for before in table.predecessors(index) {
+                    if table.values[before] != UNKNOWN {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        table.values[before] = plies as u8 + 2;
+                    }
+                    else {
+                        let counter = &mut counters[before - size / 2];
+                        *counter -= 1;
+                        if *counter == 0 {
+                            table.values[before] = plies as u8 + 2;
+                        }
+                    }
+                }
+            }
+            plies += 1;
+            if !found && promotions.iter().all(|&(_, win)| win < plies) {
+                break;
+            }
+        }
+        Some(table)
+    }
+
+    //The index of `game` if it has the material of the table, the strong side being mirrored to white
+    fn index_of(&self, game : &Game) -> Option<usize> {
+        if game.castling_rights() != (false, false, false, false) {
+            return None;
+        }
+        let strong = if game.side(Color::BLACK).count_ones() == 1 { Color::WHITE } else { Color::BLACK };
+        if game.side(!strong).count_ones() != 1 || game.side(strong).count_ones() as usize != 1 + self.pieces.len() {
+            return None;
+        }
+        let mirror = |square : Square| if strong == Color::WHITE { square } else { square.flip_rank() };
+        let mut squares = vec![mirror(game.king_square(strong)), mirror(game.king_square(!strong))];
+        for &piece in &self.pieces {
+            let square = Bitboard(game.pieces(strong, piece)).lsb()?;
+            squares.push(mirror(square));
+        }
+        let side_to_move = if game.side_to_move == strong { Color::WHITE } else { Color::BLACK };
+        Some(Self::index(side_to_move, &squares))
+    }
+
+    fn dtm(value : u8) -> Dtm {
+        match value {
+            UNKNOWN | DRAW | ILLEGAL => Dtm::Draw,
+            value if value % 2 == 0 => Dtm::Win(u32::from(value) - 1),
+            value => Dtm::Loss(u32::from(value) - 1),
+        }
+    }
+
+    pub fn probe(&self, game : &Game) -> Option<Dtm> {
+        let index = self.index_of(game)?;
+        match self.values[index] {
+            ILLEGAL => None,
+            value => Some(Self::dtm(value)),
+        }
+    }
+
+    pub fn stats(&self) -> TableStats {
+        let mut stats = TableStats { wins : [0;2], draws : [0;2], losses : [0;2], longest : None };
+        let mut longest = (0, 0);
+        for (index, &value) in self.values.iter().enumerate() {
+            if value == ILLEGAL {
+                continue;
+            }
+            let side = index / (self.values.len() / 2);
+            match Self::dtm(value) {
+                Dtm::Win(plies) => {
+                    stats.wins[side] += 1;
+                    if plies > longest.0 {
+                        longest = (plies, index);
+                    }
+                },
+                Dtm::Loss(_) => stats.losses[side] += 1,
+                Dtm::Draw => stats.draws[side] += 1,
+            }
+        }
+        if longest.0 > 0 {
+            stats.longest = self.position(longest.1).map(|game| (longest.0, game));
+        }
+        stats
+    }
+}
+
+//The requested tables solved in order, with the tables they depend on
+pub struct Endgames {
+    tables : Vec<EndgameTable>,
+}
+
+impl Endgames {
+    pub fn solve(names : &[&str]) -> Result<Endgames, String> {
+        let mut endgames = Endgames { tables : Vec::new() };
+        for name in names {
+            let name = SIGNATURES.iter().find(|&&s| s.eq_ignore_ascii_case(name))
+                .ok_or(format!("unknown endgame '{name}', expected one of {}", SIGNATURES.join(", ")))?;
+            let needed : &[&'static str] = if *name == "KPK" { &["KQK", "KRK", "KPK"] } else { std::slice::from_ref(name) };
+            for &needed in needed {
+                if endgames.table(needed).is_none() {
+                    let table = EndgameTable::solve(needed, &endgames.tables).ok_or(format!("cannot solve {needed}"))?;
+                    endgames.tables.push(table);
+                }
+            }
+        }
+        Ok(endgames)
+    }
+
+    pub fn table(&self, name : &str) -> Option<&EndgameTable> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    pub fn tables(&self) -> &[EndgameTable] {
+        &self.tables
+    }
+
+    pub fn probe(&self, game : &Game) -> Option<Dtm> {
+        self.tables.iter().find_map(|table| table.probe(game))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    //Solved once for all the tests, it takes a while without optimizations
+    fn solved() -> &'static Endgames {
+        static ENDGAMES : OnceLock<Endgames> = OnceLock::new();
+        ENDGAMES.get_or_init(|| Endgames::solve(&["kqk", "KRK", "KPK"]).unwrap())
+    }
+
+    fn probe(endgames : &Endgames, fen : &str) -> Option<Dtm> {
+        endgames.probe(&Game::from_fen(fen).unwrap())
+    }
+
+    //Every solved value, sampled by `step`, is one ply more than the best reply, or a draw when the material leaves the tables
+    fn check_consistency(endgames : &Endgames, name : &str, step : usize) {
+        let table = endgames.table(name).unwrap();
+        for index in (0..table.values.len()).step_by(step) {
+            let Some(game) = table.position(index) else {
+                continue;
+            };
+            let moves = legal_moves(&game);
+            let replies = moves.iter().map(|&(m, _)| {
+                let mut next = game;
+                next.play(m);
+                match endgames.probe(&next).unwrap_or(Dtm::Draw) {
+                    Dtm::Win(plies) => Dtm::Loss(plies + 1),
+                    Dtm::Loss(plies) => Dtm::Win(plies + 1),
+                    Dtm::Draw => Dtm::Draw,
+                }
+            });
+            let wins = replies.clone().filter_map(|dtm| if let Dtm::Win(plies) = dtm { Some(plies) } else { None }).min();
+            let expected = if moves.is_empty() {
+                if is_attacked(game.side_to_move, &game) { Dtm::Loss(0) } else { Dtm::Draw }
+            }
+            else if let Some(plies) = wins {
+                Dtm::Win(plies)
+            }
+            else if replies.clone().all(|dtm| matches!(dtm, Dtm::Loss(_))) {
+                Dtm::Loss(replies.filter_map(|dtm| if let Dtm::Loss(plies) = dtm { Some(plies) } else { None }).max().unwrap())
+            }
+            else {
+                Dtm::Draw
+            };
+            assert_eq!(table.probe(&game), Some(expected), "{}", game.to_fen());
+        }
+    }
+
+    #[test]
+    fn known_positions() {
+        let endgames = solved();
+        assert_eq!(probe(endgames, "k7/8/1K6/8/8/8/8/7R w - - 0 1"), Some(Dtm::Win(1)));
+        assert_eq!(probe(endgames, "R6k/8/7K/8/8/8/8/8 b - - 0 1"), Some(Dtm::Loss(0)));
+        //Stalemate, and the rook taken
+        assert_eq!(probe(endgames, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        assert_eq!(probe(endgames, "8/8/8/8/8/2k5/1R6/7K b - - 0 1"), Some(Dtm::Draw));
+        //The strong side can be black
+        let white = probe(endgames, "8/8/8/4k3/8/8/8/KQ6 w - - 0 1");
+        assert!(matches!(white, Some(Dtm::Win(_))));
+        assert_eq!(probe(endgames, "kq6/8/8/8/4K3/8/8/8 b - - 0 1"), white);
+        //Other material, castling rights
+        assert_eq!(probe(endgames, "8/8/8/4k3/8/8/8/KB6 w - - 0 1"), None);
+        assert_eq!(probe(endgames, "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), None);
+    }
+
+    #[test]
+    fn pawn_endings() {
+        let endgames = solved();
+        //Mate by promotion, to a queen or a rook
+        assert_eq!(probe(endgames, "k7/2P5/1K6/8/8/8/8/8 w - - 0 1"), Some(Dtm::Win(1)));
+        //The king in front of a rook pawn, and a stalemate
+        assert_eq!(probe(endgames, "k7/8/8/P7/8/8/8/K7 w - - 0 1"), Some(Dtm::Draw));
+        assert_eq!(probe(endgames, "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(Dtm::Draw));
+        //The opposition decides, and the pawn can be black
+        let loss = probe(endgames, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1");
+        assert!(matches!(loss, Some(Dtm::Loss(_))));
+        assert_eq!(probe(endgames, "8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"), loss);
+        assert_eq!(probe(endgames, "8/8/8/4K3/8/4k3/8/8 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn longest_mates() {
+        let endgames = solved();
+        //Mate in 10, 16 and 28 moves
+        for (name, plies) in [("KQK", 19), ("KRK", 31), ("KPK", 55)] {
+            let stats = endgames.table(name).unwrap().stats();
+            let (longest, game) = stats.longest.unwrap();
+            assert_eq!(longest, plies, "{name}");
+            assert_eq!(endgames.probe(&game), Some(Dtm::Win(plies)));
+            assert_eq!(stats.losses[0], 0, "{name}");
+            assert_eq!(stats.wins[1], 0, "{name}");
+            assert!(stats.wins[0] > stats.draws[0] && stats.losses[1] > 0, "{name}");
+        }
+        let stats = endgames.table("KPK").unwrap().stats();
+        assert_eq!((stats.wins[0], stats.draws[0]), (124960, 38368));
+    }
+
+    //cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn bishop_and_knight_mate() {
+        let endgames = Endgames::solve(&["KBNK"]).unwrap();
+        let (longest, game) = endgames.table("KBNK").unwrap().stats().longest.unwrap();
+        //Mate in 33 moves
+        assert_eq!(longest, 65);
+        assert_eq!(endgames.probe(&game), Some(Dtm::Win(65)));
+        check_consistency(&endgames, "KBNK", 10007);
+    }
+
+    #[test]
+    fn values_follow_the_best_reply() {
+        let endgames = solved();
+        check_consistency(endgames, "KQK", 97);
+        check_consistency(endgames, "KRK", 97);
+        check_consistency(endgames, "KPK", 97);
+    }
+
+    #[test]
+    fn unknown_endgame() {
+        assert!(Endgames::solve(&["KNNK"]).is_err());
+        assert!(solved().table("KQK").is_some());
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use crate::Instant;
+use crate::search::MATE;
+
+//A UCI engine run as a child process, its output read on a thread so that reads can time out
+
+//How long an engine may take to answer "uci" and "isready"
+const HANDSHAKE_TIMEOUT : Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineFailure {
+    //No answer in time
+    TimedOut,
+    //The process ended or closed its pipes
+    Disconnected,
+}
+
+//Answer to "go"
+pub struct EngineMove {
+    pub bestmove : String,
+    //Last score reported, mates as +-(MATE - plies)
+    pub score : Option<i32>,
+    pub elapsed : Duration,
+}
+
+pub struct EngineProcess {
+    //"id name" of the engine
+    pub name : String,
+    child : Child,
+    stdin : ChildStdin,
+    lines : Receiver<String>,
+}
+
+//Score of an info line, "score cp 35" or "score mate -3", mate in 3 moves is MATE - 5 plies
+//and getting mated in 3 is -MATE + 6. What follows "string" is free text.
+fn parse_score(line : &str) -> Option<i32> {
+    let words : Vec<&str> = line.split_whitespace().take_while(|&w| w != "string").collect();
+    let at = words.iter().position(|&w| w == "score")?;
+    let value : i32 = words.get(at + 2)?.parse().ok()?;
+    match *words.get(at + 1)? {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - 2 * value + 1),
+        "mate" => Some(-MATE - 2 * value),
+        _ => None,
+    }
+}
+
+impl EngineProcess {
+    pub fn start(command : &str, args : &[String]) -> Result<EngineProcess, String> {
+        let mut child = Command::new(command).args(args)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null())
+            .spawn().map_err(|e| format!("cannot start {command} : {e}"))?;
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = EngineProcess { name : command.to_string(), child, stdin, lines };
+        engine.send("uci").map_err(|_| format!("{command} does not read its input"))?;
+        loop {
+            let line = engine.read_line(HANDSHAKE_TIMEOUT).map_err(|e| format!("{command} does not speak UCI : {e:?}"))?;
+            if let Some(name) = line.trim().strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+            if line.trim() == "uciok" {
+                break;
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, line : &str) -> Result<(), EngineFailure> {
+        writeln!(self.stdin, "{line}").and_then(|_| self.stdin.flush()).map_err(|_| EngineFailure::Disconnected)
+    }
+
+    fn read_line(&mut self, timeout : Duration) -> Result<String, EngineFailure> {
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => EngineFailure::TimedOut,
+            RecvTimeoutError::Disconnected => EngineFailure::Disconnected,
+        })
+    }
+
+    //Wait until the engine has read everything sent so far
+    pub fn sync(&mut self) -> Result<(), EngineFailure> {
+        self.send("isready")?;
+        while self.read_line(HANDSHAKE_TIMEOUT)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name : &str, value : &str) -> Result<(), EngineFailure> {
+        self.send(&format!("setoption name {name} value {value}"))
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineFailure> {
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    //Search `position` ("startpos moves ..." or "fen ... moves ..."), the engine has `timeout` to answer
+    pub fn go(&mut self, position : &str, go : &str, timeout : Duration) -> Result<EngineMove, EngineFailure> {
+        self.send(&format!("position {position}"))?;
+        self.send(&format!("go {go}"))?;
+        let start = Instant::now();
+        let mut score = None;
+        loop {
+            let line = self.read_line(timeout.saturating_sub(start.elapsed()))?;
+            let line = line.trim();
+            if line.starts_with("info") {
+                score = parse_score(line).or(score);
+            }
+            else if let Some(rest) = line.strip_prefix("bestmove") {
+                let bestmove = rest.split_whitespace().next().unwrap_or("0000").to_string();
+                return Ok(EngineMove { bestmove, score, elapsed : start.elapsed() });
+            }
+        }
+    }
+
+    fn quit(&mut self) {
+        self.send("quit").ok();
+        //Give it a moment before it is killed
+        for _ in 0..50 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        self.quit();
+    }
+}
+
+//A UCI engine written in sh for the tests, `go` runs the commands given for it and `plies`
+//counts the moves of the last position
+#[cfg(all(test, unix))]
+pub(crate) fn sh_engine_script(name : &str, go : &str) -> std::path::PathBuf {
+    let script = format!("while read -r line; do
+  case \"$line\" in
+    uci) echo 'id name {name}'; echo 'option name Hash type spin default 16 min 1 max 64'; echo uciok ;;
+    isready) echo readyok ;;
+    position*) set -- $line; plies=$(($# > 2 ? $# - 3 : 0)) ;;
+    go*) {go} ;;
+    quit) exit 0 ;;
+  esac
+done
+");
+    let path = std::env::temp_dir().join(format!("bitboard-mock-{}-{name}.sh", std::process::id()));
+    std::fs::write(&path, script).unwrap();
+    path
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::search::uci_score;
+
+    fn mock_engine(name : &str, go : &str) -> Result<EngineProcess, String> {
+        let path = sh_engine_script(name, go);
+        let engine = EngineProcess::start("sh", &[path.to_string_lossy().to_string()]);
+        std::fs::remove_file(&path).ok();
+        engine
+    }
+
+    #[test]
+    fn handshake_and_search() {
+        let mut engine = mock_engine("Mock", "echo 'info depth 1 score cp 12 pv e2e4'; echo 'info depth 2 score mate -2'; echo 'bestmove e2e4 ponder e7e5'").unwrap();
+        assert_eq!(engine.name, "Mock");
+        engine.set_option("Hash", "32").unwrap();
+        engine.new_game().unwrap();
+        let answer = engine.go("startpos", "movetime 10", Duration::from_secs(5)).unwrap();
+        assert_eq!((answer.bestmove.as_str(), answer.score), ("e2e4", Some(-MATE + 4)));
+        //No info line and nothing after bestmove
+        let mut engine = mock_engine("Mute", "echo bestmove").unwrap();
+        let answer = engine.go("startpos moves e2e4", "depth 1", Duration::from_secs(5)).unwrap();
+        assert_eq!((answer.bestmove.as_str(), answer.score), ("0000", None));
+    }
+
+    #[test]
+    fn timeouts_and_disconnections() {
+        let mut engine = mock_engine("Slow", ":").unwrap();
+        let start = Instant::now();
+        assert_eq!(engine.go("startpos", "movetime 10", Duration::from_millis(200)).err(), Some(EngineFailure::TimedOut));
+        assert!(start.elapsed() >= Duration::from_millis(200) && start.elapsed() < Duration::from_secs(5));
+        let mut engine = mock_engine("Crash", "exit 1").unwrap();
+        assert_eq!(engine.go("startpos", "movetime 10", Duration::from_secs(5)).err(), Some(EngineFailure::Disconnected));
+        assert_eq!(engine.sync().err(), Some(EngineFailure::Disconnected));
+        //Not an engine at all
+        assert!(EngineProcess::start("sh", &["-c".to_string(), "exit 0".to_string()]).is_err());
+        assert!(EngineProcess::start("/nonexistent/engine", &[]).is_err());
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(parse_score("info depth 5 score cp -35 nodes 100 pv e2e4"), Some(-35));
+        assert_eq!(parse_score("info depth 5 score cp 20 lowerbound"), Some(20));
+        assert_eq!(parse_score("info depth 9 score mate 1 pv d8h4"), Some(MATE - 1));
+        assert_eq!(parse_score("info depth 9 score mate -1"), Some(-MATE + 2));
+        //Already mated
+        assert_eq!(parse_score("info depth 0 score mate 0"), Some(-MATE));
+        assert_eq!(parse_score("info depth 5 nodes 100"), None);
+        assert_eq!(parse_score("info string the score cp 500 is free text"), None);
+        assert_eq!(parse_score("info score wdl 500"), None);
+        //Back to the same text as our own engine writes
+        for moves in (-20..=20).filter(|&moves| moves != 0) {
+            let score = parse_score(&format!("info score mate {moves}")).unwrap();
+            assert_eq!(uci_score(score), format!("mate {moves}"));
+        }
+    }
+}
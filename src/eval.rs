@@ -0,0 +1,35 @@
+use crate::{Game, Color, Piece, SQUARE_CENTER, RANK_MASK};
+
+pub const PAWN_VALUE : i32 = 100;
+pub const KNIGHT_VALUE : i32 = 320;
+pub const BISHOP_VALUE : i32 = 330;
+pub const ROOK_VALUE : i32 = 500;
+pub const QUEEN_VALUE : i32 = 900;
+
+fn count(bitboard : u64) -> i32 {
+    bitboard.count_ones() as i32
+}
+
+//Static evaluation in centipawns from white's point of view
+pub fn evaluate_white(game : &Game) -> i32 {
+    let white = &game.bitboards[Color::WHITE.index()];
+    let black = &game.bitboards[Color::BLACK.index()];
+    let mut score = 0;
+    for (piece, value) in [(Piece::PAWN, PAWN_VALUE), (Piece::KNIGHT, KNIGHT_VALUE), (Piece::BISHOP, BISHOP_VALUE), (Piece::ROOK, ROOK_VALUE), (Piece::QUEEN, QUEEN_VALUE)] {
+        score += value * (count(white[piece.index()]) - count(black[piece.index()]));
+    }
+    let (wp, bp) = (white[Piece::PAWN.index()], black[Piece::PAWN.index()]);
+    let (wn, bn) = (white[Piece::KNIGHT.index()], black[Piece::KNIGHT.index()]);
+    //Occupying the center with pawns and knights
+    score += 20 * (count((wp | wn) & SQUARE_CENTER) - count((bp | bn) & SQUARE_CENTER));
+    //Advanced pawns
+    for r in 3..7 {
+        score += 5 * (r as i32 - 2) * (count(wp & RANK_MASK[r]) - count(bp & RANK_MASK[7 - r]));
+    }
+    score
+}
+
+//Static evaluation in centipawns from the side to move's point of view
+pub fn evaluate(game : &Game) -> i32 {
+    if game.side_to_move == Color::WHITE { evaluate_white(game) } else { -evaluate_white(game) }
+}
@@ -0,0 +1,182 @@
+use crate::{Game, Color, ColoredPiece, piece_char};
+use crate::square::Square;
+
+//Largest fullmove number a Game can count, its plies are kept in a u16
+pub const MAX_FULLMOVE : u16 = u16::MAX / 2;
+
+impl Game {
+    pub fn from_fen(fen : &str) -> Result<Game, String> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or("empty FEN")?;
+        let mut game = Game::empty();
+        let ranks : Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("expected 8 ranks in the placement, found {}", ranks.len()));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let r = 7 - i as u8;
+            let mut f = 0u8;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    if f as u32 + empty > 8 {
+                        return Err(format!("rank {} is too long", r + 1));
+                    }
+                    f += empty as u8;
+                    continue;
+                }
+                if f >= 8 {
+                    return Err(format!("rank {} is too long", r + 1));
+                }
+                let piece = ColoredPiece::from_char(c).ok_or(format!("unknown piece '{c}'"))?;
+                game.put_piece(Square::from_coords(f, r), piece);
+                f += 1;
+            }
+            if f != 8 {
+                return Err(format!("rank {} does not have 8 squares", r + 1));
+            }
+        }
+        game.side_to_move = match fields.next().unwrap_or("w") {
+            "w" => Color::WHITE,
+            "b" => Color::BLACK,
+            side => return Err(format!("unknown side to move '{side}'")),
+        };
+        set_castling_field(&mut game, fields.next().unwrap_or("-"))?;
+        let en_passant = fields.next().unwrap_or("-");
+        if en_passant != "-" {
+            let square : Square = en_passant.parse().map_err(|_| format!("invalid en passant square '{en_passant}'"))?;
+            game.en_passant = square.bit();
+        }
+        game.halfmove = match fields.next() {
+            Some(halfmove) => halfmove.parse().map_err(|_| format!("invalid halfmove clock '{halfmove}'"))?,
+            None => 0,
+        };
+        let fullmove : u16 = match fields.next() {
+            Some(fullmove) => fullmove.parse().ok().filter(|&f| f <= MAX_FULLMOVE)
+                .ok_or(format!("invalid fullmove number '{fullmove}', expected at most {MAX_FULLMOVE}"))?,
+            None => 1,
+        };
+        game.nb_coups = fullmove.max(1) * 2 - 2 + u16::from(game.side_to_move == Color::BLACK);
+        game.validate().map_err(|errors| {
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+        })?;
+        Ok(game)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for r in (0..8).rev() {
+            let mut empty = 0;
+            for f in 0..8 {
+                let c = piece_char(self, Square::from_coords(f, r));
+                if c == ' ' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                fen.push(c);
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if r > 0 {
+                fen.push('/');
+            }
+        }
+        fen.push_str(if self.side_to_move == Color::WHITE { " w " } else { " b " });
+        let mut castling = String::new();
+        for (color, king_side, letter) in [(Color::WHITE, true, 'K'), (Color::WHITE, false, 'Q'), (Color::BLACK, true, 'k'), (Color::BLACK, false, 'q')] {
+            let Some(rook) = self.castling_rook(color, king_side) else {
+                continue;
+            };
+            //X-FEN : the file of the rook only when it is not the outermost one
+            if !self.chess960 || self.outer_rook(color, king_side) == Some(rook) {
+                castling.push(letter);
+            }
+            else {
+                let file = (b'A' + rook.file()) as char;
+                castling.push(if color == Color::WHITE { file } else { file.to_ascii_lowercase() });
+            }
+        }
+        if castling.is_empty() { castling.push('-'); }
+        fen.push_str(&castling);
+        fen.push(' ');
+        if self.en_passant != 0 {
+            fen.push_str(&Square::new(self.en_passant.trailing_zeros() as u8).to_string());
+        }
+        else {
+            fen.push('-');
+        }
+        fen.push_str(&format!(" {} {}", self.halfmove, self.nb_coups / 2 + 1));
+        fen
+    }
+}
+
+//Castling field of a FEN or X-FEN ("KQkq", "-", "KQkqB" when an inner rook castles) or of a Shredder-FEN ("HAha")
+fn set_castling_field(game : &mut Game, castling : &str) -> Result<(), String> {
+    for c in castling.chars() {
+        if c == '-' {
+            continue;
+        }
+        let color = if c.is_ascii_uppercase() { Color::WHITE } else { Color::BLACK };
+        let rank = if color == Color::WHITE { 0 } else { 7 };
+        let (king_side, rook) = match c.to_ascii_lowercase() {
+            'k' => (true, game.outer_rook(color, true).unwrap_or(Square::from_coords(7, rank))),
+            'q' => (false, game.outer_rook(color, false).unwrap_or(Square::from_coords(0, rank))),
+            file @ 'a'..='h' => {
+                let rook = Square::from_coords(file as u8 - b'a', rank);
+                (rook.file() > game.king_square(color).file(), rook)
+            },
+            _ => return Err(format!("unknown castling right '{c}'")),
+        };
+        game.set_castling_rook(color, king_side, Some(rook));
+    }
+    game.chess960 = game.has_chess960_castling();
+    Ok(())
+}
+
+//Castling field of a FEN, e.g. "KQkq" or "-"
+pub fn parse_castling(castling : &str) -> Result<(bool, bool, bool, bool), String> {
+    let mut rights = (false, false, false, false);
+    for c in castling.chars() {
+        match c {
+            'K' => rights.0 = true,
+            'Q' => rights.1 = true,
+            'k' => rights.2 = true,
+            'q' => rights.3 = true,
+            '-' => {},
+            _ => return Err(format!("unknown castling right '{c}'")),
+        }
+    }
+    Ok(rights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PositionBuilder;
+    use crate::validate::PositionError;
+
+    #[test]
+    fn fullmove_out_of_range_is_rejected() {
+        assert!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 40000").is_err());
+        let game = Game::from_fen(&format!("4k3/8/8/8/8/8/8/4K3 b - - 65535 {MAX_FULLMOVE}")).unwrap();
+        assert_eq!(game.to_fen(), format!("4k3/8/8/8/8/8/8/4K3 b - - 65535 {MAX_FULLMOVE}"));
+        let mut next = game;
+        for chessmove in ["e8d8", "e1d1", "d8e8"] {
+            next.play(chessmove.parse().unwrap());
+        }
+        assert_eq!(next.halfmove, u16::MAX);
+        let mut builder = PositionBuilder::from_game(&game);
+        assert_eq!(builder.fullmove(40000).build().err(), Some(vec![PositionError::FullmoveOutOfRange(40000)]));
+    }
+
+    #[test]
+    fn long_runs_of_empty_squares_are_rejected() {
+        assert!(Game::from_fen("4k3/99999999999999999999999999999/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(Game::from_fen("4k3/54/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(Game::from_fen("4k3/44/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+    }
+}
@@ -1,15 +1,47 @@
 use std::collections::VecDeque;
-use std::{io};
-use std::env;
 use lazy_static::lazy_static;
 #[cfg(target_os = "linux")]
 use minstant::Instant;
 
 #[cfg(not(target_os = "linux"))]
 use std::time::Instant;
-use bitintr::{Tzcnt, Lzcnt, Andn, Popcnt};
+use moves::Move;
+use bitboard::Bitboard;
+use square::Square;
 
+mod bitboard;
+mod book;
+mod builder;
+mod chess960;
+mod cli;
+mod endgame;
+mod engine;
+mod eval;
+mod fen;
+mod mate;
+mod mcts;
+mod movepick;
+mod moves;
+mod pgn;
+mod render;
+mod repl;
+mod san;
+mod search;
+mod selfplay;
+#[cfg(test)]
+mod snapshot;
+mod square;
+mod svg;
+mod syzygy;
+mod time;
+mod tt;
+mod uci;
+mod validate;
+mod zobrist;
 
+
+//Board convention used everywhere : a1 = 0, h1 = 7, h8 = 63 and uppercase letters for white.
+//Char grids are written as the board is seen by white, row 0 is the 8th rank like in a FEN.
 static BASICSTART_CHESS_BOARD:[[char;8];8] = [
     ['r','n','b','q','k','b','n','r'],
     ['p','p','p','p','p','p','p','p'],
@@ -20,8 +52,10 @@ static BASICSTART_CHESS_BOARD:[[char;8];8] = [
     ['P','P','P','P','P','P','P','P'],
     ['R','N','B','Q','K','B','N','R'],
 ];
+#[cfg(test)]
+static BASICSTART_FEN : &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Piece {
     NONE,
     PAWN,
@@ -31,30 +65,251 @@ pub enum Piece {
     QUEEN,
     KING
 }
+static PIECES : [Piece;6] = [Piece::PAWN, Piece::KNIGHT, Piece::BISHOP, Piece::ROOK, Piece::QUEEN, Piece::KING];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Color {
+    WHITE,
+    BLACK
+}
+impl Color {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+impl std::ops::Not for Color {
+    type Output = Color;
+    fn not(self) -> Color {
+        match self {
+            Color::WHITE => Color::BLACK,
+            Color::BLACK => Color::WHITE,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ColoredPiece {
+    pub color : Color,
+    pub piece : Piece,
+}
+impl ColoredPiece {
+    pub fn new(color : Color, piece : Piece) -> ColoredPiece {
+        ColoredPiece { color, piece }
+    }
+    //FEN letter, uppercase for white
+    pub fn to_char(self) -> char {
+        let c = match self.piece {
+            Piece::PAWN => 'p',
+            Piece::KNIGHT => 'n',
+            Piece::BISHOP => 'b',
+            Piece::ROOK => 'r',
+            Piece::QUEEN => 'q',
+            Piece::KING => 'k',
+            Piece::NONE => ' ',
+        };
+        if self.color == Color::WHITE { c.to_ascii_uppercase() } else { c }
+    }
+    pub fn from_char(c : char) -> Option<ColoredPiece> {
+        let piece = match c.to_ascii_lowercase() {
+            'p' => Piece::PAWN,
+            'n' => Piece::KNIGHT,
+            'b' => Piece::BISHOP,
+            'r' => Piece::ROOK,
+            'q' => Piece::QUEEN,
+            'k' => Piece::KING,
+            _ => return None,
+        };
+        let color = if c.is_ascii_uppercase() { Color::WHITE } else { Color::BLACK };
+        Some(ColoredPiece { color, piece })
+    }
+}
+
+impl Piece {
+    //Index in the piece dimension of the board storage, NONE has no bitboard
+    pub fn index(self) -> usize {
+        self as usize - 1
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Game {
-    pub wp : u64, pub wn : u64, pub wb : u64, pub wr : u64, pub wq : u64, pub wk : u64,
-    pub bp : u64, pub bn : u64, pub bb : u64, pub br : u64, pub bq : u64, pub bk : u64,
-    pub white_to_play : bool,
-    wking_rook_never_move : bool,
-    wqueen_rook_never_move : bool,
-    wking_never_move : bool,
-    bking_rook_never_move : bool,
-    bqueen_rook_never_move : bool,
-    bking_never_move : bool,
+    pub bitboards : [[u64;6];2],
+    mailbox : [Option<ColoredPiece>;64],
+    pub side_to_move : Color,
+    //Starting square of the rooks that may still castle, [colour][king side, queen side]
+    castling_rooks : [[Option<Square>;2];2],
+    //Castling moves are written king takes rook in UCI
+    pub chess960 : bool,
+    pub en_passant : u64,
+    pub halfmove : u16,
     pub nb_coups : u16,
+    //Polyglot key of the pieces alone, the rest of the key is cheap to add
+    pieces_key : u64,
 }
 impl Game {
     pub fn occupied(&self) -> u64 {
-        self.wp | self.wn | self.wb | self.wr | self.wq | self.wk | self.bp | self.bn | self.bb | self.br | self.bq | self.bk
+        self.white() | self.black()
     }
     pub fn white(&self) -> u64 {
-        self.wp | self.wn | self.wb | self.wr | self.wq | self.wk
+        self.side(Color::WHITE)
     }
     pub fn black(&self) -> u64 {
-        self.bp | self.bn | self.bb | self.br | self.bq | self.bk
+        self.side(Color::BLACK)
+    }
+    //All the pieces of one side
+    pub fn side(&self, color : Color) -> u64 {
+        self.bitboards[color.index()].iter().fold(0, |all, bitboard| all | bitboard)
+    }
+    pub fn pieces(&self, color : Color, piece : Piece) -> u64 {
+        if piece == Piece::NONE {
+            return 0;
+        }
+        self.bitboards[color.index()][piece.index()]
+    }
+    pub fn piece_at(&self, square : Square) -> Option<ColoredPiece> {
+        self.mailbox[square.index()]
+    }
+    //Every piece change goes through put_piece and remove_piece to keep the mailbox in sync
+    fn put_piece(&mut self, square : Square, piece : ColoredPiece) {
+        self.bitboards[piece.color.index()][piece.piece.index()] |= square.bit();
+        self.mailbox[square.index()] = Some(piece);
+        self.pieces_key ^= zobrist::piece_key(piece, square);
+    }
+    fn remove_piece(&mut self, square : Square) -> Option<ColoredPiece> {
+        let piece = self.mailbox[square.index()].take()?;
+        self.bitboards[piece.color.index()][piece.piece.index()] &= !square.bit();
+        self.pieces_key ^= zobrist::piece_key(piece, square);
+        Some(piece)
+    }
+    fn move_piece(&mut self, from : Square, to : Square) {
+        if let Some(piece) = self.remove_piece(from) {
+            self.put_piece(to, piece);
+        }
+    }
+    //The bitboards do not overlap, the mailbox and the key agree with them
+    pub fn is_consistent(&self) -> bool {
+        let mut seen = 0u64;
+        for color in [Color::WHITE, Color::BLACK] {
+            for piece in PIECES {
+                let bitboard = self.pieces(color, piece);
+                if seen & bitboard != 0 {
+                    return false;
+                }
+                seen |= bitboard;
+            }
+        }
+        Square::all().all(|square| {
+            let from_bitboards = [Color::WHITE, Color::BLACK].into_iter()
+                .flat_map(|color| PIECES.into_iter().map(move |piece| ColoredPiece::new(color, piece)))
+                .find(|p| self.pieces(p.color, p.piece) & square.bit() != 0);
+            from_bitboards == self.mailbox[square.index()]
+        }) && self.pieces_key == zobrist::pieces_key(self)
+    }
+    pub fn king_square(&self, color : Color) -> Square {
+        Bitboard(self.pieces(color, Piece::KING)).lsb().unwrap_or_default()
+    }
+    //Remove the piece of the given side standing on `target` and return its value
+    fn remove_captured(&mut self, color : Color, target : Square) -> i8 {
+        match self.piece_at(target) {
+            Some(captured) if captured.color == color && captured.piece != Piece::KING => {
+                self.remove_piece(target);
+                match captured.piece {
+                    Piece::PAWN => 1,
+                    Piece::KNIGHT | Piece::BISHOP => 3,
+                    Piece::ROOK => 5,
+                    _ => 11,
+                }
+            },
+            _ => 0,
+        }
+    }
+    //A move from or to the starting square of a castling rook loses that right
+    fn update_castling_rights(&mut self, touched : u64) {
+        for rooks in self.castling_rooks.iter_mut() {
+            for rook in rooks.iter_mut() {
+                if rook.is_some_and(|square| touched & square.bit() != 0) {
+                    *rook = None;
+                }
+            }
+        }
+    }
+    pub fn castling_rook(&self, color : Color, king_side : bool) -> Option<Square> {
+        self.castling_rooks[color.index()][usize::from(!king_side)]
+    }
+    pub fn set_castling_rook(&mut self, color : Color, king_side : bool, rook : Option<Square>) {
+        self.castling_rooks[color.index()][usize::from(!king_side)] = rook;
+    }
+    //Castling rights as (white king side, white queen side, black king side, black queen side)
+    pub fn castling_rights(&self) -> (bool, bool, bool, bool) {
+        (
+            self.castling_rook(Color::WHITE, true).is_some(),
+            self.castling_rook(Color::WHITE, false).is_some(),
+            self.castling_rook(Color::BLACK, true).is_some(),
+            self.castling_rook(Color::BLACK, false).is_some(),
+        )
+    }
+    //Each right goes to the outermost rook on that side of the king, or to the corner if there is none
+    pub fn set_castling_rights(&mut self, rights : (bool, bool, bool, bool)) {
+        for (color, king_side, right) in [
+            (Color::WHITE, true, rights.0), (Color::WHITE, false, rights.1),
+            (Color::BLACK, true, rights.2), (Color::BLACK, false, rights.3),
+        ] {
+            let corner = Square::from_coords(if king_side { 7 } else { 0 }, if color == Color::WHITE { 0 } else { 7 });
+            let rook = right.then(|| self.outer_rook(color, king_side).unwrap_or(corner));
+            self.set_castling_rook(color, king_side, rook);
+        }
+    }
+    //Castling rights that only exist in Chess960 : a king off the e file or a rook off the corner
+    pub fn has_chess960_castling(&self) -> bool {
+        [(Color::WHITE, 0), (Color::BLACK, 7)].into_iter().any(|(color, rank)| {
+            [true, false].into_iter().any(|king_side| {
+                self.castling_rook(color, king_side).is_some_and(|rook| {
+                    rook != Square::from_coords(if king_side { 7 } else { 0 }, rank) || self.king_square(color) != Square::from_coords(4, rank)
+                })
+            })
+        })
+    }
+    //The rook of `color` on its first rank farthest from the king on the given side
+    pub fn outer_rook(&self, color : Color, king_side : bool) -> Option<Square> {
+        let rank = if color == Color::WHITE { 0 } else { 7 };
+        let king = self.king_square(color);
+        if self.pieces(color, Piece::KING) == 0 || king.rank() != rank {
+            return None;
+        }
+        let mut rooks = Bitboard(self.pieces(color, Piece::ROOK) & RANK_MASK[rank as usize]);
+        if king_side {
+            rooks.filter(|rook| rook.file() > king.file()).last()
+        }
+        else {
+            rooks.find(|rook| rook.file() < king.file())
+        }
+    }
+    pub fn empty() -> Game {
+        Game {
+            bitboards : [[0;6];2],
+            mailbox : [None;64],
+            side_to_move : Color::WHITE,
+            castling_rooks : [[None;2];2],
+            chess960 : false,
+            en_passant : 0,
+            halfmove : 0,
+            nb_coups : 0,
+            pieces_key : 0,
+        }
+    }
+    //Play a move for the side to move, return -1 and leave the game untouched if it is not legal
+    pub fn play(&mut self, chessmove : Move) -> i8 {
+        let mut next = *self;
+        let response = compute_move(self.side_to_move, chessmove, &mut next);
+        if response < 0 || is_attacked(self.side_to_move, &next) {
+            return -1;
+        }
+        next.side_to_move = !next.side_to_move;
+        next.nb_coups = next.nb_coups.saturating_add(1);
+        debug_assert!(next.is_consistent());
+        *self = next;
+        response
     }
-    
 }
 impl Default for Game {
     fn default() -> Self { 
@@ -62,17 +317,12 @@ impl Default for Game {
     }
 }
 
-pub fn convert_square_to_move(a_move : u64) -> String{
-    let b = (a_move / 8) as u8;
-    let a:u8 = (a_move % 8) as u8;
-    let f = (b'a' + a ) as char;
-    let mut a = String::from(f);
-    a.push((48 + b+1 ) as char );
-    a
+pub fn convert_square_to_move(a_move : Square) -> String{
+    a_move.to_string()
 }
 
 static RANK_MASK : [u64;8] = [
-    255, 65280, 16711680, 4278190080, 1095216660480, 280375465082880, 71776119061217280, 18374686479671624000
+    255, 65280, 16711680, 4278190080, 1095216660480, 280375465082880, 71776119061217280, 18374686479671623680
 ];
 static FILE_MASKS : [u64;8] = [
     0x101010101010101, 0x202020202020202, 0x404040404040404, 0x808080808080808,
@@ -95,22 +345,23 @@ static ANTIDIAG_MASKS : [u64;15] = [
 ];
 
 pub static SQUARE_CENTER : u64 = 103481868288;
+static PROMOTIONS : [Piece;4] = [Piece::QUEEN, Piece::KNIGHT, Piece::ROOK, Piece::BISHOP];
 
 lazy_static! {
     static ref FIRST_RANK_ATTACKS: [[u64; 8]; 64] = {
         let mut first_rank_attacks = [[0; 8]; 64];
-        for o in 0..64 {
-            for f in 0..8 {
-                first_rank_attacks[o][f] = 0;
+        for (o, attacks) in first_rank_attacks.iter_mut().enumerate() {
+            for (f, attack) in attacks.iter_mut().enumerate() {
+                *attack = 0;
 
                 for i in (f + 1)..8 {
-                    first_rank_attacks[o][f] |= 1 << i;
+                    *attack |= 1 << i;
                     if (o << 1) & (1 << i) > 0 {
                         break;
                     }
                 }
                 for i in (0..f).rev() {
-                    first_rank_attacks[o][f] |= 1 << i;
+                    *attack |= 1 << i;
                     if (o << 1) & (1 << i) > 0 {
                         break;
                     }
@@ -137,139 +388,67 @@ lazy_static! {
     };
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn array_to_bitboard(chessboard : [[char;8]; 8], wp:&mut u64, wn:&mut u64, wb:&mut u64, wr:&mut u64, wq:&mut u64, wk:&mut u64, bp:&mut u64, bn:&mut u64, bb:&mut u64, br:&mut u64, bq:&mut u64, bk:&mut u64) {
-    let mut i = 0;
-    for v in chessboard {
-        for c in v {
-            match c {
-                'p' => { *wp += convert_string_to_bitboard(i); },
-                'n' => { *wn += convert_string_to_bitboard(i); },
-                'b' => { *wb += convert_string_to_bitboard(i); },
-                'r' => { *wr += convert_string_to_bitboard(i); },
-                'q' => { *wq += convert_string_to_bitboard(i); },
-                'k' => { *wk += convert_string_to_bitboard(i); },
-                'P' => { *bp += convert_string_to_bitboard(i); },
-                'N' => { *bn += convert_string_to_bitboard(i); },
-                'B' => { *bb += convert_string_to_bitboard(i); },
-                'R' => { *br += convert_string_to_bitboard(i); },
-                'Q' => { *bq += convert_string_to_bitboard(i); },
-                'K' => { *bk += convert_string_to_bitboard(i); },
-                _ => {}
+//Place the pieces of a char grid on an empty game, unknown chars are empty squares
+pub fn array_to_bitboard(chessboard : [[char;8]; 8], game : &mut Game) {
+    for (row, line) in chessboard.iter().enumerate() {
+        for (file, &c) in line.iter().enumerate() {
+            if let Some(piece) = ColoredPiece::from_char(c) {
+                game.put_piece(Square::from_coords(file as u8, 7 - row as u8), piece);
             }
-            i+=1;
         }
     }
 }
-pub fn get_game_from_basicpos() -> Game {
-    let mut wp : u64 = 0;
-    let mut wn : u64 = 0;
-    let mut wb : u64 = 0;
-    let mut wr : u64 = 0;
-    let mut wq : u64 = 0;
-    let mut wk : u64 = 0;
-    let mut bp : u64 = 0;
-    let mut bn : u64 = 0;
-    let mut bb : u64 = 0;
-    let mut br : u64 = 0;
-    let mut bq : u64 = 0;
-    let mut bk : u64 = 0;
-
-    array_to_bitboard(BASICSTART_CHESS_BOARD, &mut wp, &mut wn, &mut wb, &mut wr, &mut wq, &mut wk, &mut bp, &mut bn, &mut bb, &mut br, &mut bq, &mut bk);
-    
-    Game {
-        wp, wn, wb, wr, wq, wk,
-        bp, bn, bb, br, bq, bk,
-        white_to_play : true,
-        wking_never_move : true, wqueen_rook_never_move : true, wking_rook_never_move : true,
-        bking_never_move : true, bqueen_rook_never_move : true, bking_rook_never_move : true,
-        nb_coups : 0,
-    }
-}
-pub fn _draw_bitboard(bitboard : u64) {
-    let mut i = 0;
-    for _k in 0..8 {
-        println!();
-        for _p in 0..8 {
-            print!("{}", bitboard>>i & 1);
-            i+=1;
+pub fn bitboard_to_array(game : &Game) -> [[char;8]; 8] {
+    let mut chessboard = [[' ';8];8];
+    for (row, line) in chessboard.iter_mut().enumerate() {
+        for (file, c) in line.iter_mut().enumerate() {
+            *c = piece_char(game, Square::from_coords(file as u8, 7 - row as u8));
         }
     }
-    println!();
-}
-pub fn _count_bit(mut bit : u64) -> i8 {
-    let mut count = 0;
-    while bit != 0 {
-        bit &= bit-1;
-        count+=1;
-    }
-    count
+    chessboard
 }
-pub fn _draw_board(game : &Game) {
-    let mut chess_board:[[char;8];8] = [[' ';8];8];
-    let mut i = 0;
-    for x in &mut chess_board {
-        for c in x {
-            if ((game.wp >> i) & 1) == 1  { *c = 'P'; }
-            if ((game.wn >> i) & 1) == 1  { *c = 'N'; }
-            if ((game.wb >> i) & 1) == 1  { *c = 'B'; }
-            if ((game.wr >> i) & 1) == 1  { *c = 'R'; }
-            if ((game.wq >> i) & 1) == 1  { *c = 'Q'; }
-            if ((game.wk >> i) & 1) == 1  { *c = 'K'; }
-            if ((game.bp >> i) & 1) == 1  { *c = 'p'; }
-            if ((game.bn >> i) & 1) == 1  { *c = 'n'; }
-            if ((game.bb >> i) & 1) == 1  { *c = 'b'; }
-            if ((game.br >> i) & 1) == 1  { *c = 'r'; }
-            if ((game.bq >> i) & 1) == 1  { *c = 'q'; }
-            if ((game.bk >> i) & 1) == 1  { *c = 'k'; }
-            i+=1;
-        }
-    }
-    let letter = 'a';
-    print!("     ");
-    for i in 0..8 {
-        print!("  {} ", (letter as u8+i) as char);
-    }
-    println!();
-    chess_board.reverse();
-    for (i, x) in chess_board.iter().enumerate() {
-        println!("     ---------------------------------");
-        print!("   {} ", 7-i+1);
-        for c in x {
-            print!("| {c} ");
-        }
-        println!("|");
-    }
-    println!("     ---------------------------------");
+pub fn get_game_from_basicpos() -> Game {
+    let mut game = Game::empty();
+    array_to_bitboard(BASICSTART_CHESS_BOARD, &mut game);
+    game.set_castling_rights((true, true, true, true));
+    game
 }
-pub fn convert_string_to_bitboard(binary:usize) -> u64 {
-    //u64::pow(2, (binary) as u32)
-    1<<binary
+pub fn _draw_bitboard(bitboard : u64) {
+    print!("{}", Bitboard(bitboard));
 }
-pub fn possibility_wp(wpawn : u64, empty : u64, black : u64) -> u64 {
-    let pmoves1 = (wpawn & !FILE_MASKS[0])<<7 & black;// & !RANK_MASK[7] ;
-    let pmoves2 = (wpawn & !FILE_MASKS[7])<<9 & black;// & !RANK_MASK[7] ;
-    let pmoves3 = wpawn<<8 & empty;// & !RANK_MASK[7];
-    let pmoves4 = wpawn<<16 & empty & (empty<<8) & RANK_MASK[3];
-    pmoves1 | pmoves2 | pmoves3 | pmoves4
+pub fn _count_bit(bit : u64) -> i8 {
+    Bitboard(bit).popcount() as i8
 }
-pub fn possibility_bp2( bpawn: u64, empty : u64, white : u64) -> u64 {
-
-    let pmoves1 = (bpawn & !FILE_MASKS[7])>>7 & white;// & !RANK_MASK[0] ;
-    let pmoves2 = (bpawn & !FILE_MASKS[0])>>9 & white;// & !RANK_MASK[0] ;
-    let pmoves3 = bpawn>>8 & empty;// & !RANK_MASK[0];
-    let pmoves4 = bpawn>>16 & empty & (empty>>8) & RANK_MASK[4];
-    pmoves1 | pmoves2 | pmoves3 | pmoves4
+pub fn piece_char(game : &Game, square : Square) -> char {
+    game.piece_at(square).map_or(' ', ColoredPiece::to_char)
 }
-pub fn attack_wp(wpawn : u64, black : u64) -> u64 {
-    let pmoves1 = wpawn<<7 & black;// & !FILE_MASKS[0];
-    let pmoves2 = wpawn<<9 & black;// & !FILE_MASKS[7];
-    pmoves1 | pmoves2
-}
-pub fn attack_bp(wpawn : u64, white : u64) -> u64 {
-    let pmoves1 = wpawn>>7 & white;// & !FILE_MASKS[0];
-    let pmoves2 = wpawn>>9 & white;// & !FILE_MASKS[7];
-    pmoves1 | pmoves2
+pub fn _draw_board(game : &Game) {
+    print!("{game}");
+}
+//Pushes and captures of the pawns of `color`
+pub fn possibility_p(color : Color, pawns : u64, empty : u64, enemy : u64) -> u64 {
+    let pawns = Bitboard(pawns);
+    let empty = Bitboard(empty);
+    let moves = match color {
+        Color::WHITE => {
+            let push = pawns.north() & empty;
+            push | (push.north() & empty & RANK_MASK[3])
+        },
+        Color::BLACK => {
+            let push = pawns.south() & empty;
+            push | (push.south() & empty & RANK_MASK[4])
+        },
+    };
+    moves.0 | attack_p(color, pawns.0, enemy)
+}
+//Squares of `targets` attacked by the pawns of `color`
+pub fn attack_p(color : Color, pawns : u64, targets : u64) -> u64 {
+    let pawns = Bitboard(pawns);
+    let attack = match color {
+        Color::WHITE => pawns.north_west() | pawns.north_east(),
+        Color::BLACK => pawns.south_east() | pawns.south_west(),
+    };
+    (attack & targets).0
 }
 pub fn possibility_n(knight : u64) -> u64 {
     let nonoea:u64 =  (knight << 17) & !FILE_MASKS[0];
@@ -290,11 +469,10 @@ pub fn possibility_k(mut wk : u64) -> u64 {
     attack
 }*/
 pub fn possibility_k(wk : u64) -> u64 {
-    let mut attack = (wk & !FILE_MASKS[7])<<1 | (wk & !FILE_MASKS[0])>>1;
-    attack |= (wk & !FILE_MASKS[7])<<9 | (wk & !FILE_MASKS[7])>>7;
-    attack |= (wk & !FILE_MASKS[0])>>9 | (wk & !FILE_MASKS[0])<<7;
-    attack |= wk<<8 | wk>>8;
-    attack
+    let king = Bitboard(wk);
+    let attack = king.east() | king.west() | king.north() | king.south()
+        | king.north_east() | king.north_west() | king.south_east() | king.south_west();
+    attack.0
 }
 pub fn hyperbola_quintessence(occupied : u64, mask: u64, mut number : u64) -> u64 {
     number = 1<<number;
@@ -314,767 +492,357 @@ pub fn rank_attacks(occupied: u64, sq: u64) -> u64 {
     let o = (occupied >> (r + 1)) & 63;
     FIRST_RANK_ATTACKS[o as usize][f as usize] << r
 }
-pub fn convert_move_to_bitboard(moves : &str) -> (u64, u64, Piece) {
-    let mut length = 4;
-
-    if moves.len() == 5 {
-        length = 5;
+pub fn diag_antid_moves(square : Square, occupied : u64) -> u64 {
+    let (file, rank) = (square.file() as usize, square.rank() as usize);
+    let index = square.index() as u64;
+    hyperbola_quintessence(occupied, DIAG_MASKS[rank + file], index) | hyperbola_quintessence(occupied, ANTIDIAG_MASKS[rank + 7 - file], index)
+}
+pub fn hv_moves(square : Square, occupied : u64) -> u64 {
+    let b = hyperbola_quintessence(occupied, FILE_MASKS[square.file() as usize], square.index() as u64);
+    rank_attacks(occupied, square.index() as u64) | b
+}
+//Squares reached by a piece of type `piece` standing on `square`, own pieces included
+pub fn piece_attacks(piece : Piece, square : Square, occupied : u64) -> u64 {
+    match piece {
+        Piece::KNIGHT => KNIGHT_MOVE[square.index()],
+        Piece::BISHOP => diag_antid_moves(square, occupied),
+        Piece::ROOK => hv_moves(square, occupied),
+        Piece::QUEEN => hv_moves(square, occupied) | diag_antid_moves(square, occupied),
+        Piece::KING => KING_MOVE[square.index()],
+        _ => 0,
+    }
+}
+//Squares of a rank from `a` to `b`, both included
+fn rank_span(a : Square, b : Square) -> u64 {
+    let (low, high) = (a.index().min(b.index()), a.index().max(b.index()));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+//Castle with the rook on `rook_from`, the king lands on the g or c file and the rook next to it like in Chess960.
+//Return -1 if the rights, the empty squares or the attacked squares forbid it
+fn castle(color : Color, rook_from : Square, game : &mut Game) -> i8 {
+    let king_from = game.king_square(color);
+    let king_side = rook_from.file() > king_from.file();
+    if game.castling_rook(color, king_side) != Some(rook_from) {
+        return -1;
+    }
+    let rank = king_from.rank();
+    let king_to = Square::from_coords(if king_side { 6 } else { 2 }, rank);
+    let rook_to = Square::from_coords(if king_side { 5 } else { 3 }, rank);
+    let path = rank_span(king_from, king_to) | rank_span(rook_from, rook_to);
+    if game.occupied() & path & !(king_from.bit() | rook_from.bit()) != 0
+        || possibility(!color, game) & rank_span(king_from, king_to) != 0 {
+        return -1;
+    }
+    game.castling_rooks[color.index()] = [None;2];
+    game.remove_piece(king_from);
+    game.remove_piece(rook_from);
+    game.put_piece(king_to, ColoredPiece::new(color, Piece::KING));
+    game.put_piece(rook_to, ColoredPiece::new(color, Piece::ROOK));
+    game.en_passant = 0;
+    game.halfmove = game.halfmove.saturating_add(1);
+    0
+}
+pub fn compute_move(color : Color, chessmove : Move, game : &mut Game) -> i8 {
+    let own = game.side(color);
+    let enemy = game.side(!color);
+    let occupied = own | enemy;
+    let square_a = chessmove.from;
+    let square_b = chessmove.to;
+    let a = square_a.bit();
+    let b = square_b.bit();
+    let piece = match game.piece_at(square_a) {
+        Some(moving) if moving.color == color => moving.piece,
+        _ => return -1,
+    };
+    //Castling is encoded as the king taking its own rook
+    if piece == Piece::KING && game.piece_at(square_b) == Some(ColoredPiece::new(color, Piece::ROOK)) {
+        return castle(color, square_b, game);
     }
-    let mut iter1 = moves[0..length].chars();
-    let un = iter1.next().unwrap() as u64-96;
-    let deux = iter1.next().unwrap() as u64-48;
-    let trois = iter1.next().unwrap() as u64-96;
-    let quatre = iter1.next().unwrap() as u64-48;
-    let a = (deux-1) *8 +  un-1 ;
-    let b = (quatre-1) *8 +  trois-1;
-    let mut promotion_piece = Piece::NONE;
-    if moves.len() == 5 {
-        let promote = iter1.next().unwrap();
-        promotion_piece = match promote {
-            'q' => Piece::QUEEN ,
-            'r' => Piece::ROOK,
-            'b' => Piece::BISHOP,
-            'n' => Piece::KNIGHT,
-             _  => Piece::NONE
-        }
+    let moves = if piece == Piece::PAWN {
+        possibility_p(color, a, !occupied, enemy | game.en_passant)
     }
-    (a,b, promotion_piece)
-}
-pub fn diag_antid_moves(square : u64, occupied : u64) -> u64 {
-    hyperbola_quintessence(occupied, DIAG_MASKS[((square/8) + (square%8)) as usize], square) | hyperbola_quintessence(occupied, ANTIDIAG_MASKS[((square/8)+7 - (square%8)) as usize], square)
-}
-pub fn hv_moves(square : u64, occupied : u64) -> u64 {
-    let b = hyperbola_quintessence(occupied, FILE_MASKS[(square % 8) as usize], square);
-    rank_attacks(occupied, square) | b
-}
-pub fn compute_move_w(chessmove : (u64, u64, Piece), game : &mut Game) -> i8 {
-    let black = game.bp | game.bn | game.bb | game.br | game.bq | game.bk;
-    let white = game.wp | game.wn | game.wb | game.wr | game.wq | game.wk;
-    let occupied = black | white;
-    let mut a = chessmove.0;
-    let mut b = chessmove.1;
-    let square_a = a;
-    let square_b = b;
-    a = 1u64<<a;
-    b = 1u64<<b;
-    let mut moves= 0;
-    let mut from: &mut u64 = &mut 0;
-    if (game.wp & a) != 0 {
-        moves = possibility_wp(a, !occupied, black);
-        if moves & b != 0 && b & RANK_MASK[7] != 0 {
-            game.wp &= !a;
-            match chessmove.2 {
-                Piece::QUEEN  => game.wq |= b,
-                Piece::ROOK   => game.wr |= b,
-                Piece::BISHOP => game.wb |= b,
-                Piece::KNIGHT => game.wn |= b,
-                _ => { game.wp |= b; }
-            }
-            if black & b != 0 {
-                if game.bp & b != 0 {  game.bp &= !b; return 1;}
-                else if game.bn & b != 0 { game.bn &= !b; return 3;}
-                else if game.bb & b != 0 { game.bb &= !b; return 3;}
-                else if game.br & b != 0 { game.br &= !b; return 5;}
-                else if game.bq & b != 0 { game.bq &= !b; return 11;}
+    else {
+        piece_attacks(piece, square_a, occupied) & !own
+    };
+    if moves & b == 0 {
+        return -1;
+    }
+    let mut capture = game.remove_captured(!color, square_b);
+    game.move_piece(square_a, square_b);
+    game.update_castling_rights(a | b);
+    if piece == Piece::KING {
+        game.castling_rooks[color.index()] = [None;2];
+    }
+    game.halfmove = game.halfmove.saturating_add(1);
+    if piece == Piece::PAWN {
+        game.halfmove = 0;
+        if b == game.en_passant {
+            if let Some(captured) = square_b.offset(if color == Color::WHITE { -8 } else { 8 }) {
+                game.remove_piece(captured);
             }
-            return 1;
+            capture = 1;
         }
-        from = &mut game.wp;
-    }
-    else if game.wn & a != 0 {
-        //moves = possibility_n(game.wn & a) & !white;
-        moves = KNIGHT_MOVE[square_a as usize] & !white;
-        from = &mut game.wn;
-    }
-    else if game.wb & a != 0 {
-        let occupied = black | white;
-        moves = diag_antid_moves(square_a, occupied) & !white;
-        from = &mut game.wb;
-    }
-    else if game.wr & a != 0 {
-        let occupied = black | white;
-        moves = hv_moves(square_a, occupied) & !white;
-        from = &mut game.wr;
-        if moves & b != 0 {
-            game.wking_rook_never_move = false;
+        if b & (RANK_MASK[0] | RANK_MASK[7]) != 0 {
+            let promotion = if chessmove.promotion == Piece::NONE { Piece::QUEEN } else { chessmove.promotion };
+            game.remove_piece(square_b);
+            game.put_piece(square_b, ColoredPiece::new(color, promotion));
         }
     }
-    else if game.wq & a != 0 {
-        let occupied = black | white;
-        moves = (hv_moves(square_a, occupied) | diag_antid_moves(square_a, occupied)) & !white;
-        
-        from = &mut game.wq;
-    }
-    else if game.wk & a != 0 {
-        //println!("{square_b} {} {} {}", game.wking_never_move, game.wking_rook_never_move, game.wqueen_rook_never_move);
-        if square_b == 2 && square_a == 4 { // Grand roque
-            //check if the king and the rook has never move
-            if game.wking_never_move && game.wking_rook_never_move && (black | white) & (2u64.pow(1) + 2u64.pow(2)) == 0 && possibility_b(game) & (2u64.pow(1) + 2u64.pow(2)) == 0 {
-                game.wking_never_move = false;
-                game.wking_rook_never_move = false;
-                //Do grand roque
-                game.wk &= !a;
-                game.wk |= b;
-                game.wr &= !(2u64.pow(0));
-                game.wr |= 2u64.pow(3);
-                return 0;
-            }
-            return -1;
-            //check if no piece is between
-            //check if square between isn't attacked
-        }
-        else if square_b == 6  && square_a == 4 { //Petit Roque
-            if game.wking_never_move && game.wqueen_rook_never_move && (black | white) & (2u64.pow(6) + 2u64.pow(5)) == 0 && possibility_b(game) & (2u64.pow(6) + 2u64.pow(5)) == 0 {
-                game.wking_never_move = false;
-                game.wqueen_rook_never_move = false;
-                game.wk &= !a;
-                game.wk |= b;
-                game.wr &= !(2u64.pow(7));
-                game.wr |= 2u64.pow(5);
-                return 0;
-            }
-            return -1;
-        }
-        moves = possibility_k(game.wk) & !white;
-        from = &mut game.wk;
-        if moves & b != 0 {
-            game.wking_never_move = false;
-        }
+    if capture > 0 {
+        game.halfmove = 0;
     }
-    if moves & b != 0 {
-        (*from) &= !a;
-        (*from) |= b;
-        if black & b != 0 {
-            if game.bp & b != 0 {  game.bp &= !b; return 1;}
-            else if game.bn & b != 0 { game.bn &= !b; return 3;}
-            else if game.bb & b != 0 { game.bb &= !b; return 3;}
-            else if game.br & b != 0 { game.br &= !b; return 5;}
-            else if game.bq & b != 0 { game.bq &= !b; return 11;}
-        }
-        0
+    game.en_passant = if piece == Piece::PAWN && square_a.index().abs_diff(square_b.index()) == 16 {
+        Square::new((square_a.index() + square_b.index()) as u8 / 2).bit()
     }
     else {
-        -1
-    }
-}
-
-pub fn compute_move_b(chessmove : (u64,u64,Piece), game :&mut Game) -> i8 {
-    let black = game.bp | game.bn | game.bb | game.br | game.bq | game.bk;
-    let white = game.wp | game.wn | game.wb | game.wr | game.wq | game.wk;
-    let mut a = chessmove.0;
-    let mut b = chessmove.1;
-    let square_a = a;
-    let square_b = b;
-    a = 1<<a;
-    b = 1<<b;
-    let mut moves = 0;
-    let mut from = &mut (0);
-    if (game.bp & a) != 0 {
-        moves = possibility_bp2(a, !(black | white), white);
-        if moves & b != 0 && b & RANK_MASK[0] != 0 {
-            game.bp &= !(1u64<<square_a);
-            match chessmove.2 {
-                Piece::QUEEN  => game.bq |= 1u64<<square_b,
-                Piece::ROOK   => game.br |= 1u64<<square_b,
-                Piece::BISHOP => game.bb |= 1u64<<square_b,
-                Piece::KNIGHT => game.bn |= 1u64<<square_b,
-                _ => { game.bp |= 1u64<<square_b; }
-            }
-            if white & b != 0 {
-                if game.wp & b != 0 { game.wp &= !b; return 1;}
-                else if game.wn & b != 0 { game.wn &= !b; return 3;}
-                else if game.wb & b != 0 { game.wb &= !b; return 3;}
-                else if game.wr & b != 0 { game.wr &= !b; return 5;}
-                else if game.wq & b != 0 { game.wq &= !b; return 11;}
-            }
-            return 1;
-        }
-        from = &mut game.bp;
-    }
-    else if game.bn & a != 0 {
-        //moves = possibility_n( a) & !black;
-        moves = KNIGHT_MOVE[square_a as usize] & !black;
-        from = &mut game.bn;
-    }
-    else if game.bb & a != 0 {
-        let occupied = black | white;
-        moves = diag_antid_moves(square_a, occupied) & !black;
-        from = &mut game.bb;
-    }
-    else if game.br & a != 0 {
-        let occupied = black | white;
-        moves = hv_moves(square_a, occupied) & !black;
-        from = &mut game.br;
-    }
-    else if game.bq & a != 0 {
-        let occupied = black | white;
-        moves = (hv_moves(square_a, occupied) | diag_antid_moves(square_a, occupied)) & !black;
-        from = &mut game.bq;
-    }
-    else if game.bk & a != 0 {
-        //println!("{square_b} {} {} {}", game.bking_never_move, (black | white) & (2u64.pow(61) + 2u64.pow(62)) == 0, possibility_w(game) & (2u64.pow(61) + 2u64.pow(62)) == 0);
-        
-        if square_a == 60 && square_b == 58 && game.bking_never_move && game.bking_rook_never_move && (black | white) & (2u64.pow(58) + 2u64.pow(57)) == 0 && possibility_w(game) & (2u64.pow(58) + 2u64.pow(57)) == 0 {
-                //println!("Grand roque");
-                game.bking_never_move = false;
-                game.bking_rook_never_move = false;
-                //Do grand roque
-                game.bk &= !a;
-                game.bk |= b;
-                game.br &= !(2u64.pow(56));
-                game.br |= 2u64.pow(59);
-                return 0;
-        }
-            //check if no piece is between
-            //check if square between isn't attacked
-        
-        else if square_a == 60 && square_b == 62  && game.bking_never_move && game.bqueen_rook_never_move && (black | white) & (2u64.pow(61) + 2u64.pow(62)) == 0 && possibility_w(game) & (2u64.pow(61) + 2u64.pow(62)) == 0 {
-                game.bking_never_move = false;
-                game.bqueen_rook_never_move = false;
-                game.bk &= !a;
-                game.bk |= b;
-                game.br &= !(2u64.pow(63));
-                game.br |= 2u64.pow(61);
-                return 0;
-            
-        }
-        moves = possibility_k(game.bk) & !black;
-        from = &mut game.bk;
-        if moves & b != 0 {
-            game.bking_never_move = false;
-        }
-    }
-    if moves & b != 0 {
-        (*from) &= !a;
-        (*from) |=  b;
-        if white & b != 0 {
-            if game.wp & b != 0 { game.wp &= !b; return 1;}
-            else if game.wn & b != 0 { game.wn &= !b; return 3;}
-            else if game.wb & b != 0 { game.wb &= !b; return 3;}
-            else if game.wr & b != 0 { game.wr &= !b; return 5;}
-            else if game.wq & b != 0 { game.wq &= !b; return 11;}
-        }
         0
-    }
-    else {
-        -1
-    }
+    };
+    capture
 }
-pub fn possibility_w( game : &Game) -> u64 {
-    let black = game.black();
-    let white = game.white();
-    let occupied = black | white;
-    let mut attack = 0;
-    attack |= attack_wp(game.wp, black);
-    
-    if game.wn != 0 {
-        attack |= possibility_n(game.wn) & !white;
-        //attack |= KNIGHT_MOVE(game.wn.) & !white;
-    }
-    let mut copy_wb = game.wb;
-    while copy_wb != 0 {
-        attack |= diag_antid_moves(copy_wb.tzcnt() as u64, occupied) & !white;
-        copy_wb &= copy_wb-1;
-    }
-    let mut copy_wr = game.wr;
-    while copy_wr != 0 {
-        attack |= hv_moves(copy_wr.tzcnt() as u64, occupied) & !white;
-        copy_wr &= copy_wr-1;
-    }
-    let mut copy_wq = game.wq;
-    while copy_wq != 0 {
-        attack |= (hv_moves(copy_wq.tzcnt(), occupied) | diag_antid_moves(copy_wq.tzcnt(), occupied)) & !white;
-        copy_wq &= copy_wq-1;
+//Squares attacked by `color`, its own pieces excluded
+pub fn possibility(color : Color, game : &Game) -> u64 {
+    let own = game.side(color);
+    let occupied = game.occupied();
+    let mut attack = attack_p(color, game.pieces(color, Piece::PAWN), !own);
+    attack |= possibility_n(game.pieces(color, Piece::KNIGHT));
+    for piece in [Piece::BISHOP, Piece::ROOK, Piece::QUEEN] {
+        for square in Bitboard(game.pieces(color, piece)) {
+            attack |= piece_attacks(piece, square, occupied);
+        }
     }
-    attack |= possibility_k(game.wk) & !white;
-    //attack |= KING_MOVE[game.wk.tzcnt() as usize];
-    
-    attack
+    attack |= possibility_k(game.pieces(color, Piece::KING));
+    attack & !own
 }
-pub fn possibility_b( game : &Game) -> u64 {
-    let black = game.bp | game.bn | game.bb | game.br | game.bq | game.bk;
-    let white = game.wp | game.wn | game.wb | game.wr | game.wq | game.wk;
-    let occupied = black | white;
-    let mut attack = 0;
 
-    attack |= attack_bp(game.bp, white);
+//Is the king of `color` in check
+pub fn is_attacked(color : Color, game : &Game) -> bool {
+    let Some(king) = Bitboard(game.pieces(color, Piece::KING)).lsb() else {
+        return false;
+    };
+    //Look from the king for each kind of attacker instead of building every attack of the enemy
+    let enemy = !color;
+    let occupied = game.occupied();
+    let queens = game.pieces(enemy, Piece::QUEEN);
+    attack_p(color, king.bit(), game.pieces(enemy, Piece::PAWN)) != 0
+        || possibility_n(king.bit()) & game.pieces(enemy, Piece::KNIGHT) != 0
+        || possibility_k(king.bit()) & game.pieces(enemy, Piece::KING) != 0
+        || diag_antid_moves(king, occupied) & (game.pieces(enemy, Piece::BISHOP) | queens) != 0
+        || hv_moves(king, occupied) & (game.pieces(enemy, Piece::ROOK) | queens) != 0
+}
 
-    if game.bn != 0 {
-        attack |= possibility_n(game.bn) & !black;
-    }
-    let mut copy_bb = game.bb;
-    while copy_bb != 0 {
-        attack |= diag_antid_moves(copy_bb.tzcnt() , occupied) & !black;
-        copy_bb &= copy_bb-1;
-    }
-    let mut copy_br = game.br;
-    while copy_br != 0 {
-        attack |= hv_moves(copy_br.tzcnt(), occupied) & !black;
-        copy_br &= copy_br-1;
-    }
-    let mut copy_bq = game.bq;
-    while copy_bq != 0 {
-        attack |= (hv_moves(copy_bq.tzcnt(), occupied) | diag_antid_moves(copy_bq.tzcnt(), occupied) ) & !black;
-        copy_bq &= copy_bq-1;
-    }
-    attack |= possibility_k(game.bk) & !black;
-    attack
+//Moves wanted from the generator, promotions count as captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    ALL,
+    CAPTURES,
+    QUIETS,
 }
 
-pub fn is_attacked(target_is_wking : bool, game : &Game) -> bool {
-    if target_is_wking {
-        possibility_b(game) & game.wk != 0
-    }
-    else {
-        possibility_w(game) & game.bk != 0
-    }
+pub fn get_legal_move(color : Color, game : &Game) -> VecDeque<(Move, Piece)> {
+    get_moves(color, game, MoveKind::ALL)
 }
 
-pub fn get_legal_move(side_w : bool, game : &Game) -> VecDeque<(u64, Piece)> {
-    //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-    let black = game.bp | game.bn | game.bb | game.br | game.bq | game.bk;
-    let white = game.wp | game.wn | game.wb | game.wr | game.wq | game.wk;
-    let occupied = black | white;
-    let mut legal_moves = VecDeque::<(u64, Piece)>::new();
-    
-    if side_w { //White Possibility
-        //Pions Possibility
-        let mut wp_test = game.wp;
-        while  wp_test != 0 {
-            let piece = wp_test.tzcnt();
-            let wp_extract = 1u64 << piece;
-            wp_test = wp_test & (wp_test-1);
-            let mut possi_wp = possibility_wp(wp_extract, !(occupied), black);
-            while possi_wp != 0 {
-                let mut promote = 0;
-                let mut promote_piece = Piece::NONE;
-                let b = possi_wp.tzcnt();
-                if b & RANK_MASK[0] != 0 {
-                    promote = 1;
-                    promote_piece = Piece::QUEEN;
-                }
-                let mut game1 = *game;
-                let b = possi_wp.tzcnt();
-                let capture = compute_move_w((piece, b, promote_piece), &mut game1);
-                let is_check = is_attacked(true, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece<<9) + (b<<1) + promote, Piece::PAWN));
-                    }
-                    else {
-                        legal_moves.push_back(((piece<<9) + (b<<1), Piece::PAWN));
-                    }
-                }
-                possi_wp = possi_wp & (possi_wp - 1);
+pub fn get_moves(color : Color, game : &Game, kind : MoveKind) -> VecDeque<(Move, Piece)> {
+    let own = game.side(color);
+    let enemy = game.side(!color);
+    let occupied = own | enemy;
+    let mut legal_moves = VecDeque::<(Move, Piece)>::new();
+    //Play the move on a copy, keep it if the king is safe, captures first
+    let mut try_move = |chessmove : Move, piece : Piece, promotions : &[Piece]| {
+        let mut game1 = *game;
+        let capture = compute_move(color, chessmove, &mut game1);
+        if capture < 0 || is_attacked(color, &game1) {
+            return;
+        }
+        for &promote_piece in promotions {
+            let chessmove = Move::new(chessmove.from, chessmove.to, promote_piece);
+            if capture > 0 || promote_piece != Piece::NONE {
+                legal_moves.push_front((chessmove, piece));
             }
-        }
-        //Knight
-        //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-        let mut wn_test = game.wn;
-        while wn_test != 0 {
-            let piece = wn_test.tzcnt();
-            let wn_extract = 1u64 << piece;
-            wn_test = wn_test & (wn_test - 1);
-            let mut wn_possi = possibility_n( wn_extract) & !white;
-            while wn_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = wn_possi.tzcnt();
-                let capture = compute_move_w((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(true, &game1);
-                
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece<<9) + (b<<1), Piece::KNIGHT));
-                    }
-                    else {
-                        legal_moves.push_back(((piece<<9) + (b<<1), Piece::KNIGHT));
-                    }
-                }
-                wn_possi = wn_possi & (wn_possi - 1);
+            else {
+                legal_moves.push_back((chessmove, piece));
             }
         }
-        
-        //Bishop
-        let mut wb_test = game.wb;
-        while wb_test != 0 {
-            let piece = wb_test.tzcnt();
-            wb_test = wb_test & (wb_test - 1);
-            let mut wb_possi = diag_antid_moves(piece, occupied) & !white;
-            while wb_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = wb_possi.tzcnt();
-                let capture = compute_move_w((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(true, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece<<9) + (b<<1), Piece::BISHOP));
-                    }
-                    else {
-                        legal_moves.push_back(((piece<<9) + (b<<1), Piece::BISHOP));
-                    }
-                }
-                wb_possi = wb_possi & (wb_possi - 1);
-            }
-        }
-        //Rook
-        let mut wr_test = game.wr;
-        while wr_test != 0 {
-            let piece = wr_test.tzcnt();
-            wr_test = wr_test & (wr_test - 1);
-            let mut wr_possi = hv_moves(piece, occupied) & !white;
-            while wr_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = wr_possi.tzcnt();
-                let capture = compute_move_w((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(true, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece<<9) + (b<<1), Piece::ROOK));
-                    }
-                    else {
-                        legal_moves.push_back(((piece<<9) + (b<<1), Piece::ROOK));
-                    }
-                }
-                wr_possi = wr_possi & (wr_possi - 1);
-            }
-        }
-
-        //Queen
-        if game.wq != 0 {
-            let piece = game.wq.tzcnt();
-            let mut wq_possi = (hv_moves(piece, occupied) | diag_antid_moves(piece, occupied)) & !white;
-            while wq_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = wq_possi.tzcnt();
-                let capture = compute_move_w((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(true, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece<<9) + (b<<1), Piece::QUEEN));
-                    }
-                    else {
-                        legal_moves.push_back(((piece<<9) + (b<<1), Piece::QUEEN));
-                    }
-                }
-                wq_possi = wq_possi & (wq_possi - 1);
-            }
-        }
-        //King
-        
-        let mut possi_wk = possibility_k(game.wk) & !white;
-        while possi_wk != 0 {
-            let mut game1 = *game;
-            let b = possi_wk.tzcnt();
-            let capture = compute_move_w((game.wk.tzcnt(), b, Piece::NONE), &mut game1);
-            let is_check = is_attacked(true, &game1);
-            _draw_board(&game1);
-            println!("CHECK : {}", is_check);
-            if !is_check {
-                if capture > 0 {
-                    legal_moves.push_front(((game.wk.tzcnt() <<9) + (b<<1), Piece::KING));
-                }
-                else {
-                    legal_moves.push_back(((game.wk.tzcnt() <<9) + (b<<1), Piece::KING));
-                }
+    };
+    //Pions Possibility
+    for square in Bitboard(game.pieces(color, Piece::PAWN)) {
+        for b in Bitboard(possibility_p(color, square.bit(), !occupied, enemy | game.en_passant)) {
+            let tactical = b.bit() & (enemy | game.en_passant | RANK_MASK[0] | RANK_MASK[7]) != 0;
+            if (kind == MoveKind::CAPTURES && !tactical) || (kind == MoveKind::QUIETS && tactical) {
+                continue;
             }
-            possi_wk = possi_wk & (possi_wk - 1);
+            let promotions : &[Piece] = if b.bit() & (RANK_MASK[0] | RANK_MASK[7]) != 0 { &PROMOTIONS } else { &[Piece::NONE] };
+            try_move(Move::new(square, b, promotions[0]), Piece::PAWN, promotions);
         }
     }
-    else { //Black Possiblity
-        //Pions Possibility
-        let mut bp_test = game.bp;
-        while  bp_test != 0 {
-            let piece = bp_test.tzcnt();
-            let bp_extract = 1u64 << piece;
-            
-            bp_test = bp_test & (bp_test-1);
-            let mut possi_bp = possibility_bp2(bp_extract, !(occupied), white);
-            while possi_bp != 0 {
-                let mut game1 = *game;
-                let mut promote = 0;
-                let mut promote_piece = Piece::NONE;
-                let b = possi_bp.tzcnt();
-                if b & RANK_MASK[0] != 0 {
-                    promote = 1;
-                    promote_piece = Piece::QUEEN;
-                }
-                let capture = compute_move_b((piece, b, promote_piece), &mut game1);
-                let is_check = is_attacked(false, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece <<9) + (b<<1)+promote, Piece::PAWN));
-                    }
-                    else {
-                        legal_moves.push_back(((piece <<9) + (b<<1), Piece::PAWN));
-                    }
-                }
-                possi_bp = possi_bp & (possi_bp - 1);
-            }
-        }
-        //Knight
-        //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-        let mut bn_test = game.bn;
-        while bn_test != 0 {
-            let piece = bn_test.tzcnt() ;
-            let bn_extract = 1u64 << piece;
-            bn_test = bn_test & (bn_test-1);
-            let mut bn_possi = possibility_n(bn_extract) & !black;
-            while bn_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = bn_possi.tzcnt() ;
-                let capture = compute_move_b((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(false, &game1);
-                if !is_check {
-                    if capture > 0 {
-                    legal_moves.push_front(((piece <<9) + (b<<1), Piece::KNIGHT));
-                    }
-                    else {
-                        legal_moves.push_back(((piece <<9) + (b<<1), Piece::KNIGHT));
-                    }
-                }
-                bn_possi = bn_possi & (bn_possi - 1);
-            }
-        }
-        
-        //Bishop
-        let mut bb_test = game.bb;
-        while bb_test != 0 {
-            let piece = bb_test.tzcnt();
-            bb_test = bb_test & (bb_test - 1);
-            let mut bb_possi = diag_antid_moves(piece, occupied) & !black;
-            while bb_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = bb_possi.tzcnt();
-                let capture = compute_move_b((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(false, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece <<9) + (b<<1), Piece::BISHOP));
-                    }
-                    else {
-                        legal_moves.push_back(((piece <<9) + (b<<1), Piece::BISHOP));
-                    }
-                }
-                bb_possi = bb_possi & (bb_possi - 1);
-            }
-        }
-        //Rook
-        let mut br_test = game.br;
-        while br_test != 0 {
-            let piece = br_test.tzcnt();
-            br_test = br_test & (br_test - 1);
-            let mut br_possi = hv_moves(piece, occupied) & !black;
-            while br_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = br_possi.tzcnt();
-                let capture = compute_move_b((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(false, &game1);
-                if !is_check {
-                    if capture > 0 {
-                    legal_moves.push_front(((piece <<9) + (b<<1), Piece::ROOK));
-                    }
-                    else {
-                        legal_moves.push_back(((piece <<9) + (b<<1), Piece::ROOK));
-                    }
-                }
-                br_possi = br_possi & (br_possi - 1);
-            }
-        }
-
-        //Queen
-        if game.bq != 0 {
-            let piece = game.bq.tzcnt();
-            let mut bq_possi = (hv_moves(piece, occupied) | diag_antid_moves(piece, occupied)) & !black;
-            while bq_possi != 0 {
-                //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-                let mut game1 = *game;
-                let b = bq_possi.tzcnt();
-                let capture = compute_move_b((piece, b, Piece::NONE), &mut game1);
-                let is_check = is_attacked(false, &game1);
-                if !is_check {
-                    if capture > 0 {
-                        legal_moves.push_front(((piece <<9) + (b<<1), Piece::QUEEN));
-                    }
-                    else {
-                        legal_moves.push_back(((piece <<9) + (b<<1), Piece::QUEEN));
-                    }
-                }
-                bq_possi = bq_possi & (bq_possi - 1);
+    //Knight, Bishop, Rook, Queen, King
+    let targets = match kind {
+        MoveKind::ALL => !own,
+        MoveKind::CAPTURES => enemy,
+        MoveKind::QUIETS => !occupied,
+    };
+    for piece in [Piece::KNIGHT, Piece::BISHOP, Piece::ROOK, Piece::QUEEN, Piece::KING] {
+        for square in Bitboard(game.pieces(color, piece)) {
+            for b in Bitboard(piece_attacks(piece, square, occupied) & targets) {
+                try_move(Move::new(square, b, Piece::NONE), piece, &[Piece::NONE]);
             }
         }
-        
-        //King
-        let mut possi_bk = possibility_k(game.bk) & !black;
-        let piece = game.bk.tzcnt();
-        while possi_bk != 0 {
-            //let (mut wp, mut wn, mut wb, mut wr, mut wq, mut wk, mut bp, mut bn, mut bb, mut br, mut bq, mut bk) = copy_bitboard(wp1, wn1, wb1, wr1, wq1, wk1, bp1, bn1, bb1, br1, bq1, bk1);
-            let mut game1 = *game;
-            let b = possi_bk.tzcnt();
-            let capture = compute_move_b((piece, b, Piece::NONE), &mut game1);
-            let is_check = is_attacked(false, &game1);
-            if !is_check {
-                if capture > 0 {
-                    legal_moves.push_front(((piece <<9) + (b<<1), Piece::KING));
-                }
-                else {
-                    legal_moves.push_back(((piece <<9) + (b<<1), Piece::KING));
-                }
-            }
-            possi_bk = possi_bk & (possi_bk - 1);
+    }
+    //Castling
+    if kind == MoveKind::CAPTURES {
+        return legal_moves;
+    }
+    for king_side in [true, false] {
+        if let Some(rook) = game.castling_rook(color, king_side) {
+            try_move(Move::new(game.king_square(color), rook, Piece::NONE), Piece::KING, &[Piece::NONE]);
         }
     }
     legal_moves
 }
 
-pub fn print_custum_move(a_move : (u64,Piece)) {
-    let a = a_move.0>>9;
-    let b = (a_move.0 & 510)>>1;
-    if a_move.0 & 1 != 0 {
-        println!("{}{}q {:?}", convert_square_to_move(a), convert_square_to_move(b), a_move.1);
-    }
-    else {
-        println!("{}{} {:?}", convert_square_to_move(a), convert_square_to_move(b), a_move.1);
-    }
-}
-pub fn convert_custum_move(the_move : (u64, Piece)) -> (u64, u64, u64) {
-    (the_move.0>>9, (the_move.0 & 510)>>1, the_move.0 & 1)
+pub fn print_custum_move(a_move : (Move, Piece)) {
+    println!("{} {:?}", a_move.0, a_move.1);
 }
 pub fn draw_the_game_state(game : &Game) {
     println!("WPAWN");
-    _draw_bitboard(game.wp);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::PAWN));
     println!("WKNIGHT");
-    _draw_bitboard(game.wn);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::KNIGHT));
     println!("WBISHOP");
-    _draw_bitboard(game.wb);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::BISHOP));
     println!("WROOK");
-    _draw_bitboard(game.wr);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::ROOK));
     println!("WQUEEN");
-    _draw_bitboard(game.wq);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::QUEEN));
     println!("WKING");
-    _draw_bitboard(game.wk);
+    _draw_bitboard(game.pieces(Color::WHITE, Piece::KING));
     println!("BPAWN");
-    _draw_bitboard(game.bp);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::PAWN));
     println!("BKNIGHT");
-    _draw_bitboard(game.bn);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::KNIGHT));
     println!("BBISHOP");
-    _draw_bitboard(game.bb);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::BISHOP));
     println!("BROOK");
-    _draw_bitboard(game.br);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::ROOK));
     println!("BQUEEN");
-    _draw_bitboard(game.bq);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::QUEEN));
     println!("BKING");
-    _draw_bitboard(game.bk);
+    _draw_bitboard(game.pieces(Color::BLACK, Piece::KING));
+}
+pub fn legal_moves(game : &Game) -> Vec<(Move, Piece)> {
+    get_legal_move(game.side_to_move, game).into_iter().collect()
+}
+pub fn perft(game : &Game, depth : u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if depth == 1 {
+        return get_legal_move(game.side_to_move, game).len() as u64;
+    }
+    let mut nodes = 0;
+    for (chessmove, _) in legal_moves(game) {
+        let mut game1 = *game;
+        game1.play(chessmove);
+        nodes += perft(&game1, depth - 1);
+    }
+    nodes
 }
 fn main() {
-    let now = Instant::now();
-    println!("Instant init : {} nano seconde", now.elapsed().as_nanos());
-    env::set_var("RUST_BACKTRACE", "1");
-    let chess_board:[[char;8];8] = [
-        ['r','n','b','q','k','b','n','r'],
-        ['p','p','p','p','p','p','p','p'],
-        [' ',' ',' ',' ',' ',' ',' ',' '],
-        [' ',' ',' ',' ',' ',' ',' ',' '],
-        [' ',' ',' ',' ',' ',' ',' ',' '],
-        [' ',' ',' ',' ',' ',' ',' ',' '],
-        ['P','P','P','P','P','P','P','P'],
-        ['R','N','B','Q','K','B','N','R'],
-    ];
-    
-    let mut wp : u64 = 0;
-    let mut wn : u64 = 0;
-    let mut wb : u64 = 0;
-    let mut wr : u64 = 0;
-    let mut wq : u64 = 0;
-    let mut wk : u64 = 0;
-    let mut bp : u64 = 0;
-    let mut bn : u64 = 0;
-    let mut bb : u64 = 0;
-    let mut br : u64 = 0;
-    let mut bq : u64 = 0;
-    let mut bk : u64 = 0;
+    match cli::run(std::env::args().skip(1).collect()) {
+        Ok(true) => {},
+        Ok(false) => repl::run(),
+        Err(e) => {
+            eprintln!("error : {e}");
+            std::process::exit(1);
+        },
+    }
+}
 
-    array_to_bitboard(chess_board, &mut wp, &mut wn, &mut wb, &mut wr, &mut wq, &mut wk, &mut bp, &mut bn, &mut bb, &mut br, &mut bq, &mut bk);
-    
-    let mut game = Game {
-        wp, wn, wb, wr, wq, wk,
-        bp, bn, bb, br, bq, bk,
-        white_to_play : true,
-        nb_coups : 0,
-        wking_never_move : true, wqueen_rook_never_move : true, wking_rook_never_move : true,
-        bking_never_move : true, bqueen_rook_never_move : true, bking_rook_never_move : true,
-    };
-    
-    //let moves = ["e2e3", "e7e6", "f1d3", "d8g5"];
-    //let moves = ["b1c3","g8f6", "c3b1"];
-    //let moves = ["e2e4","e7e5", "f2f4", "d2d4", "d7d5", "f1e2", "d8d6" ];
-    //let moves = ["e2e4", "e7e5", "f1e2"];
-    //let moves = ["e2e4", "e7e5", "d1h5", "b8c6", "h5f7"]; //Just Check
-    //let moves = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"]; //Bergé
-    //let moves = ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5", "e1g1", "g8f6", "d1e2", "e8g8"]; // Test ROQUE
-    //let moves = ["e2e4", "b7b6", "d2d4", "c8a6", "f1a6", "h7h6", "d1f3", "b6b5", "a6b5", "a7a6", "b5a6", "h6h5", "e4e5", "h5h4", "f3a8", "b8a6", "a8d8", "e8d8", "g1f3", "h4h3", "g2h3"]; // Test ROQUE
-    //let moves = ["e2e4", "b7b6", "d2d4", "c8a6", "f1a6", "b8a6", "f2f4", "d7d5", "e4e5", "b6b5", "c2c3", "c7c6", "f4f5", "g8h6", "g2g4", "h6g4", "d1g4", "h7h5", "g4h5", "h8h5", "g1f3", "h5h2", "h1h2", "d8d7", "h2h5", "d7f5", "h5f5", "e7e6", "f5f7", "f8e7", "f7g7", "e8f8", "g7h7", "a6b4", "h7h8", "f8f7", "h8a8", "b4c2", "e1f2", "e7d8", "a8d8", "f7e7", "d8g8", "a7a5", "c1g5", "e7d7", "g8g7", "d7c8", "g7a7"];
-    //let moves = ["e2e4", "g8f6", "b1c3", "c7c6", "d2d4", "d8a5", "e4e5", "f6e4", "c1d2", "e4f2"];
-    //let moves = ["e2e4", "e7e6", "d2d4", "b7b6", "c2c4", "f8b4", "c1d2", "b4d2", "d1d2", "d8h4", "g1f3", "h4e4", "f1e2", "e4g4", "e1g1", "c8b7", "h2h3", "g4f5", "b1c3", "a7a5", "a2a3", "b7f3", "e2f3", "c7c6", "c4c5", "b6c5", "d4c5", "f5c5", "a1d1", "a5a4", "c3e4", "c5e5", "e4d6", "e8e7", "d6b7", "e5c7", "b7c5", "d7d6", "c5e4", "b8a6", "e4d6", "e6e5", "d6f5", "e7f6", "g2g4", "g7g6", "f5h4", "g6g5", "d2g5", "f6g5", "h4g2", "g8f6", "h3h4", "g5g6", "h4h5"];
-    //let moves = ["e2e4", "e7e5", "g1f3", "d8h4"];
-    //let moves = ["e2e4", "e7e5", "g1f3", "d8f6", "b1c3", "f6f4", "d2d4", "f4g4", "f3e5", "g4e6", "f1e2", "f8e7", "e1g1", "e8f8", "c1f4", "g7g5", "f4g3", "h7h6", "e2h5", "e6a6", "h5f7", "d7d5", "d1h5", "g8f6", "h5g6", "h8h7", "c3d5", "b8d7", "d5e7", "f8e7", "e5d7", "c8d7", "g3c7", "h7f7", "c7e5", "f6g4", "g6a6", "b7a6", "e5g3", "a8c8", "c2c3", "d7b5", "f1e1", "h6h5", "e4e5", "h5h4", "h2h3", "h4g3", "h3g4", "c8h8", "f2g3", "h8h1"];
-    //let moves = ["g2g3", "e7e5", "f1g2", "d8f6", "e2e4", "f8c5", "d1e2", "b8c6", "b1c3", "c6b4", "a1b1", "c5f2", "e2f2", "b4c2", "e1f1", "f6a6", "g1e2", "c2d4", "d2d3", "a6d3", "a2a3", "d7d6", "h2h4", "c8g4", "c1e3", "g4e2", "f1g1"];
-    //let moves = ["b2b3", "e7e6", "c1b2", "d8g5", "e2e4", "b8c6", "g1f3", "g5g4", "b1c3", "f8c5", "h2h3", "g4g6", "d1e2", "g6h6", "e1c1"];
-    //let moves = ["b2b3", "e7e6", "c1b2", "d8g5", "e2e4", "b8c6", "g1f3", "g5g4", "g2g3", "g4e4", "f1e2", "a7a5", "e1g1", "e4f5", "d2d4", "c6b4", "e2d3", "f5g4", "a2a3", "b4d5", "f1e1", "d5f4", "e1e4", "f4h3", "g1f1", "h3f2", "f1f2", "g4h5", "d4d5", "h5d5", "e4e5", "d5c6", "c2c4", "g8h6", "h2h4", "h6g4", "f2g2", "g4e5", "g2h3", "c6f3", "b1c3", "f3d3", "d1d3", "e5d3", "c3e4", "d3b2", "a1f1", "f8a3", "e4g5", "f7f5", "h4h5", "a5a4", "f1a1", "a4b3", "h3h4", "h7h6", "g5f3", "a3e7", "h4h3", "a8a1", "f3d2", "a1a3", "c4c5", "e7c5", "g3g4", "h8f8", "g4f5", "c5b4", "f5e6", "b4d2", "e6d7"];
-    //let moves = ["e2e4", "g2h1q"];
-    //let moves = ["e2e4", "e7e5q", "g1f3", "d8f6", "b1c3", "f6f4", "d2d4", "f4g4", "f3e5", "g4e6", "f2f4", "f8b4", "c1d2", "e6e7", "f1d3", "b7b6q", "e1g1", "c7c6q", "f4f5", "e7d6", "e5g4", "d6d4", "g1h1", "h7h5q", "d2e3", "d4d6", "e4e5", "d6c7", "f5f6", "h5g4q", "f6g7", "h8h5", "d1g4", "c7e5", "e3f4", "e5c5", "d3e2", "h5f5", "g4h4", "b4c3", "b2c3", "c5c3", "h4h8", "f5f4", "h8g8", "e8e7", "g8f8", "e7e6", "g7g8q", "c3h8", "f8e8", "e6d5", "g8g5"];
-    //let moves = ["d2d4", "e7e6q", "e2e4", "d8f6", "e4e5", "f6f5", "f1d3"];
-    let moves = ["e2e4", "e7e5", "b1c3", "b8c6", "g1f3", "g8f6", "a2a3", "d7d5", "f1b5", "f6e4", "c3e4", "d5e4", "f3e5", "d8g5", "d2d4", "g5g2", "e1d2", "f7f6", "e5c6", "g2g5"];
-    _draw_board(&game);
-    //let now = Instant::now();
-    for m in moves {
-    //loop {
-        //let mut m = String::new();
-        if game.white_to_play { println!("WHITE : "); }
-        else { println!("BLACK : "); }
-        let legal = get_legal_move(game.white_to_play, &game);
-        for x in legal {
-            print_custum_move(x);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::PERFT_POSITIONS;
+
+    //Every position of the tree keeps the mailbox and the bitboards in step
+    fn check_tree(game : &Game, depth : u32) {
+        assert!(game.is_consistent(), "{}", game.to_fen());
+        if depth == 0 {
+            return;
+        }
+        for (chessmove, _) in legal_moves(game) {
+            let mut next = *game;
+            next.play(chessmove);
+            check_tree(&next, depth - 1);
         }
-        //io::stdin().read_line(&mut m).unwrap();
-        println!("MOVE {m}");
-        let chessmove = convert_move_to_bitboard(&m);
+    }
 
-        let now = Instant::now();
-        let response = if game.white_to_play {
-            compute_move_w(chessmove, &mut game)
+    #[test]
+    fn perft_positions_stay_consistent() {
+        for (fen, _, _) in PERFT_POSITIONS {
+            check_tree(&Game::from_fen(fen).unwrap(), 3);
         }
-        else {
-            compute_move_b(chessmove, &mut game)
-        };
-        if response >= 0 {
-            game.white_to_play ^= true;
+    }
+
+    //One ply shallower than the perft subcommand, which checks the full depth
+    #[test]
+    fn perft_node_counts() {
+        let shallow = [197281, 97862, 674624, 422333];
+        for ((fen, depth, _), expected) in PERFT_POSITIONS.into_iter().zip(shallow) {
+            assert_eq!(perft(&Game::from_fen(fen).unwrap(), depth - 1), expected, "{fen}");
         }
-        
-        let k_attacked =  is_attacked(game.white_to_play, &game);
-        //let legal = get_legal_move(game.white_to_play, &game);
-        println!(" {} nano seconde", now.elapsed().as_nanos());
-        if k_attacked {
-            print!("CHECK");
-            if get_legal_move(game.white_to_play, &game).is_empty() {
-                print!(" MATE");
-            }
-            println!();
+    }
+
+    //The grid, the bitboards and the FEN have to agree on the orientation
+    #[test]
+    fn start_position_round_trips() {
+        let game = get_game_from_basicpos();
+        assert_eq!(bitboard_to_array(&game), BASICSTART_CHESS_BOARD);
+        assert_eq!(game.to_fen(), BASICSTART_FEN);
+        assert_eq!(bitboard_to_array(&Game::from_fen(BASICSTART_FEN).unwrap()), BASICSTART_CHESS_BOARD);
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for (fen, _, _) in PERFT_POSITIONS {
+            assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
         }
-        _draw_board(&game);
-        //draw_the_game_state(&game);
-        println!("{response}");
+        let chess960 = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(Game::from_fen(chess960).unwrap().to_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9");
     }
-    let legal = get_legal_move(game.white_to_play, &game);
-    for x in legal {
-        print_custum_move(x);
+
+    #[test]
+    fn char_grid_round_trips() {
+        for (fen, _, _) in PERFT_POSITIONS {
+            let game = Game::from_fen(fen).unwrap();
+            let grid = bitboard_to_array(&game);
+            let mut back = Game::empty();
+            array_to_bitboard(grid, &mut back);
+            assert_eq!(bitboard_to_array(&back), grid);
+            //The placement field of the FEN is the grid read row by row
+            assert_eq!(back.to_fen().split(' ').next(), fen.split(' ').next());
+            //a1 is the first square and white is uppercase
+            assert_eq!(grid[7][0], piece_char(&game, Square::A1));
+        }
     }
-  
 
+    #[test]
+    fn corrupted_boards_are_not_consistent() {
+        //A piece in the mailbox only
+        let mut game = Game::default();
+        game.mailbox[Square::E4.index()] = Some(ColoredPiece::new(Color::WHITE, Piece::QUEEN));
+        assert!(!game.is_consistent());
+        //A piece in the bitboards only
+        let mut game = Game::default();
+        game.bitboards[Color::BLACK.index()][Piece::KNIGHT.index()] |= Square::E5.bit();
+        assert!(!game.is_consistent());
+        //The mailbox names another piece
+        let mut game = Game::default();
+        game.mailbox[Square::D1.index()] = Some(ColoredPiece::new(Color::WHITE, Piece::KING));
+        assert!(!game.is_consistent());
+        //Two bitboards share a square, the mailbox agreeing with one of them
+        let mut game = Game::default();
+        game.bitboards[Color::WHITE.index()][Piece::ROOK.index()] |= Square::B1.bit();
+        assert!(!game.is_consistent());
+        assert!(Game::default().is_consistent());
+    }
 }
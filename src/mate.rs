@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use crate::{Game, legal_moves, is_attacked};
+use crate::moves::Move;
+use crate::search::{SearchLimits, MAX_DEPTH};
+use crate::zobrist::polyglot_key;
+
+//Proof of forced mates : every reply of the defender is tried, the attacker tries checks
+//first, then captures, then the other moves
+
+struct MateSearch<'a> {
+    limits : &'a SearchLimits,
+    nodes : u64,
+    stopped : bool,
+    //Most moves in which the attacker was shown not to mate, by position
+    no_mate : HashMap<u64, u32>,
+}
+
+impl MateSearch<'_> {
+    //The stop flag, the nodes and the hard time limit, which does not run while pondering
+    fn out_of_limits(&self) -> bool {
+        let limits = self.limits;
+        limits.stop.load(Ordering::Relaxed) || limits.nodes.is_some_and(|nodes| self.nodes >= nodes)
+            || (!limits.pondering() && limits.time.as_ref().is_some_and(|time| time.hard_limit_reached()))
+    }
+
+    //Attacker moves, checks first then captures
+    fn ordered_moves(game : &Game) -> Vec<Move> {
+        let mut moves : Vec<(Move, u8)> = legal_moves(game).into_iter().map(|(chessmove, _)| {
+            let mut next = *game;
+            next.play(chessmove);
+            let order = if is_attacked(next.side_to_move, &next) { 0 } else if game.side(!game.side_to_move) & chessmove.to.bit() != 0 { 1 } else { 2 };
+            (chessmove, order)
+        }).collect();
+        moves.sort_by_key(|&(_, order)| order);
+        moves.into_iter().map(|(chessmove, _)| chessmove).collect()
+    }
+
+    //An attacker move mating in at most `n` moves
+    fn attack(&mut self, game : &Game, n : u32) -> Option<Move> {
+        self.nodes += 1;
+        //A node generates the replies to each of its moves, the limits are read often
+        if self.nodes.is_multiple_of(64) {
+            self.stopped |= self.out_of_limits();
+        }
+        if self.stopped {
+            return None;
+        }
+        let key = polyglot_key(game);
+        if self.no_mate.get(&key).is_some_and(|&proven| proven >= n) {
+            return None;
+        }
+        for chessmove in Self::ordered_moves(game) {
+            let mut next = *game;
+            next.play(chessmove);
+            let in_check = is_attacked(next.side_to_move, &next);
+            //The last move must mate, so it must check
+            if n == 1 && !in_check {
+                break;
+            }
+            let replies = legal_moves(&next);
+            if replies.is_empty() {
+                if in_check {
+                    return Some(chessmove);
+                }
+                continue;
+            }
+            if n > 1 && replies.iter().all(|&(reply, _)| self.mates_after(&next, reply, n - 1)) {
+                return Some(chessmove);
+            }
+        }
+        if !self.stopped {
+            self.no_mate.insert(key, n);
+        }
+        None
+    }
+
+    //Whether the attacker still mates in at most `n` moves after the defender's `reply`
+    fn mates_after(&mut self, game : &Game, reply : Move, n : u32) -> bool {
+        let mut next = *game;
+        next.play(reply);
+        self.attack(&next, n).is_some()
+    }
+
+    //Fewest moves in which the attacker mates, up to `n`
+    fn shortest(&mut self, game : &Game, n : u32) -> Option<u32> {
+        (1..=n).find(|&k| self.attack(game, k).is_some())
+    }
+
+    //The mating line of a position known to be mate in `n`, the defender delaying the mate the most
+    fn line(&mut self, game : &Game, n : u32) -> Vec<Move> {
+        let Some(chessmove) = self.attack(game, n) else {
+            return Vec::new();
+        };
+        let mut next = *game;
+        next.play(chessmove);
+        let mut line = vec![chessmove];
+        let mut longest : Option<(Move, Game, u32)> = None;
+        for (reply, _) in legal_moves(&next) {
+            let mut after = next;
+            after.play(reply);
+            let k = self.shortest(&after, n - 1).unwrap_or(n - 1);
+            if longest.as_ref().is_none_or(|&(_, _, most)| k > most) {
+                longest = Some((reply, after, k));
+            }
+        }
+        if let Some((reply, after, k)) = longest {
+            line.push(reply);
+            line.extend(self.line(&after, k));
+        }
+        line
+    }
+}
+
+//Shortest forced mate for the side to move in at most `moves` moves, given up on when the stop flag,
+//the nodes or the time of `limits` run out. The line alternates attacker and defender moves and ends with the mate.
+pub fn find_mate_until(game : &Game, moves : u32, limits : &SearchLimits) -> Option<Vec<Move>> {
+    let mut search = MateSearch { limits, nodes : 0, stopped : false, no_mate : HashMap::new() };
+    search.stopped = search.out_of_limits();
+    let n = (1..=moves).find(|&n| search.stopped || search.attack(game, n).is_some())?;
+    if search.stopped {
+        return None;
+    }
+    let line = search.line(game, n);
+    //Stopped while the line was rebuilt, it may be cut short
+    if search.stopped || line.len() != 2 * n as usize - 1 {
+        return None;
+    }
+    Some(line)
+}
+
+//`None` means there is no mate in `moves` moves
+pub fn find_mate(game : &Game, moves : u32) -> Option<Vec<Move>> {
+    find_mate_until(game, moves, &SearchLimits::depth(MAX_DEPTH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::Instant;
+    use crate::time::TimeManager;
+
+    fn game_in_two() -> Game {
+        Game::from_fen("k7/8/2K5/8/8/8/8/1R6 w - - 0 1").unwrap()
+    }
+
+    //The line is legal, alternates the sides and ends with the mate
+    fn check_line(fen : &str, n : u32) {
+        let mut game = Game::from_fen(fen).unwrap();
+        let line = find_mate(&game, n).unwrap_or_else(|| panic!("no mate in {n} for {fen}"));
+        assert_eq!(line.len(), 2 * n as usize - 1, "{fen}");
+        assert!(find_mate(&game, n - 1).is_none(), "{fen}");
+        for chessmove in line {
+            assert!(legal_moves(&game).iter().any(|&(m, _)| m == chessmove), "{fen}");
+            game.play(chessmove);
+        }
+        assert!(legal_moves(&game).is_empty() && is_attacked(game.side_to_move, &game), "{fen}");
+    }
+
+    #[test]
+    fn known_mates() {
+        check_line("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+        check_line("k7/8/2K5/8/8/8/8/1R6 w - - 0 1", 2);
+        check_line("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 2);
+        check_line("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1", 3);
+    }
+
+    #[test]
+    fn no_mate() {
+        assert!(find_mate(&Game::default(), 3).is_none());
+        //Mate in 2, not in 1
+        assert!(find_mate(&Game::from_fen("k7/8/2K5/8/8/8/8/1R6 w - - 0 1").unwrap(), 1).is_none());
+    }
+
+    #[test]
+    fn already_stopped() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let limits = SearchLimits::depth(MAX_DEPTH);
+        limits.stop.store(true, Ordering::Relaxed);
+        assert!(find_mate_until(&game, 3, &limits).is_none());
+    }
+
+    #[test]
+    fn out_of_time_or_nodes() {
+        let start = Instant::now();
+        let limits = SearchLimits::time(TimeManager::fixed(Duration::from_millis(50), Duration::ZERO));
+        assert!(find_mate_until(&Game::default(), 10, &limits).is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let limits = SearchLimits { nodes : Some(2000), ..SearchLimits::depth(MAX_DEPTH) };
+        assert!(find_mate_until(&Game::default(), 10, &limits).is_none());
+        //The clock does not run while pondering
+        let limits = SearchLimits::time(TimeManager::fixed(Duration::ZERO, Duration::ZERO));
+        limits.ponder.store(true, Ordering::Relaxed);
+        assert_eq!(find_mate_until(&game_in_two(), 2, &limits).map(|line| line.len()), Some(3));
+    }
+}
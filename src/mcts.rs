@@ -0,0 +1,309 @@
+use std::sync::atomic::Ordering;
+use crate::{Game, Piece, legal_moves, is_attacked};
+use crate::eval::evaluate;
+use crate::moves::Move;
+use crate::search::{PvLine, SearchLimits, SearchResult, MATE, TT_MEGABYTES};
+use crate::time::TimeManager;
+use crate::zobrist::polyglot_key;
+
+//Monte Carlo tree search : UCT selection, one node added per iteration, a short playout
+//scored by the evaluation when it does not end the game
+
+#[derive(Clone)]
+pub struct MctsOptions {
+    pub iterations : u64,
+    //Weight of the exploration term of UCT
+    pub exploration : f64,
+    //Captures are more likely in the playouts, the more so the bigger the victim
+    pub biased : bool,
+    //Plies of a playout before the evaluation decides
+    pub playout_plies : u32,
+    //Size of the tree, once full the iterations go on without adding nodes
+    pub max_nodes : usize,
+}
+
+impl Default for MctsOptions {
+    fn default() -> Self {
+        MctsOptions { iterations : 10000, exploration : 1.4, biased : true, playout_plies : 16, max_nodes : Mcts::nodes_in(TT_MEGABYTES) }
+    }
+}
+
+struct Node {
+    game : Game,
+    //Move from the parent
+    chessmove : Option<Move>,
+    children : Vec<usize>,
+    untried : Vec<Move>,
+    visits : u32,
+    //Sum of the results for the side that played `chessmove`, 1 for a win
+    reward : f64,
+    //Result for the side to move if the game is over
+    outcome : Option<f64>,
+}
+
+impl Node {
+    fn new(game : Game, chessmove : Option<Move>) -> Node {
+        let moves : Vec<Move> = legal_moves(&game).into_iter().map(|(m, _)| m).collect();
+        let outcome = Mcts::outcome(&game, &moves);
+        Node { game, chessmove, children : Vec::new(), untried : moves, visits : 0, reward : 0.0, outcome }
+    }
+}
+
+//The tree is kept between moves : the next search starts from the node of the new position
+pub struct Mcts {
+    nodes : Vec<Node>,
+    root : usize,
+    //xorshift64*
+    state : u64,
+}
+
+//Chance of winning for the side to move, from the evaluation
+fn win_probability(centipawns : i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-f64::from(centipawns) / 400.0))
+}
+
+fn centipawns(probability : f64) -> i32 {
+    let probability = probability.clamp(0.001, 0.999);
+    (400.0 * (probability / (1.0 - probability)).log10()) as i32
+}
+
+impl Mcts {
+    pub fn new(seed : u64) -> Mcts {
+        Mcts { nodes : Vec::new(), root : 0, state : seed.max(1) }
+    }
+
+    //Nodes held by `megabytes`, counting the untried moves a node starts with
+    pub fn nodes_in(megabytes : usize) -> usize {
+        let node = std::mem::size_of::<Node>() + 32 * std::mem::size_of::<Move>();
+        (megabytes * 1024 * 1024 / node).max(1)
+    }
+
+    fn random(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    //Keep the subtree of `game` if it is the root, a child or a grandchild of the root
+    fn reroot(&mut self, game : &Game) {
+        let key = polyglot_key(game);
+        let found = self.nodes.get(self.root).and_then(|root| {
+            std::iter::once(self.root)
+                .chain(root.children.iter().copied())
+                .chain(root.children.iter().flat_map(|&child| self.nodes[child].children.iter().copied()))
+                .find(|&i| polyglot_key(&self.nodes[i].game) == key)
+        });
+        let Some(found) = found else {
+            self.nodes = vec![Node::new(*game, None)];
+            self.root = 0;
+            return;
+        };
+        //Copy the subtree into a new arena
+        let mut nodes : Vec<Node> = Vec::new();
+        let mut old : Vec<Option<Node>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut stack : Vec<(usize, Option<usize>)> = vec![(found, None)];
+        while let Some((index, parent)) = stack.pop() {
+            let Some(mut node) = old[index].take() else {
+                continue;
+            };
+            let children = std::mem::take(&mut node.children);
+            nodes.push(node);
+            let new_index = nodes.len() - 1;
+            if let Some(parent) = parent {
+                nodes[parent].children.push(new_index);
+            }
+            stack.extend(children.into_iter().map(|child| (child, Some(new_index))));
+        }
+        nodes[0].chessmove = None;
+        self.nodes = nodes;
+        self.root = 0;
+    }
+
+    //Child with the best UCT value
+    fn select(&self, index : usize, exploration : f64) -> usize {
+        let parent = &self.nodes[index];
+        let log_visits = f64::from(parent.visits.max(1)).ln();
+        let uct = |child : usize| {
+            let node = &self.nodes[child];
+            let visits = f64::from(node.visits.max(1));
+            node.reward / visits + exploration * (log_visits / visits).sqrt()
+        };
+        parent.children.iter().copied().max_by(|&a, &b| uct(a).total_cmp(&uct(b))).unwrap_or(index)
+    }
+
+    //Result of the game for the side to move, if it is over
+    fn outcome(game : &Game, moves : &[Move]) -> Option<f64> {
+        if moves.is_empty() {
+            return Some(if is_attacked(game.side_to_move, game) { 0.0 } else { 0.5 });
+        }
+        if game.halfmove >= 100 || game.occupied().count_ones() == 2 {
+            return Some(0.5);
+        }
+        None
+    }
+
+    fn pick(&mut self, game : &Game, moves : &[Move], biased : bool) -> Move {
+        if !biased {
+            return moves[(self.random() % moves.len() as u64) as usize];
+        }
+        let weight = |m : &Move| match game.piece_at(m.to) {
+            Some(p) if p.color != game.side_to_move => 1 + p.piece as u64,
+            _ if m.promotion != Piece::NONE => 4,
+            _ => 1,
+        };
+        let total : u64 = moves.iter().map(weight).sum();
+        let mut draw = self.random() % total;
+        for m in moves {
+            if draw < weight(m) {
+                return *m;
+            }
+            draw -= weight(m);
+        }
+        moves[0]
+    }
+
+    //Result for the side to move of `game` after a playout
+    fn playout(&mut self, game : &Game, options : &MctsOptions) -> f64 {
+        let mut game = *game;
+        let mut flipped = false;
+        for _ in 0..options.playout_plies {
+            let moves : Vec<Move> = legal_moves(&game).into_iter().map(|(m, _)| m).collect();
+            if let Some(result) = Self::outcome(&game, &moves) {
+                return if flipped { 1.0 - result } else { result };
+            }
+            let chessmove = self.pick(&game, &moves, options.biased);
+            game.play(chessmove);
+            flipped = !flipped;
+        }
+        let result = win_probability(evaluate(&game));
+        if flipped { 1.0 - result } else { result }
+    }
+
+    fn iterate(&mut self, options : &MctsOptions) {
+        //Selection
+        let mut path = vec![self.root];
+        let mut index = self.root;
+        while self.nodes[index].outcome.is_none() && self.nodes[index].untried.is_empty() && !self.nodes[index].children.is_empty() {
+            index = self.select(index, options.exploration);
+            path.push(index);
+        }
+        //Expansion, while there is room in the tree
+        if self.nodes[index].outcome.is_none() && !self.nodes[index].untried.is_empty() && self.nodes.len() < options.max_nodes {
+            let pick = (self.random() % self.nodes[index].untried.len() as u64) as usize;
+            let chessmove = self.nodes[index].untried.swap_remove(pick);
+            let mut game = self.nodes[index].game;
+            game.play(chessmove);
+            self.nodes.push(Node::new(game, Some(chessmove)));
+            let child = self.nodes.len() - 1;
+            self.nodes[index].children.push(child);
+            path.push(child);
+            index = child;
+        }
+        //Simulation, for the side to move at the leaf
+        let game = self.nodes[index].game;
+        let result = match self.nodes[index].outcome {
+            Some(result) => result,
+            None => self.playout(&game, options),
+        };
+        //Backpropagation, the reward of a node is for the side that moved into it
+        let mut reward = 1.0 - result;
+        for &i in path.iter().rev() {
+            self.nodes[i].visits += 1;
+            self.nodes[i].reward += reward;
+            reward = 1.0 - reward;
+        }
+    }
+
+    //Most visited moves from `index`
+    fn principal_variation(&self, mut index : usize) -> Vec<Move> {
+        let mut pv = Vec::new();
+        while let Some(&best) = self.nodes[index].children.iter().max_by_key(|&&child| self.nodes[child].visits) {
+            pv.extend(self.nodes[best].chessmove);
+            index = best;
+        }
+        pv
+    }
+
+    fn result(&self, iterations : u64) -> SearchResult {
+        let pv = self.principal_variation(self.root);
+        let best = self.nodes[self.root].children.iter().copied().max_by_key(|&child| self.nodes[child].visits);
+        let score = best.map_or(0, |child| {
+            let node = &self.nodes[child];
+            centipawns(node.reward / f64::from(node.visits.max(1)))
+        });
+        //Without an expanded child yet, any legal move beats no move at all
+        let best_move = pv.first().or(self.nodes[self.root].untried.first()).copied();
+        SearchResult { best_move, score, nodes : iterations, depth : pv.len() as u32, pv : pv.clone(), lines : vec![PvLine { score, pv }] }
+    }
+
+    //Iterations until the count of the options, the nodes, the time or the stop flag of the limits,
+    //`on_progress` is called every 1000 iterations
+    pub fn search(&mut self, game : &Game, options : &MctsOptions, limits : &SearchLimits, on_progress : &mut dyn FnMut(&SearchResult)) -> SearchResult {
+        self.reroot(game);
+        let root = &mut self.nodes[self.root];
+        if root.untried.is_empty() && root.children.is_empty() {
+            limits.wait_for_stop();
+            let score = if is_attacked(game.side_to_move, game) { -MATE } else { 0 };
+            return SearchResult { best_move : None, score, nodes : 0, depth : 0, pv : Vec::new(), lines : Vec::new() };
+        }
+        //A move is still wanted past the fifty moves
+        root.outcome = None;
+        let mut iterations = 0;
+        //An infinite search goes on until it is stopped
+        let max = if limits.infinite { u64::MAX } else { options.iterations }.min(limits.nodes.unwrap_or(u64::MAX));
+        while iterations < max && !limits.stop.load(Ordering::Relaxed) {
+            if !limits.pondering() && limits.time.as_ref().is_some_and(|time : &TimeManager| !time.start_iteration(1.0)) {
+                break;
+            }
+            self.iterate(options);
+            iterations += 1;
+            if iterations % 1000 == 0 {
+                on_progress(&self.result(iterations));
+            }
+        }
+        limits.wait_for_stop();
+        let result = self.result(iterations);
+        on_progress(&result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_stays_within_its_budget() {
+        let options = MctsOptions { iterations : 3000, max_nodes : 200, ..MctsOptions::default() };
+        let mut mcts = Mcts::new(1);
+        let mut game = Game::default();
+        for _ in 0..2 {
+            let result = mcts.search(&game, &options, &SearchLimits::depth(1), &mut |_| {});
+            assert!(mcts.nodes.len() <= options.max_nodes);
+            assert_eq!(result.nodes, options.iterations);
+            //The iterations past the budget still go through the tree, the second search reuses a subtree
+            assert!(u64::from(mcts.nodes[mcts.root].visits) >= options.iterations);
+            let best = result.best_move.unwrap();
+            assert!(legal_moves(&game).iter().any(|&(m, _)| m == best));
+            game.play(best);
+        }
+        assert!(Mcts::nodes_in(1) < Mcts::nodes_in(16) && Mcts::nodes_in(0) == 1);
+    }
+
+    #[test]
+    fn a_move_without_any_iteration() {
+        let game = Game::default();
+        let legal = |m : Option<Move>| m.is_some_and(|m| legal_moves(&game).iter().any(|&(l, _)| l == m));
+        let options = MctsOptions { iterations : 0, ..MctsOptions::default() };
+        let result = Mcts::new(1).search(&game, &options, &SearchLimits::depth(1), &mut |_| {});
+        assert_eq!(result.nodes, 0);
+        assert!(legal(result.best_move));
+        //The root alone fills the tree, the iterations cannot expand it
+        let options = MctsOptions { iterations : 100, max_nodes : 1, ..MctsOptions::default() };
+        let mut mcts = Mcts::new(1);
+        let result = mcts.search(&game, &options, &SearchLimits::depth(1), &mut |_| {});
+        assert!(mcts.nodes[mcts.root].children.is_empty());
+        assert!(legal(result.best_move));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::{Game, Color, Piece, PIECES, MoveKind, get_moves, attack_p, diag_antid_moves, hv_moves, KNIGHT_MOVE, KING_MOVE};
+use crate::bitboard::Bitboard;
+use crate::eval::{PAWN_VALUE, KNIGHT_VALUE, BISHOP_VALUE, ROOK_VALUE, QUEEN_VALUE};
+use crate::moves::Move;
+use crate::square::Square;
+
+pub const MAX_PLY : usize = 128;
+
+fn value(piece : Piece) -> i32 {
+    match piece {
+        Piece::PAWN => PAWN_VALUE,
+        Piece::KNIGHT => KNIGHT_VALUE,
+        Piece::BISHOP => BISHOP_VALUE,
+        Piece::ROOK => ROOK_VALUE,
+        Piece::QUEEN => QUEEN_VALUE,
+        Piece::KING => 20000,
+        _ => 0,
+    }
+}
+
+//Piece taken by the move, a pawn for en passant
+fn captured(game : &Game, chessmove : Move) -> Option<Piece> {
+    match game.piece_at(chessmove.to) {
+        Some(piece) if piece.color != game.side_to_move => Some(piece.piece),
+        None if chessmove.to.bit() == game.en_passant && game.pieces(game.side_to_move, Piece::PAWN) & chessmove.from.bit() != 0 => Some(Piece::PAWN),
+        _ => None,
+    }
+}
+
+fn is_legal(game : &Game, chessmove : Move) -> bool {
+    let mut next = *game;
+    next.play(chessmove) >= 0
+}
+
+pub fn is_quiet(game : &Game, chessmove : Move) -> bool {
+    chessmove.promotion == Piece::NONE && captured(game, chessmove).is_none()
+}
+
+//Pieces of both sides attacking `square` with the given occupancy
+fn attackers_to(game : &Game, square : Square, occupied : u64) -> u64 {
+    let bit = square.bit();
+    let both = |piece : Piece| game.pieces(Color::WHITE, piece) | game.pieces(Color::BLACK, piece);
+    let diagonal = both(Piece::BISHOP) | both(Piece::QUEEN);
+    let straight = both(Piece::ROOK) | both(Piece::QUEEN);
+    let attackers = attack_p(Color::BLACK, bit, game.pieces(Color::WHITE, Piece::PAWN))
+        | attack_p(Color::WHITE, bit, game.pieces(Color::BLACK, Piece::PAWN))
+        | (KNIGHT_MOVE[square.index()] & both(Piece::KNIGHT))
+        | (KING_MOVE[square.index()] & both(Piece::KING))
+        | (diag_antid_moves(square, occupied) & diagonal)
+        | (hv_moves(square, occupied) & straight);
+    attackers & occupied
+}
+
+//Static exchange evaluation : material won by the side to move after the captures on the target square
+pub fn see(game : &Game, chessmove : Move) -> i32 {
+    let Some(mut attacker) = game.piece_at(chessmove.from).map(|p| p.piece) else {
+        return 0;
+    };
+    let mut gain = [0;32];
+    gain[0] = captured(game, chessmove).map_or(0, value);
+    let mut occupied = game.occupied();
+    let mut from = chessmove.from.bit();
+    let mut side = game.side_to_move;
+    let mut d = 0;
+    loop {
+        d += 1;
+        side = !side;
+        gain[d] = value(attacker) - gain[d - 1];
+        if d == gain.len() - 1 {
+            break;
+        }
+        occupied ^= from;
+        let attackers = attackers_to(game, chessmove.to, occupied);
+        let Some(next) = PIECES.into_iter().find(|&piece| attackers & game.pieces(side, piece) != 0) else {
+            break;
+        };
+        attacker = next;
+        from = Bitboard(attackers & game.pieces(side, next)).lsb().map_or(0, Square::bit);
+    }
+    d -= 1;
+    while d > 0 {
+        gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+        d -= 1;
+    }
+    gain[0]
+}
+
+//What the search learnt about quiet moves : killers per ply, butterfly history and countermoves
+pub struct Heuristics {
+    killers : [[Option<Move>;2];MAX_PLY],
+    history : [[[i32;64];64];2],
+    counter_moves : [[Option<Move>;64];64],
+}
+
+impl Heuristics {
+    pub fn new() -> Box<Heuristics> {
+        Box::new(Heuristics { killers : [[None;2];MAX_PLY], history : [[[0;64];64];2], counter_moves : [[None;64];64] })
+    }
+
+    pub fn killers(&self, ply : usize) -> [Option<Move>;2] {
+        self.killers[ply.min(MAX_PLY - 1)]
+    }
+
+    pub fn counter_move(&self, previous : Option<Move>) -> Option<Move> {
+        previous.and_then(|p| self.counter_moves[p.from.index()][p.to.index()])
+    }
+
+    fn history(&self, color : Color, chessmove : Move) -> i32 {
+        self.history[color.index()][chessmove.from.index()][chessmove.to.index()]
+    }
+
+    //Bonus for the quiet move that failed high, malus for the quiet moves tried before it
+    pub fn update(&mut self, color : Color, chessmove : Move, tried : &[Move], ply : usize, depth : u32, previous : Option<Move>) {
+        let killers = &mut self.killers[ply.min(MAX_PLY - 1)];
+        if killers[0] != Some(chessmove) {
+            killers[1] = killers[0];
+            killers[0] = Some(chessmove);
+        }
+        if let Some(p) = previous {
+            self.counter_moves[p.from.index()][p.to.index()] = Some(chessmove);
+        }
+        let bonus = (depth * depth).min(400) as i32;
+        let mut add = |m : Move, bonus : i32| {
+            let entry = &mut self.history[color.index()][m.from.index()][m.to.index()];
+            //Gravity keeps the values within +-16384
+            *entry += bonus - *entry * bonus.abs() / 16384;
+        };
+        add(chessmove, bonus);
+        for &m in tried {
+            add(m, -bonus);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killer1,
+    Killer2,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    //Without ordering, the moves as they are generated
+    GenerateAll,
+    All,
+    Done,
+}
+
+//Yields the legal moves stage by stage, quiet moves are only generated if no capture cut off the search
+pub struct MovePicker {
+    stage : Stage,
+    tt_move : Option<Move>,
+    killers : [Option<Move>;2],
+    counter_move : Option<Move>,
+    moves : Vec<(Move, Piece, i32)>,
+    bad_captures : Vec<(Move, Piece)>,
+    index : usize,
+    quiescence : bool,
+}
+
+impl MovePicker {
+    pub fn new(tt_move : Option<Move>, killers : [Option<Move>;2], counter_move : Option<Move>, ordered : bool) -> MovePicker {
+        MovePicker {
+            stage : if ordered { Stage::TtMove } else { Stage::GenerateAll },
+            tt_move, killers, counter_move,
+            moves : Vec::new(),
+            bad_captures : Vec::new(),
+            index : 0,
+            quiescence : false,
+        }
+    }
+
+    //Captures and promotions only, losing captures are left out
+    pub fn quiescence(ordered : bool) -> MovePicker {
+        MovePicker { quiescence : true, stage : if ordered { Stage::GenerateCaptures } else { Stage::GenerateAll }, ..MovePicker::new(None, [None;2], None, ordered) }
+    }
+
+    //A move remembered from another position, if it is a legal quiet move here
+    fn quiet_move(game : &Game, chessmove : Option<Move>) -> Option<(Move, Piece)> {
+        let chessmove = chessmove?;
+        let piece = game.piece_at(chessmove.from).filter(|p| p.color == game.side_to_move)?.piece;
+        let last_rank = chessmove.to.rank() == 0 || chessmove.to.rank() == 7;
+        if !is_quiet(game, chessmove) || (piece == Piece::PAWN && last_rank) || !is_legal(game, chessmove) {
+            return None;
+        }
+        Some((chessmove, piece))
+    }
+
+    fn is_special(&self, chessmove : Move) -> bool {
+        Some(chessmove) == self.tt_move || self.killers.contains(&Some(chessmove)) || Some(chessmove) == self.counter_move
+    }
+
+    //Take the best scored move left
+    fn select_best(&mut self) -> Option<(Move, Piece)> {
+        let best = (self.index..self.moves.len()).max_by_key(|&i| self.moves[i].2)?;
+        self.moves.swap(self.index, best);
+        self.index += 1;
+        let (chessmove, piece, _) = self.moves[self.index - 1];
+        Some((chessmove, piece))
+    }
+
+    pub fn next(&mut self, game : &Game, heuristics : &Heuristics) -> Option<(Move, Piece)> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(tt_move) = self.tt_move {
+                        let piece = game.piece_at(tt_move.from).filter(|p| p.color == game.side_to_move);
+                        if let Some(piece) = piece.filter(|_| is_legal(game, tt_move)) {
+                            return Some((tt_move, piece.piece));
+                        }
+                    }
+                },
+                Stage::GenerateCaptures => {
+                    //Most valuable victim first, least valuable attacker first
+                    self.moves = get_moves(game.side_to_move, game, MoveKind::CAPTURES).into_iter()
+                        .filter(|&(m, _)| Some(m) != self.tt_move)
+                        .map(|(m, piece)| {
+                            let victim = captured(game, m).map_or(0, value) + value(m.promotion);
+                            (m, piece, 10 * victim - value(piece).min(QUEEN_VALUE + 100))
+                        })
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                },
+                Stage::GoodCaptures => {
+                    match self.select_best() {
+                        Some((m, piece)) if see(game, m) < 0 => {
+                            if !self.quiescence {
+                                self.bad_captures.push((m, piece));
+                            }
+                        },
+                        Some(found) => return Some(found),
+                        None => self.stage = if self.quiescence { Stage::Done } else { Stage::Killer1 },
+                    }
+                },
+                Stage::Killer1 | Stage::Killer2 => {
+                    let (killer, next) = if self.stage == Stage::Killer1 { (self.killers[0], Stage::Killer2) } else { (self.killers[1], Stage::CounterMove) };
+                    self.stage = next;
+                    if killer != self.tt_move {
+                        if let Some(found) = Self::quiet_move(game, killer) {
+                            return Some(found);
+                        }
+                    }
+                },
+                Stage::CounterMove => {
+                    self.stage = Stage::GenerateQuiets;
+                    let counter = self.counter_move;
+                    if counter != self.tt_move && !self.killers.contains(&counter) {
+                        if let Some(found) = Self::quiet_move(game, counter) {
+                            return Some(found);
+                        }
+                    }
+                },
+                Stage::GenerateQuiets => {
+                    let color = game.side_to_move;
+                    self.moves = get_moves(color, game, MoveKind::QUIETS).into_iter()
+                        .filter(|&(m, _)| !self.is_special(m))
+                        .map(|(m, piece)| (m, piece, heuristics.history(color, m)))
+                        .collect();
+                    self.moves.sort_by_key(|&(_, _, history)| std::cmp::Reverse(history));
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                },
+                Stage::Quiets => {
+                    if let Some(&(m, piece, _)) = self.moves.get(self.index) {
+                        self.index += 1;
+                        return Some((m, piece));
+                    }
+                    self.index = 0;
+                    self.stage = Stage::BadCaptures;
+                },
+                Stage::BadCaptures => {
+                    if let Some(&found) = self.bad_captures.get(self.index) {
+                        self.index += 1;
+                        return Some(found);
+                    }
+                    self.stage = Stage::Done;
+                },
+                Stage::GenerateAll => {
+                    let kind = if self.quiescence { MoveKind::CAPTURES } else { MoveKind::ALL };
+                    self.moves = get_moves(game.side_to_move, game, kind).into_iter().map(|(m, piece)| (m, piece, 0)).collect();
+                    self.index = 0;
+                    self.stage = Stage::All;
+                },
+                Stage::All => {
+                    let &(m, piece, _) = self.moves.get(self.index)?;
+                    self.index += 1;
+                    return Some((m, piece));
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legal_moves;
+
+    fn uci(moves : &[(Move, Piece)]) -> Vec<String> {
+        moves.iter().map(|(m, _)| m.to_string()).collect()
+    }
+
+    #[test]
+    fn stages_in_order() {
+        //Two winning pawn captures, a rook taking a defended pawn, and quiet moves
+        let game = Game::from_fen("4k3/1p6/p7/2n1r3/3P4/8/8/R5K1 w - - 0 1").unwrap();
+        let parse = |m : &str| m.parse::<Move>().unwrap();
+        let mut picker = MovePicker::new(Some(parse("g1g2")), [Some(parse("a1a2")), Some(parse("g1f1"))], Some(parse("a1b1")), true);
+        let heuristics = Heuristics::new();
+        let mut picked = Vec::new();
+        while let Some(found) = picker.next(&game, &heuristics) {
+            picked.push(found);
+        }
+        let picked = uci(&picked);
+        assert_eq!(picked[..6], ["g1g2", "d4e5", "d4c5", "a1a2", "g1f1", "a1b1"]);
+        assert_eq!(picked.last().map(String::as_str), Some("a1a6"));
+        //Every legal move once
+        let mut sorted = picked.clone();
+        sorted.sort();
+        let mut legal = uci(&legal_moves(&game));
+        legal.sort();
+        assert_eq!(sorted, legal);
+        //Quiescence keeps the winning captures only
+        let mut picker = MovePicker::quiescence(true);
+        let mut captures = Vec::new();
+        while let Some(found) = picker.next(&game, &heuristics) {
+            captures.push(found);
+        }
+        assert_eq!(uci(&captures), ["d4e5", "d4c5"]);
+    }
+
+    #[test]
+    fn static_exchanges() {
+        let see_of = |fen : &str, chessmove : &str| see(&Game::from_fen(fen).unwrap(), chessmove.parse().unwrap());
+        //Pawn takes a knight, then a defended knight
+        assert_eq!(see_of("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"), KNIGHT_VALUE);
+        assert_eq!(see_of("4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"), KNIGHT_VALUE - PAWN_VALUE);
+        //Queen takes a pawn defended by a pawn
+        assert_eq!(see_of("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", "d1d5"), PAWN_VALUE - QUEEN_VALUE);
+        //The rook behind the queen recaptures, the x-ray is seen
+        assert_eq!(see_of("4k3/8/8/3r4/8/8/3Q4/3RK3 w - - 0 1", "d2d5"), ROOK_VALUE);
+        assert_eq!(see_of("3rk3/8/8/3r4/8/8/3Q4/3RK3 w - - 0 1", "d2d5"), ROOK_VALUE - QUEEN_VALUE + ROOK_VALUE);
+        //A quiet move to an attacked square
+        assert_eq!(see_of("4k3/8/8/8/2p5/8/3N4/4K3 w - - 0 1", "d2b3"), -KNIGHT_VALUE);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::{Game, Piece, ColoredPiece, legal_moves};
+use crate::square::{Square, SquareParseError};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from : Square,
+    pub to : Square,
+    pub promotion : Piece,
+}
+
+impl Move {
+    pub fn new(from : Square, to : Square, promotion : Piece) -> Move {
+        Move { from, to, promotion }
+    }
+    //Castling is stored as the king taking its own rook
+    pub fn is_castling(self, game : &Game) -> bool {
+        match (game.piece_at(self.from), game.piece_at(self.to)) {
+            (Some(king), Some(rook)) => king.piece == Piece::KING && rook == ColoredPiece::new(king.color, Piece::ROOK),
+            _ => false,
+        }
+    }
+    //Castling written as the two squares jump of the king, e.g. e1g1 instead of e1h1
+    fn king_jump(self) -> Move {
+        let file = if self.to.file() > self.from.file() { 6 } else { 2 };
+        Move::new(self.from, Square::from_coords(file, self.from.rank()), Piece::NONE)
+    }
+    //UCI text of the move in `game`, castling is only written king takes rook in Chess960
+    pub fn to_uci(self, game : &Game) -> String {
+        if !game.chess960 && self.is_castling(game) { self.king_jump().to_string() } else { self.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveParseError {
+    //Not of the form <from><to>[promotion], e.g. "e2", "e2-e4" or non-ASCII input
+    BadSyntax(String),
+    //A square outside a1..h8, e.g. "z9"
+    OffBoard(String),
+    //A promotion letter other than q, r, b or n
+    IllegalPromotion(char),
+    //Well formed but not legal in the given position
+    IllegalMove(Move),
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveParseError::BadSyntax(input) => write!(f, "'{input}' is not a move, expected something like e2e4 or e7e8q"),
+            MoveParseError::OffBoard(square) => write!(f, "'{square}' is not a square of the board"),
+            MoveParseError::IllegalPromotion(c) => write!(f, "'{c}' is not a promotion piece, expected q, r, b or n"),
+            MoveParseError::IllegalMove(chessmove) => write!(f, "{chessmove} is not a legal move in this position"),
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+//A malformed square reports the whole move, a square off the board only itself
+fn parse_square(moves : &str, square : &str) -> Result<Square, MoveParseError> {
+    square.parse().map_err(|e| match e {
+        SquareParseError::BadSyntax => MoveParseError::BadSyntax(moves.to_string()),
+        SquareParseError::OffBoard => MoveParseError::OffBoard(square.to_string()),
+    })
+}
+
+impl FromStr for Move {
+    type Err = MoveParseError;
+
+    //Parse a move in UCI notation without checking it against a position
+    fn from_str(moves : &str) -> Result<Move, MoveParseError> {
+        if !moves.is_ascii() || !(4..=5).contains(&moves.len()) {
+            return Err(MoveParseError::BadSyntax(moves.to_string()));
+        }
+        let from = parse_square(moves, &moves[0..2])?;
+        let to = parse_square(moves, &moves[2..4])?;
+        let promotion = match moves[4..].chars().next() {
+            None => Piece::NONE,
+            Some('q') => Piece::QUEEN,
+            Some('r') => Piece::ROOK,
+            Some('b') => Piece::BISHOP,
+            Some('n') => Piece::KNIGHT,
+            Some(c) => return Err(MoveParseError::IllegalPromotion(c)),
+        };
+        Ok(Move { from, to, promotion })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        match self.promotion {
+            Piece::QUEEN => write!(f, "q"),
+            Piece::ROOK => write!(f, "r"),
+            Piece::BISHOP => write!(f, "b"),
+            Piece::KNIGHT => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+//Parse a move in UCI notation and check that it is legal in `game`.
+//Outside of Chess960 castling is also accepted as the king jump, e.g. e1g1
+pub fn parse_move(moves : &str, game : &Game) -> Result<Move, MoveParseError> {
+    let chessmove : Move = moves.parse()?;
+    legal_moves(game).into_iter()
+        .map(|(m, _)| m)
+        .find(|&m| m == chessmove || (!game.chess960 && m.is_castling(game) && m.king_jump() == chessmove))
+        .ok_or(MoveParseError::IllegalMove(chessmove))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BASICSTART_FEN;
+
+    #[test]
+    fn parse_errors() {
+        let start = Game::from_fen(BASICSTART_FEN).unwrap();
+        let e2e5 = Move::new(Square::E2, Square::E5, Piece::NONE);
+        let cases = [
+            ("e2e4", Ok(Move::new(Square::E2, Square::E4, Piece::NONE))),
+            ("e1g1", Err(MoveParseError::IllegalMove(Move::new(Square::E1, Square::G1, Piece::NONE)))),
+            ("z9z9", Err(MoveParseError::OffBoard("z9".to_string()))),
+            ("e2i4", Err(MoveParseError::OffBoard("i4".to_string()))),
+            ("e2", Err(MoveParseError::BadSyntax("e2".to_string()))),
+            ("e2e4qq", Err(MoveParseError::BadSyntax("e2e4qq".to_string()))),
+            ("é2e4", Err(MoveParseError::BadSyntax("é2e4".to_string()))),
+            ("e2-e4", Err(MoveParseError::BadSyntax("e2-e4".to_string()))),
+            ("e7e8k", Err(MoveParseError::IllegalPromotion('k'))),
+            ("e2e5", Err(MoveParseError::IllegalMove(e2e5))),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_move(input, &start), expected, "{input}");
+        }
+        //Promotions and castling once they are legal
+        let game = Game::from_fen("4k3/1P6/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(parse_move("b7b8n", &game), Ok(Move::new(Square::B7, Square::B8, Piece::KNIGHT)));
+        assert_eq!(parse_move("e1g1", &game), Ok(Move::new(Square::E1, Square::H1, Piece::NONE)));
+        assert_eq!(parse_move("b7b8", &game), Err(MoveParseError::IllegalMove(Move::new(Square::B7, Square::B8, Piece::NONE))));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::Game;
+use crate::moves::{parse_move, Move};
+use crate::san::parse_san;
+
+//A game of a PGN file, the moves are kept in SAN as written in the file
+#[derive(Debug, Clone, Default)]
+pub struct PgnGame {
+    pub tags : Vec<(String, String)>,
+    pub moves : Vec<String>,
+    pub result : String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name : &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    //The starting position, from the FEN tag if there is one
+    pub fn start(&self) -> Result<Game, String> {
+        match self.tag("FEN") {
+            Some(fen) => Game::from_fen(fen),
+            None => Ok(Game::default()),
+        }
+    }
+
+    //Replay the first `plies` moves, stop at the first move that cannot be read
+    pub fn replay(&self, plies : usize) -> Result<Vec<(Game, Move)>, String> {
+        let mut game = self.start()?;
+        let mut played = Vec::new();
+        for san in self.moves.iter().take(plies) {
+            let chessmove = parse_san(&game, san).or_else(|| parse_move(san, &game).ok())
+                .ok_or(format!("illegal move '{san}' after {} plies", played.len()))?;
+            played.push((game, chessmove));
+            game.play(chessmove);
+        }
+        Ok(played)
+    }
+
+    //The game as PGN text, the tags first, the moves numbered and wrapped at 80 columns
+    pub fn to_pgn(&self) -> String {
+        let mut text : String = self.tags.iter().map(|(name, value)| format!("[{name} \"{}\"]\n", value.replace('"', "'"))).collect();
+        text.push('\n');
+        let start = self.start().unwrap_or_default();
+        let mut words = Vec::new();
+        for (i, (ply, san)) in (start.nb_coups as usize..).zip(&self.moves).enumerate() {
+            if ply.is_multiple_of(2) {
+                words.push(format!("{}.", ply / 2 + 1));
+            }
+            else if i == 0 {
+                words.push(format!("{}...", ply / 2 + 1));
+            }
+            words.push(san.clone());
+        }
+        words.push(if self.result.is_empty() { "*".to_string() } else { self.result.clone() });
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() > 80 {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        text.push_str(&line);
+        text.push_str("\n\n");
+        text
+    }
+}
+
+fn is_result(token : &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+//Close the current token once a separator is found
+fn finish_token(token : &mut String, game : &mut PgnGame, games : &mut Vec<PgnGame>, variation : usize) {
+    //Move numbers may be glued to the move, as in "1.e4" or "12...Nf6"
+    let word = if is_result(token) || token.starts_with("0-0") {
+        token.as_str()
+    }
+    else {
+        token.trim_start_matches(|c : char| c.is_ascii_digit() || c == '.')
+    };
+    if variation == 0 && !word.is_empty() && !word.starts_with('$') {
+        if is_result(word) {
+            game.result = word.to_string();
+            games.push(std::mem::take(game));
+        }
+        else {
+            game.moves.push(word.to_string());
+        }
+    }
+    token.clear();
+}
+
+//Every game of a PGN text. Comments, variations and annotations are skipped
+pub fn parse_pgn(text : &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut chars = text.chars();
+    let mut variation = 0;
+    let mut token = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if variation == 0 && token.is_empty() => {
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                let line : String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = line.split_once(' ') {
+                    game.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+                }
+            },
+            '{' => {
+                finish_token(&mut token, &mut game, &mut games, variation);
+                chars.by_ref().find(|&c| c == '}');
+            },
+            ';' => {
+                finish_token(&mut token, &mut game, &mut games, variation);
+                chars.by_ref().find(|&c| c == '\n');
+            },
+            '(' => {
+                finish_token(&mut token, &mut game, &mut games, variation);
+                variation += 1;
+            },
+            ')' => {
+                finish_token(&mut token, &mut game, &mut games, variation);
+                variation = variation.saturating_sub(1);
+            },
+            c if c.is_whitespace() => finish_token(&mut token, &mut game, &mut games, variation),
+            c => token.push(c),
+        }
+    }
+    finish_token(&mut token, &mut game, &mut games, variation);
+    if !game.moves.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME : &str = r#"[Event "Casual game"]
+[White "Anderssen"]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1 b5?! {Bryan's countergambit}5. Bxb5 Nf6
+6. Nf3 Qh6 7. d3 Nh5 8. Nh4 $1 Qg5 (8... g6 9. Nf5 (9. Qg4) gxf5) 9. Nf5 c6
+10. g4 Nf6 11. Rg1!{a famous sacrifice} cxb5 12. h4 Qg6 13. h5 Qg5 14. Qf3 Ng8
+15. Bxf4 Qf6 16. Nc3 Bc5 17. Nd5 Qxb2 18. Bd6 Bxg1; the second rook
+19. e5 Qxa1+ 20. Ke2 Na6 21. Nxg7+ Kd8 22. Qf6+ Nxf6 23. Be7# 1-0
+
+[Event "Second"]
+[Result "1/2-1/2"]
+
+1.d4{no space}d5 2.c4 e6 1/2-1/2
+"#;
+
+    #[test]
+    fn comments_variations_and_annotations() {
+        let games = parse_pgn(GAME);
+        assert_eq!(games.len(), 2);
+        let immortal = &games[0];
+        assert_eq!(immortal.tag("White"), Some("Anderssen"));
+        assert_eq!(immortal.result, "1-0");
+        assert_eq!(immortal.moves.len(), 45);
+        assert_eq!(&immortal.moves[6..10], ["Kf1", "b5?!", "Bxb5", "Nf6"]);
+        assert_eq!(&immortal.moves[14..18], ["Nh4", "Qg5", "Nf5", "c6"]);
+        assert_eq!(immortal.moves[20], "Rg1!");
+        assert_eq!(&immortal.moves[34..37], ["Bd6", "Bxg1", "e5"]);
+        let played = immortal.replay(usize::MAX).unwrap();
+        assert_eq!(played.len(), 45);
+        assert_eq!(games[1].moves, ["d4", "d5", "c4", "e6"]);
+        assert_eq!(games[1].result, "1/2-1/2");
+    }
+
+    #[test]
+    fn to_pgn_reads_back() {
+        let games = parse_pgn(GAME);
+        let again = parse_pgn(&games[0].to_pgn());
+        assert_eq!(again.len(), 1);
+        assert_eq!(again[0].moves, games[0].moves);
+        assert_eq!(again[0].tags, games[0].tags);
+    }
+}
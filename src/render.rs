@@ -0,0 +1,129 @@
+use std::fmt;
+use crate::{Game, piece_char};
+use crate::bitboard::Bitboard;
+use crate::square::Square;
+
+const LIGHT_SQUARE : &str = "\x1b[48;5;223m";
+const DARK_SQUARE : &str = "\x1b[48;5;137m";
+const HIGHLIGHT_LIGHT : &str = "\x1b[48;5;150m";
+const HIGHLIGHT_DARK : &str = "\x1b[48;5;107m";
+const PIECE_COLOR : &str = "\x1b[38;5;16m";
+const RESET : &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    //Chess glyphs (♔♕♖...) instead of letters
+    pub unicode : bool,
+    //ANSI background colours for light and dark squares
+    pub color : bool,
+    //Black at the bottom
+    pub flipped : bool,
+    //Squares to highlight, e.g. the last move or the legal destinations of a piece
+    pub highlight : u64,
+}
+
+pub fn piece_glyph(c : char) -> char {
+    match c {
+        'K' => '♔', 'Q' => '♕', 'R' => '♖', 'B' => '♗', 'N' => '♘', 'P' => '♙',
+        'k' => '♚', 'q' => '♛', 'r' => '♜', 'b' => '♝', 'n' => '♞', 'p' => '♟',
+        _ => c,
+    }
+}
+
+//Draw an 8x8 grid where `square` gives the character standing on each square, a8 in the top left corner
+fn render_grid(options : &RenderOptions, square : impl Fn(Square) -> char) -> String {
+    let files : Vec<u8> = if options.flipped { (0..8).rev().collect() } else { (0..8).collect() };
+    let ranks : Vec<u8> = if options.flipped { (0..8).collect() } else { (0..8).rev().collect() };
+    let mut out = String::new();
+    if options.color {
+        for r in &ranks {
+            out.push_str(&format!(" {} ", r + 1));
+            for f in &files {
+                let sq = Square::from_coords(*f, *r);
+                let light = (r + f) % 2 == 1;
+                let highlighted = options.highlight & sq.bit() != 0;
+                out.push_str(match (light, highlighted) {
+                    (true, false) => LIGHT_SQUARE,
+                    (false, false) => DARK_SQUARE,
+                    (true, true) => HIGHLIGHT_LIGHT,
+                    (false, true) => HIGHLIGHT_DARK,
+                });
+                let c = square(sq);
+                let c = if options.unicode { piece_glyph(c) } else { c };
+                out.push_str(&format!("{PIECE_COLOR} {c} "));
+            }
+            out.push_str(RESET);
+            out.push('\n');
+        }
+        out.push_str("   ");
+        for f in &files {
+            out.push_str(&format!(" {} ", (b'a' + *f) as char));
+        }
+        out.push('\n');
+    }
+    else {
+        out.push_str("     ");
+        for f in &files {
+            out.push_str(&format!("  {} ", (b'a' + *f) as char));
+        }
+        out.push('\n');
+        for r in &ranks {
+            out.push_str("     ---------------------------------\n");
+            out.push_str(&format!("   {} ", r + 1));
+            for f in &files {
+                let sq = Square::from_coords(*f, *r);
+                let c = square(sq);
+                let c = if options.unicode { piece_glyph(c) } else { c };
+                if options.highlight & sq.bit() != 0 {
+                    out.push_str(&format!("|[{c}]"));
+                }
+                else {
+                    out.push_str(&format!("| {c} "));
+                }
+            }
+            out.push_str("|\n");
+        }
+        out.push_str("     ---------------------------------\n");
+    }
+    out
+}
+
+pub fn render(game : &Game, options : &RenderOptions) -> String {
+    render_grid(options, |sq| piece_char(game, sq))
+}
+
+pub fn render_bitboard(bitboard : u64, options : &RenderOptions) -> String {
+    render_grid(options, |sq| if bitboard & sq.bit() != 0 { 'x' } else { '.' })
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", render(self, &RenderOptions::default()))
+    }
+}
+
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", render_bitboard(self.0, &RenderOptions::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::check_snapshot;
+
+    #[test]
+    fn start_position() {
+        check_snapshot("render/start.txt", &Game::default().to_string());
+    }
+
+    #[test]
+    fn black_to_move_flipped() {
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let options = RenderOptions { unicode : true, color : true, flipped : true, highlight : Square::E2.bit() | Square::E4.bit() };
+        check_snapshot("render/flipped.txt", &render(&game, &options));
+        let options = RenderOptions { flipped : true, ..RenderOptions::default() };
+        check_snapshot("render/flipped_bitboard.txt", &render_bitboard(game.black(), &options));
+    }
+}
@@ -0,0 +1,566 @@
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+use crate::{Game, Color, ColoredPiece, Instant, legal_moves, perft, is_attacked, possibility};
+use crate::book::{Book, BookSelection};
+use crate::syzygy::Tablebases;
+use crate::endgame::{Dtm, Endgames};
+use crate::builder::PositionBuilder;
+use crate::fen::parse_castling;
+use crate::square::Square;
+use crate::render::{render, render_bitboard, RenderOptions};
+use crate::moves::{parse_move, Move, MoveParseError};
+use crate::eval::evaluate_white;
+use crate::san::{move_to_san, parse_san};
+use crate::mate::find_mate;
+use crate::search::{search, uci_score, SearchLimits, SearchOptions, TT_MEGABYTES};
+use crate::time::TimeManager;
+use crate::tt::TranspositionTable;
+use crate::chess960::chess960_position;
+use crate::svg::{render_svg, SvgOptions};
+use crate::uci;
+use crate::zobrist::polyglot_key;
+
+const HELP : &str = "commands :
+  new                 start a new game
+  new960 <n>          start the Chess960 position number n, 518 is the standard one
+  fen <fen>           set up a position (without argument, print the current FEN)
+  move <uci|san>      play a move, e.g. 'move e2e4' or 'move Nf3'
+  undo                take back the last move
+  moves [square]      list the legal moves, or highlight the destinations of one piece
+  attacks             show the squares attacked by the side to move
+  board               draw the board
+  flip                draw the board from the other side
+  unicode             toggle chess glyphs
+  color               toggle coloured squares
+  svg <file>          save the board as an SVG diagram, with the last move drawn as an arrow
+  eval                static evaluation of the position
+  go depth <n>        search the best move
+  go movetime <ms>    search the best move for a given time
+  mate <n>            look for a forced mate in n moves
+  book [file]         open a Polyglot book, list the book moves of the position
+  bookmove            play the best book move
+  syzygy <dirs>       use the Syzygy tablebases of the directories
+  probe               WDL and DTZ of the position and of its moves in the tablebases
+  solve <KQK|...>     solve small endgames in memory by retrograde analysis
+  dtm                 distance to mate of the position and of its moves in the solved endgames
+  perft <n>           count the leaf nodes at depth n
+  perftsuite <file> [depth]
+                      check perft against an EPD file of '<fen> ;D1 <n> ;D2 <n>...' lines
+  uci                 switch to the UCI protocol
+  edit                edit the position, then :
+    put <square> <piece>  place a piece, uppercase for white, e.g. 'put e1 K'
+    remove <square>       empty a square
+    clear                 empty the board
+    side <w|b>            set the side to move
+    castling <KQkq|->     set the castling rights
+    ep <square|->         set the en passant square
+    clock <half> <full>   set the halfmove clock and the fullmove number
+    done                  check the position and play from it
+    cancel                drop the changes
+  quit                leave";
+
+pub struct Repl {
+    game : Game,
+    history : Vec<(Game, Move)>,
+    options : RenderOptions,
+    editor : Option<PositionBuilder>,
+    book : Option<Book>,
+    tablebases : Option<Tablebases>,
+    endgames : Option<Endgames>,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl { game : Game::default(), history : Vec::new(), options : RenderOptions::default(), editor : None, book : None, tablebases : None, endgames : None }
+    }
+
+    fn show(&self) {
+        self.show_highlighted(self.last_move());
+    }
+
+    //The squares of the last move played
+    fn last_move(&self) -> u64 {
+        match self.history.last() {
+            Some((_, last)) => last.from.bit() | last.to.bit(),
+            None => 0,
+        }
+    }
+
+    fn show_highlighted(&self, highlight : u64) {
+        if let Some(editor) = &self.editor {
+            print!("{}", render(editor.board(), &RenderOptions { highlight, ..self.options }));
+            println!("editing, {} to play, type 'done' to finish", if editor.board().side_to_move == Color::WHITE { "WHITE" } else { "BLACK" });
+            return;
+        }
+        print!("{}", render(&self.game, &RenderOptions { highlight, ..self.options }));
+        if is_attacked(self.game.side_to_move, &self.game) {
+            if legal_moves(&self.game).is_empty() { println!("CHECK MATE"); }
+            else { println!("CHECK"); }
+        }
+        else if legal_moves(&self.game).is_empty() {
+            println!("STALEMATE");
+        }
+        println!("{} to play", if self.game.side_to_move == Color::WHITE { "WHITE" } else { "BLACK" });
+    }
+
+    fn play(&mut self, input : &str) -> Result<(), String> {
+        let chessmove = match parse_move(input, &self.game) {
+            Ok(chessmove) => chessmove,
+            Err(MoveParseError::IllegalMove(_)) => {
+                return Err(format!("'{input}' is not a legal move here, type 'moves' to list them"));
+            },
+            Err(MoveParseError::BadSyntax(_)) => parse_san(&self.game, input)
+                .ok_or(format!("'{input}' is not a legal move in UCI or SAN notation"))?,
+            Err(e) => return Err(e.to_string()),
+        };
+        self.history.push((self.game, chessmove));
+        self.game.play(chessmove);
+        Ok(())
+    }
+
+    //Commands of the board editor, return false if `command` is not one of them
+    fn edit(&mut self, command : &str, args : &[&str]) -> Result<bool, String> {
+        let Some(editor) = self.editor.as_mut() else {
+            return Ok(false);
+        };
+        let square = |i : usize| -> Result<Square, String> {
+            let arg = args.get(i).ok_or(format!("usage : {command} <square>"))?;
+            arg.parse().map_err(|_| format!("'{arg}' is not a square"))
+        };
+        match command {
+            "put" => {
+                let letter = args.get(1).and_then(|p| p.chars().next()).ok_or("usage : put <square> <piece>")?;
+                let piece = ColoredPiece::from_char(letter).ok_or(format!("unknown piece '{letter}'"))?;
+                editor.put(square(0)?, piece);
+            },
+            "remove" => { editor.remove(square(0)?); },
+            "clear" => { editor.clear(); },
+            "side" => {
+                let color = match args.first() {
+                    Some(&"w") => Color::WHITE,
+                    Some(&"b") => Color::BLACK,
+                    _ => return Err("usage : side <w|b>".to_string()),
+                };
+                editor.side_to_move(color);
+            },
+            "castling" => { editor.castling(parse_castling(args.first().ok_or("usage : castling <KQkq|->")?)?); },
+            "ep" if args.first() == Some(&"-") => { editor.en_passant(None); },
+            "ep" => { editor.en_passant(Some(square(0)?)); },
+            "clock" => {
+                let [halfmove, fullmove] = args else {
+                    return Err("usage : clock <halfmove> <fullmove>".to_string());
+                };
+                editor.halfmove(halfmove.parse().map_err(|_| format!("invalid halfmove clock '{halfmove}'"))?);
+                editor.fullmove(fullmove.parse().map_err(|_| format!("invalid fullmove number '{fullmove}'"))?);
+            },
+            "done" => {
+                self.game = editor.build().map_err(|errors| {
+                    errors.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+                })?;
+                self.editor = None;
+                self.history.clear();
+            },
+            "cancel" => self.editor = None,
+            "fen" if args.is_empty() => return Ok(false),
+            //The game only changes once the edited position is done
+            "edit" | "new" | "new960" | "fen" | "move" | "m" | "undo" | "bookmove" => {
+                return Err(format!("'{command}' is not available while editing, type 'done' or 'cancel' first"));
+            },
+            _ => return Ok(false),
+        }
+        self.show();
+        Ok(true)
+    }
+
+    //Execute one command line, return false when the session is over
+    pub fn execute(&mut self, line : &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let args : Vec<&str> = words.collect();
+        if self.edit(command, &args)? {
+            return Ok(true);
+        }
+        match command {
+            "edit" => {
+                self.editor = Some(PositionBuilder::from_game(&self.game));
+                self.show();
+            },
+            "new" => {
+                self.game = Game::default();
+                self.history.clear();
+                self.show();
+            },
+            "new960" => {
+                let index = args.first().ok_or("usage : new960 <n>")?;
+                self.game = index.parse().ok().and_then(chess960_position)
+                    .ok_or(format!("'{index}' is not a Chess960 position, expected 0 to 959"))?;
+                self.history.clear();
+                self.show();
+            },
+            "fen" if args.is_empty() => println!("{}", self.game.to_fen()),
+            "fen" => {
+                self.game = Game::from_fen(&args.join(" "))?;
+                self.history.clear();
+                self.show();
+            },
+            "move" | "m" => {
+                let input = args.first().ok_or("usage : move <uci|san>")?;
+                self.play(input)?;
+                self.show();
+            },
+            "undo" => {
+                (self.game, _) = self.history.pop().ok_or("no move to take back")?;
+                self.show();
+            },
+            "moves" if !args.is_empty() => {
+                let from : Square = args[0].parse().map_err(|_| format!("'{}' is not a square", args[0]))?;
+                let destinations = legal_moves(&self.game).iter()
+                    .filter(|(m, _)| m.from == from)
+                    .fold(0, |acc, (m, _)| acc | m.to.bit());
+                self.show_highlighted(destinations | from.bit());
+            },
+            "moves" => {
+                let moves : Vec<String> = legal_moves(&self.game).into_iter()
+                    .map(|(m, _)| format!("{} ({})", m.to_uci(&self.game), move_to_san(&self.game, m)))
+                    .collect();
+                println!("{} legal moves : {}", moves.len(), moves.join(" "));
+            },
+            "board" | "d" => self.show(),
+            "attacks" => {
+                let attacks = possibility(self.game.side_to_move, &self.game);
+                print!("{}", render_bitboard(attacks, &self.options));
+                println!("{} squares", attacks.count_ones());
+            },
+            "flip" => {
+                self.options.flipped ^= true;
+                self.show();
+            },
+            "unicode" => {
+                self.options.unicode ^= true;
+                self.show();
+            },
+            "color" | "colour" => {
+                self.options.color ^= true;
+                self.show();
+            },
+            "svg" => {
+                let path = args.first().ok_or("usage : svg <file>")?;
+                let options = SvgOptions {
+                    flipped : self.options.flipped,
+                    arrows : self.history.last().map(|(_, m)| vec![(m.from, m.to)]).unwrap_or_default(),
+                    ..SvgOptions::default()
+                };
+                std::fs::write(path, render_svg(&self.game, &options)).map_err(|e| format!("cannot write {path} : {e}"))?;
+                println!("saved {path}");
+            },
+            "eval" => println!("eval : {} cp (white's point of view)", evaluate_white(&self.game)),
+            "go" => {
+                let limits = match args.as_slice() {
+                    [] => SearchLimits::depth(4),
+                    ["depth", n] => SearchLimits::depth(n.parse().map_err(|_| format!("invalid depth '{n}'"))?),
+                    ["movetime", n] => {
+                        let movetime = n.parse().map_err(|_| format!("invalid time '{n}'"))?;
+                        SearchLimits::time(TimeManager::fixed(Duration::from_millis(movetime), Duration::ZERO))
+                    },
+                    _ => return Err("usage : go [depth <n> | movetime <ms>]".to_string()),
+                };
+                let now = Instant::now();
+                let tt = TranspositionTable::new(TT_MEGABYTES);
+                let history : Vec<u64> = self.history.iter().map(|(game, _)| polyglot_key(game)).collect();
+                let result = search(&self.game, &history, &limits, &tt, self.tablebases.as_ref(), &SearchOptions::default(), &mut |_| {});
+                let score = uci_score(result.score);
+                match result.best_move {
+                    Some(m) => println!("bestmove {} ({}) score {score} nodes {} time {} ms",
+                        m.to_uci(&self.game), move_to_san(&self.game, m), result.nodes, now.elapsed().as_millis()),
+                    None => println!("no legal move, score {score}"),
+                }
+            },
+            "mate" => {
+                let moves : u32 = args.first().ok_or("usage : mate <n>")?
+                    .parse().map_err(|_| "invalid number of moves".to_string())?;
+                let now = Instant::now();
+                match find_mate(&self.game, moves) {
+                    Some(line) => {
+                        let mut game = self.game;
+                        let sans : Vec<String> = line.iter().map(|&m| {
+                            let san = move_to_san(&game, m);
+                            game.play(m);
+                            san
+                        }).collect();
+                        println!("mate in {} : {} ({} ms)", line.len().div_ceil(2), sans.join(" "), now.elapsed().as_millis());
+                    },
+                    None => println!("no mate in {moves} ({} ms)", now.elapsed().as_millis()),
+                }
+            },
+            "perft" => {
+                let depth : u32 = args.first().ok_or("usage : perft <n>")?
+                    .parse().map_err(|_| "invalid depth".to_string())?;
+                let now = Instant::now();
+                let nodes = perft(&self.game, depth);
+                println!("perft {depth} : {nodes} nodes in {} ms", now.elapsed().as_millis());
+            },
+            "book" => {
+                if let Some(path) = args.first() {
+                    self.book = Some(Book::open(path).map_err(|e| format!("cannot open {path} : {e}"))?);
+                }
+                let book = self.book.as_ref().ok_or("no book, usage : book <file>")?;
+                let moves = book.moves(&self.game).map_err(|e| e.to_string())?;
+                let total : u32 = moves.iter().map(|&(_, weight)| u32::from(weight)).sum();
+                println!("key {:016x}, {} book moves", polyglot_key(&self.game), moves.len());
+                for (m, weight) in moves {
+                    println!("  {:<6} {:<6} weight {weight} ({:.1}%)", m.to_uci(&self.game), move_to_san(&self.game, m),
+                        100.0 * f64::from(weight) / f64::from(total.max(1)));
+                }
+            },
+            "bookmove" => {
+                let book = self.book.as_ref().ok_or("no book, usage : book <file>")?;
+                let m = book.pick(&self.game, BookSelection::Best).ok_or("the position is not in the book")?;
+                self.play(&m.to_string())?;
+                self.show();
+            },
+            "syzygy" => {
+                let paths = args.first().ok_or("usage : syzygy <directories>")?;
+                let tablebases = Tablebases::open(paths);
+                println!("{} WDL and {} DTZ tables, up to {} pieces", tablebases.wdl_count(), tablebases.dtz_count(), tablebases.max_pieces);
+                self.tablebases = Some(tablebases).filter(|tb| tb.wdl_count() > 0);
+            },
+            "probe" => {
+                let tablebases = self.tablebases.as_ref().ok_or("no tablebases, usage : syzygy <directories>")?;
+                let wdl = tablebases.probe_wdl(&self.game).ok_or("the position is not in the tablebases")?;
+                match tablebases.probe_dtz(&self.game) {
+                    Some(dtz) => println!("{wdl:?}, dtz {dtz}"),
+                    None => println!("{wdl:?}, no DTZ table"),
+                }
+                for (m, wdl, dtz) in tablebases.root_moves(&self.game).unwrap_or_default() {
+                    println!("  {:<6} {:<6} {wdl:?} dtz {dtz}", m.to_uci(&self.game), move_to_san(&self.game, m));
+                }
+            },
+            "solve" => {
+                let now = Instant::now();
+                let endgames = Endgames::solve(&args)?;
+                let names : Vec<&str> = endgames.tables().iter().map(|table| table.name).collect();
+                println!("{} solved in {} ms", names.join(", "), now.elapsed().as_millis());
+                self.endgames = Some(endgames);
+            },
+            "dtm" => {
+                let endgames = self.endgames.as_ref().ok_or("no endgame solved, usage : solve <KQK|KRK|KPK|KBNK>")?;
+                let dtm = endgames.probe(&self.game).ok_or("the position is not in the solved endgames")?;
+                println!("{}", describe_dtm(dtm));
+                for (m, _) in legal_moves(&self.game) {
+                    let mut next = self.game;
+                    next.play(m);
+                    //Leaving the tables only happens by taking the last piece, a draw
+                    let after = match endgames.probe(&next).unwrap_or(Dtm::Draw) {
+                        Dtm::Win(plies) => Dtm::Loss(plies + 1),
+                        Dtm::Loss(plies) => Dtm::Win(plies + 1),
+                        Dtm::Draw => Dtm::Draw,
+                    };
+                    println!("  {:<6} {:<6} {}", m.to_uci(&self.game), move_to_san(&self.game, m), describe_dtm(after));
+                }
+            },
+            "perftsuite" => {
+                let path = args.first().ok_or("usage : perftsuite <file> [depth]")?;
+                let max_depth : u32 = match args.get(1) {
+                    Some(depth) => depth.parse().map_err(|_| format!("invalid depth '{depth}'"))?,
+                    None => u32::MAX,
+                };
+                let suite = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path} : {e}"))?;
+                let now = Instant::now();
+                let (passed, failures) = perft_suite(&suite, max_depth)?;
+                for failure in &failures {
+                    println!("FAILED {failure}");
+                }
+                println!("{passed} passed, {} failed in {} ms", failures.len(), now.elapsed().as_millis());
+            },
+            "help" => println!("{HELP}"),
+            "quit" | "exit" => return Ok(false),
+            _ => return Err(format!("unknown command '{command}', type 'help' for the list")),
+        }
+        Ok(true)
+    }
+}
+
+fn describe_dtm(dtm : Dtm) -> String {
+    match dtm {
+        Dtm::Win(plies) => format!("mate in {} ({plies} plies)", plies.div_ceil(2)),
+        Dtm::Loss(0) => "checkmated".to_string(),
+        Dtm::Loss(plies) => format!("mated in {} ({plies} plies)", plies / 2),
+        Dtm::Draw => "draw".to_string(),
+    }
+}
+
+//Run every '<fen> ;D<depth> <nodes>' line of an EPD perft suite up to `max_depth`,
+//return the number of checks passed and a message for each failure
+fn perft_suite(suite : &str, max_depth : u32) -> Result<(u32, Vec<String>), String> {
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    for line in suite.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap_or_default().trim();
+        let game = Game::from_fen(fen).map_err(|e| format!("{fen} : {e}"))?;
+        for field in fields {
+            let (depth, expected) = field.trim().trim_start_matches('D').split_once(' ')
+                .and_then(|(d, n)| Some((d.parse::<u32>().ok()?, n.trim().parse::<u64>().ok()?)))
+                .ok_or(format!("invalid perft field '{field}'"))?;
+            if depth > max_depth {
+                continue;
+            }
+            let nodes = perft(&game, depth);
+            if nodes == expected {
+                passed += 1;
+            }
+            else {
+                failures.push(format!("{fen} depth {depth} : {nodes} nodes instead of {expected}"));
+            }
+        }
+    }
+    Ok((passed, failures))
+}
+
+pub fn run() {
+    let stdin = io::stdin();
+    let read_line = || {
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    };
+    //Nothing is printed before the first line, a GUI starting us without arguments must only see the answer to its uci
+    let Some(mut line) = read_line() else {
+        return;
+    };
+    let mut repl = Repl::new();
+    if line.trim() != "uci" {
+        repl.show();
+    }
+    loop {
+        if line.trim() == "uci" {
+            uci::run("uci");
+            break;
+        }
+        match repl.execute(line.trim()) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(e) => println!("error : {e}"),
+        }
+        print!("> ");
+        io::stdout().flush().ok();
+        match read_line() {
+            Some(next) => line = next,
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_chess960_suite(max_depth : u32) {
+        let suite = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/data/perft960.epd")).unwrap();
+        let (passed, failures) = perft_suite(&suite, max_depth).unwrap();
+        assert!(failures.is_empty(), "{failures:#?}");
+        assert_eq!(passed, 7 * max_depth.min(5));
+    }
+
+    #[test]
+    fn chess960_perft_suite() {
+        run_chess960_suite(3);
+    }
+
+    #[test]
+    fn moves_and_undo() {
+        let mut repl = Repl::new();
+        for line in ["move e2e4", "m e5", "move Nf3", "", "board"] {
+            assert_eq!(repl.execute(line), Ok(true), "{line}");
+        }
+        assert_eq!(repl.game.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert_eq!(repl.last_move(), Square::G1.bit() | Square::F3.bit());
+        repl.execute("undo").unwrap();
+        repl.execute("undo").unwrap();
+        assert_eq!(repl.game.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        repl.execute("fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert!(repl.history.is_empty());
+        repl.execute("move e1g1").unwrap();
+        assert_eq!(repl.game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        repl.execute("new").unwrap();
+        assert_eq!((repl.game.to_fen(), repl.history.len()), (Game::default().to_fen(), 0));
+        assert_eq!(repl.execute("quit"), Ok(false));
+    }
+
+    #[test]
+    fn command_errors() {
+        let mut repl = Repl::new();
+        let errors = [
+            ("castle", "unknown command 'castle', type 'help' for the list"),
+            ("undo", "no move to take back"),
+            ("move", "usage : move <uci|san>"),
+            ("move e2e5", "'e2e5' is not a legal move here, type 'moves' to list them"),
+            ("move Ke2", "'Ke2' is not a legal move in UCI or SAN notation"),
+            ("move e7e8k", "'k' is not a promotion piece, expected q, r, b or n"),
+            ("go nodes 5", "usage : go [depth <n> | movetime <ms>]"),
+            ("new960 960", "'960' is not a Chess960 position, expected 0 to 959"),
+            ("moves z9", "'z9' is not a square"),
+            ("put e4 Q", "unknown command 'put', type 'help' for the list"),
+        ];
+        for (line, error) in errors {
+            assert_eq!(repl.execute(line), Err(error.to_string()), "{line}");
+        }
+        assert!(repl.execute("fen 8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        //Nothing was played
+        assert_eq!((repl.game.to_fen(), repl.history.len()), (Game::default().to_fen(), 0));
+    }
+
+    #[test]
+    fn board_editor() {
+        let mut repl = Repl::new();
+        repl.execute("edit").unwrap();
+        for line in ["clear", "put e1 K", "put e8 k", "put a7 P", "side b"] {
+            assert_eq!(repl.execute(line), Ok(true), "{line}");
+        }
+        assert_eq!(repl.execute("put e9 Q"), Err("'e9' is not a square".to_string()));
+        assert_eq!(repl.execute("put d4 X"), Err("unknown piece 'X'".to_string()));
+        //The editor keeps the refused position
+        repl.execute("put a8 P").unwrap();
+        assert_eq!(repl.execute("done"), Err("pawn on the back rank at a8".to_string()));
+        assert!(repl.editor.is_some());
+        repl.execute("remove a8").unwrap();
+        repl.execute("done").unwrap();
+        assert!(repl.editor.is_none());
+        assert_eq!(repl.game.to_fen(), "4k3/P7/8/8/8/8/8/4K3 b - - 0 1");
+        //Cancel leaves the game as it was
+        repl.execute("edit").unwrap();
+        repl.execute("clear").unwrap();
+        repl.execute("cancel").unwrap();
+        assert_eq!(repl.game.to_fen(), "4k3/P7/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn editing_keeps_the_game() {
+        let mut repl = Repl::new();
+        repl.execute("move e2e4").unwrap();
+        repl.execute("edit").unwrap();
+        repl.execute("clear").unwrap();
+        for line in ["move e7e5", "m e5", "undo", "bookmove", "new", "new960 518", "fen 4k3/8/8/8/8/8/8/4K3 w - - 0 1", "edit"] {
+            let command = line.split_whitespace().next().unwrap();
+            let error = format!("'{command}' is not available while editing, type 'done' or 'cancel' first");
+            assert_eq!(repl.execute(line), Err(error), "{line}");
+        }
+        //Reading the game is still allowed
+        assert_eq!(repl.execute("fen"), Ok(true));
+        assert_eq!((repl.game.to_fen(), repl.history.len()), ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string(), 1));
+        repl.execute("cancel").unwrap();
+        repl.execute("undo").unwrap();
+        assert_eq!(repl.game.to_fen(), Game::default().to_fen());
+    }
+
+    //cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn chess960_perft_suite_deep() {
+        run_chess960_suite(5);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{Game, Piece, is_attacked, legal_moves};
+use crate::moves::Move;
+use crate::square::Square;
+
+fn piece_letter(piece : Piece) -> &'static str {
+    match piece {
+        Piece::KNIGHT => "N",
+        Piece::BISHOP => "B",
+        Piece::ROOK => "R",
+        Piece::QUEEN => "Q",
+        Piece::KING => "K",
+        _ => "",
+    }
+}
+
+//Standard algebraic notation of a legal move, e.g. "Nbd7", "exd5", "O-O", "e8=Q+"
+pub fn move_to_san(game : &Game, chessmove : Move) -> String {
+    let moves = legal_moves(game);
+    let Some(&(_, piece)) = moves.iter().find(|(m, _)| *m == chessmove) else {
+        return String::from("??");
+    };
+    let Move { from : a, to : b, promotion } = chessmove;
+    let mut san = String::new();
+    if chessmove.is_castling(game) {
+        san.push_str(if b > a { "O-O" } else { "O-O-O" });
+    }
+    else {
+        let capture = game.occupied() & b.bit() != 0 || (piece == Piece::PAWN && a.file() != b.file());
+        san.push_str(piece_letter(piece));
+        if piece == Piece::PAWN {
+            if capture {
+                san.push((b'a' + a.file()) as char);
+            }
+        }
+        else {
+            let others : Vec<_> = moves.iter()
+                .filter(|(m, p)| *p == piece && m.to == b && m.from != a)
+                .map(|(m, _)| m.from)
+                .collect();
+            if !others.is_empty() {
+                let from = a.to_string();
+                if others.iter().all(|o| o.file() != a.file()) {
+                    san.push_str(&from[0..1]);
+                }
+                else if others.iter().all(|o| o.rank() != a.rank()) {
+                    san.push_str(&from[1..2]);
+                }
+                else {
+                    san.push_str(&from);
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&b.to_string());
+        if promotion != Piece::NONE {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+    }
+    let mut next = *game;
+    next.play(chessmove);
+    if is_attacked(next.side_to_move, &next) {
+        san.push(if legal_moves(&next).is_empty() { '#' } else { '+' });
+    }
+    san
+}
+
+//A move in SAN, loosely : "e8Q" for "e8=Q", "0-0" for "O-O", checks and annotations may be missing
+//or wrong, captures may lack their 'x', a piece may be disambiguated when it needs not be
+pub fn parse_san(game : &Game, san : &str) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']).trim_end_matches("e.p.").trim();
+    let moves = legal_moves(game);
+    let castling = san.replace('0', "O");
+    if castling == "O-O" || castling == "O-O-O" {
+        return moves.into_iter().map(|(m, _)| m)
+            .find(|m| m.is_castling(game) && (m.to > m.from) == (castling == "O-O"));
+    }
+    let chars : Vec<char> = san.chars().filter(|c| !matches!(c, 'x' | ':' | '-' | '=')).collect();
+    let (piece, mut rest) = match chars.first()? {
+        'N' => (Piece::KNIGHT, &chars[1..]),
+        'B' => (Piece::BISHOP, &chars[1..]),
+        'R' => (Piece::ROOK, &chars[1..]),
+        'Q' => (Piece::QUEEN, &chars[1..]),
+        'K' => (Piece::KING, &chars[1..]),
+        _ => (Piece::PAWN, &chars[..]),
+    };
+    //A promotion letter follows the rank of the target square
+    let mut promotion = Piece::NONE;
+    if let [front @ .., rank, letter] = rest {
+        if rank.is_ascii_digit() && letter.is_ascii_alphabetic() {
+            promotion = match letter.to_ascii_uppercase() {
+                'N' => Piece::KNIGHT,
+                'B' => Piece::BISHOP,
+                'R' => Piece::ROOK,
+                'Q' => Piece::QUEEN,
+                _ => return None,
+            };
+            rest = &rest[..front.len() + 1];
+        }
+    }
+    let [from @ .., file, rank] = rest else {
+        return None;
+    };
+    let to : Square = format!("{file}{rank}").parse().ok()?;
+    //What is left tells the file, the rank or the square the piece comes from
+    let from_file = from.iter().find(|c| ('a'..='h').contains(c)).map(|&c| c as u8 - b'a');
+    let from_rank = from.iter().find(|c| ('1'..='8').contains(c)).map(|&c| c as u8 - b'1');
+    if from.len() > 2 || from.len() != usize::from(from_file.is_some()) + usize::from(from_rank.is_some()) {
+        return None;
+    }
+    let mut found = moves.into_iter().filter(|&(m, p)| {
+        p == piece && m.to == to && m.promotion == promotion && !m.is_castling(game)
+            && from_file.is_none_or(|f| m.from.file() == f) && from_rank.is_none_or(|r| m.from.rank() == r)
+    });
+    let (chessmove, _) = found.next()?;
+    //Ambiguous
+    if found.next().is_some() {
+        return None;
+    }
+    Some(chessmove)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(fen : &str, san : &str) -> Option<String> {
+        let game = Game::from_fen(fen).unwrap();
+        parse_san(&game, san).map(|m| m.to_uci(&game))
+    }
+
+    #[test]
+    fn loose_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(parse(start, "e4"), Some("e2e4".to_string()));
+        assert_eq!(parse(start, "Nf3"), Some("g1f3".to_string()));
+        assert_eq!(parse(start, "Ngf3"), Some("g1f3".to_string()));
+        assert_eq!(parse(start, "Ng1f3"), Some("g1f3".to_string()));
+        assert_eq!(parse(start, "Nf3!?"), Some("g1f3".to_string()));
+        assert_eq!(parse(start, "e5"), None);
+        assert_eq!(parse(start, "Nd2"), None);
+        let promotion = "8/4P2k/8/8/8/8/8/K7 w - - 0 1";
+        for san in ["e8=Q", "e8Q", "e8=Q+", "e8q"] {
+            assert_eq!(parse(promotion, san), Some("e7e8q".to_string()), "{san}");
+        }
+        assert_eq!(parse(promotion, "e8N"), Some("e7e8n".to_string()));
+        assert_eq!(parse(promotion, "e8=B"), Some("e7e8b".to_string()));
+        assert_eq!(parse(promotion, "e8"), None);
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(parse(castling, "O-O"), Some("e1g1".to_string()));
+        assert_eq!(parse(castling, "0-0"), Some("e1g1".to_string()));
+        assert_eq!(parse(castling, "0-0-0+"), Some("e1c1".to_string()));
+        //Missing check sign, missing capture sign, en passant
+        assert_eq!(parse(castling, "Rxa8"), Some("a1a8".to_string()));
+        assert_eq!(parse(castling, "Ra8"), Some("a1a8".to_string()));
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        assert_eq!(parse(en_passant, "exd6"), Some("e5d6".to_string()));
+        assert_eq!(parse(en_passant, "exd6e.p."), Some("e5d6".to_string()));
+        assert_eq!(parse(en_passant, "ed6"), Some("e5d6".to_string()));
+        //Both rooks reach d1
+        let ambiguous = "4k3/8/8/8/8/8/8/R3K2R w - - 0 1";
+        assert_eq!(parse("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "Rd1"), None);
+        assert_eq!(parse("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "Rad1"), Some("a1d1".to_string()));
+        assert_eq!(parse(ambiguous, "Rf1"), Some("h1f1".to_string()));
+    }
+
+    #[test]
+    fn san_round_trips() {
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for (chessmove, _) in legal_moves(&game) {
+            assert_eq!(parse_san(&game, &move_to_san(&game, chessmove)), Some(chessmove));
+        }
+    }
+}
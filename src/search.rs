@@ -0,0 +1,641 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use crate::{Game, Color, Piece, Instant, legal_moves, is_attacked};
+use crate::moves::Move;
+use crate::eval::evaluate;
+use crate::movepick::{Heuristics, MovePicker, MAX_PLY, is_quiet};
+use crate::syzygy::{Tablebases, Wdl};
+use crate::time::TimeManager;
+use crate::tt::{Bound, TranspositionTable, TtEntry, score_from_tt, score_to_tt};
+use crate::zobrist::polyglot_key;
+
+pub const MATE : i32 = 30000;
+pub const INFINITY : i32 = 32000;
+//Won positions found in the tablebases, below the mate scores
+pub const TB_WIN : i32 = 20000;
+pub const TT_MEGABYTES : usize = 16;
+//Deepest iteration when only the time limits the search
+pub const MAX_DEPTH : u32 = 64;
+
+//Score as written in UCI, "cp 35" or "mate -3" in moves
+pub fn uci_score(score : i32) -> String {
+    if score.abs() > MATE - 1000 {
+        format!("mate {}", (MATE - score.abs() + 1) / 2 * score.signum())
+    }
+    else {
+        format!("cp {score}")
+    }
+}
+
+//Switches of the search, to measure each feature on its own
+#[derive(Clone)]
+pub struct SearchOptions {
+    //Hash move, captures by MVV-LVA and SEE, killers, countermoves and history
+    pub move_ordering : bool,
+    pub null_move : bool,
+    //Late move reductions
+    pub lmr : bool,
+    //Static null move : cut when the evaluation is far above beta
+    pub reverse_futility : bool,
+    //Skip the quiet moves when the evaluation is far below alpha
+    pub futility : bool,
+    //Drop into the quiescence search when the evaluation is far below alpha
+    pub razoring : bool,
+    pub check_extensions : bool,
+    //Lazy SMP : helper threads search the same tree, sharing the transposition table
+    pub threads : usize,
+    //Number of best lines searched, each root move is only in one of them
+    pub multi_pv : usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions { move_ordering : true, null_move : true, lmr : true, reverse_futility : true, futility : true, razoring : true, check_extensions : true, threads : 1, multi_pv : 1 }
+    }
+}
+
+impl SearchOptions {
+    //Name of each switch as a UCI option
+    pub const NAMES : [&'static str;7] = ["MoveOrdering", "NullMove", "LMR", "ReverseFutility", "Futility", "Razoring", "CheckExtensions"];
+
+    pub fn set(&mut self, name : &str, value : bool) -> Result<(), String> {
+        let switch = match name.to_ascii_lowercase().as_str() {
+            "moveordering" => &mut self.move_ordering,
+            "nullmove" => &mut self.null_move,
+            "lmr" => &mut self.lmr,
+            "reversefutility" => &mut self.reverse_futility,
+            "futility" => &mut self.futility,
+            "razoring" => &mut self.razoring,
+            "checkextensions" => &mut self.check_extensions,
+            _ => return Err(format!("unknown search option '{name}'")),
+        };
+        *switch = value;
+        Ok(())
+    }
+}
+
+//Iterations the helper threads skip, in cycles like Stockfish's : helper i skips depth d
+//when (d + SKIP_PHASE[i]) / SKIP_SIZE[i] is odd, so that the threads spread over the depths
+const SKIP_SIZE : [u32;20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE : [u32;20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+lazy_static! {
+    //Reduction of the late moves by depth and move number
+    static ref LMR_TABLE : [[u32;64];64] = {
+        let mut table = [[0;64];64];
+        for depth in 1u32..64 {
+            for count in 1u32..64 {
+                table[depth as usize][count as usize] = (0.75 + f64::from(depth).ln() * f64::from(count).ln() / 2.25) as u32;
+            }
+        }
+        table
+    };
+}
+
+//Without pieces other than pawns, passing may be the best move and the null move is unsafe
+fn has_non_pawn_material(game : &Game, color : Color) -> bool {
+    game.side(color) & !(game.pieces(color, Piece::PAWN) | game.pieces(color, Piece::KING)) != 0
+}
+
+//When to stop : the depth is always a limit, the time and the nodes only if given,
+//and the search also stops as soon as `stop` is raised
+pub struct SearchLimits {
+    pub depth : u32,
+    pub time : Option<TimeManager>,
+    pub nodes : Option<u64>,
+    pub stop : Arc<AtomicBool>,
+    //Raised while thinking on the opponent's time : the time is not looked at and the result
+    //waits until it is cleared (ponderhit) or the search is stopped
+    pub ponder : Arc<AtomicBool>,
+    //"go infinite" : the result waits for the search to be stopped, even past the last depth
+    pub infinite : bool,
+}
+
+impl SearchLimits {
+    pub fn depth(depth : u32) -> SearchLimits {
+        SearchLimits { depth, time : None, nodes : None, stop : Arc::new(AtomicBool::new(false)), ponder : Arc::new(AtomicBool::new(false)), infinite : false }
+    }
+
+    pub fn infinite() -> SearchLimits {
+        SearchLimits { infinite : true, ..SearchLimits::depth(MAX_DEPTH) }
+    }
+
+    pub fn pondering(&self) -> bool {
+        self.ponder.load(Ordering::Relaxed)
+    }
+
+    //The move cannot be sent while pondering or in an infinite search, until "stop" or "ponderhit"
+    pub fn wait_for_stop(&self) {
+        while (self.infinite || self.pondering()) && !self.stop.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn time(time : TimeManager) -> SearchLimits {
+        SearchLimits { time : Some(time), ..SearchLimits::depth(MAX_DEPTH) }
+    }
+}
+
+//A line of a multi-PV search
+#[derive(Clone)]
+pub struct PvLine {
+    pub score : i32,
+    pub pv : Vec<Move>,
+}
+
+pub struct SearchResult {
+    pub best_move : Option<Move>,
+    pub score : i32,
+    pub nodes : u64,
+    //Last iteration completed, or partly searched if it already changed the best move
+    pub depth : u32,
+    pub pv : Vec<Move>,
+    //The best lines sorted by score, the first one is the best move, score and pv above
+    pub lines : Vec<PvLine>,
+}
+
+struct Searcher<'a> {
+    options : &'a SearchOptions,
+    tablebases : Option<&'a Tablebases>,
+    limits : &'a SearchLimits,
+    tt : &'a TranspositionTable,
+    heuristics : Box<Heuristics>,
+    //Nodes of this thread, added to the count of all the threads every 1024 nodes
+    nodes : u64,
+    all_nodes : &'a AtomicU64,
+    //Only the main thread looks at the clock, the helpers stop with it
+    main : bool,
+    //Out of time or nodes, every score from then on is meaningless
+    stopped : bool,
+    //Keys of the game before the root and of the positions on the way to the current node,
+    //0 where a null move was played since a repetition cannot cross it
+    keys : Vec<u64>,
+}
+
+//Exact score of a position of the tablebases, right after a capture or a pawn move
+fn tablebase_score(game : &Game, ply : i32, tablebases : Option<&Tablebases>) -> Option<i32> {
+    let tablebases = tablebases?;
+    if game.halfmove != 0 || game.occupied().count_ones() > tablebases.max_pieces {
+        return None;
+    }
+    Some(match tablebases.probe_wdl(game)? {
+        Wdl::Win => TB_WIN - ply,
+        Wdl::Loss => -TB_WIN + ply,
+        Wdl::CursedWin => 1,
+        Wdl::BlessedLoss => -1,
+        Wdl::Draw => 0,
+    })
+}
+
+impl Searcher<'_> {
+    //The position was already reached since the last capture or pawn move
+    fn is_repetition(&self, key : u64, halfmove : u16) -> bool {
+        self.keys.iter().rev().take(halfmove as usize).take_while(|&&k| k != 0).any(|&k| k == key)
+    }
+
+    //Count a node, the clock is only looked at every 1024 nodes
+    fn visit(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024) {
+            let all_nodes = self.all_nodes.fetch_add(1024, Ordering::Relaxed) + 1024;
+            if self.main {
+                let out_of_time = !self.limits.pondering() && self.limits.time.as_ref().is_some_and(TimeManager::hard_limit_reached);
+                if out_of_time || self.limits.nodes.is_some_and(|nodes| all_nodes >= nodes) {
+                    self.limits.stop.store(true, Ordering::Relaxed);
+                }
+            }
+            self.stopped = self.limits.stop.load(Ordering::Relaxed);
+        }
+    }
+
+    //Nodes of all the threads so far
+    fn all_nodes(&self) -> u64 {
+        self.all_nodes.load(Ordering::Relaxed) + self.nodes % 1024
+    }
+
+    fn quiescence(&mut self, game : &Game, mut alpha : i32, beta : i32) -> i32 {
+        self.visit();
+        if self.stopped {
+            return 0;
+        }
+        let stand_pat = evaluate(game);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+        let mut picker = MovePicker::quiescence(self.options.move_ordering);
+        while let Some((chessmove, _)) = picker.next(game, &self.heuristics) {
+            let mut game1 = *game;
+            game1.play(chessmove);
+            let score = -self.quiescence(&game1, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    fn alpha_beta(&mut self, game : &Game, depth : u32, alpha : i32, beta : i32, ply : i32, previous : Option<Move>) -> i32 {
+        if self.stopped {
+            return 0;
+        }
+        if let Some(score) = tablebase_score(game, ply, self.tablebases) {
+            self.visit();
+            return score;
+        }
+        //Repeating is a draw, whoever can avoid it will
+        let key = polyglot_key(game);
+        if self.is_repetition(key, game.halfmove) {
+            return 0;
+        }
+        if depth == 0 {
+            return self.quiescence(game, alpha, beta);
+        }
+        self.visit();
+        if game.halfmove >= 100 && !legal_moves(game).is_empty() {
+            return 0;
+        }
+        self.keys.push(key);
+        let score = self.search_node(game, depth, alpha, beta, ply, previous);
+        self.keys.pop();
+        score
+    }
+
+    //The rest of alpha_beta, the key of `game` is the last one pushed
+    fn search_node(&mut self, game : &Game, depth : u32, mut alpha : i32, beta : i32, ply : i32, previous : Option<Move>) -> i32 {
+        let key = self.keys[self.keys.len() - 1];
+        let entry = self.tt.probe(key);
+        if let Some(entry) = entry.filter(|e| e.depth >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {},
+            }
+        }
+        let original_alpha = alpha;
+        let pv_node = beta - alpha > 1;
+        let in_check = is_attacked(game.side_to_move, game);
+        let options = self.options;
+        let eval = if in_check { -INFINITY } else { evaluate(game) };
+        if !pv_node && !in_check && beta.abs() < TB_WIN - 1000 {
+            if options.reverse_futility && depth <= 6 && eval - 100 * depth as i32 >= beta {
+                return eval;
+            }
+            if options.razoring && depth <= 2 && eval + 300 * depth as i32 <= alpha {
+                let score = self.quiescence(game, alpha, alpha + 1);
+                if score <= alpha {
+                    return score;
+                }
+            }
+            //Pass and see if the opponent still cannot reach beta, never twice in a row
+            if options.null_move && depth >= 3 && eval >= beta && previous.is_some() && has_non_pawn_material(game, game.side_to_move) {
+                let mut passed = *game;
+                passed.side_to_move = !passed.side_to_move;
+                passed.en_passant = 0;
+                passed.halfmove = passed.halfmove.saturating_add(1);
+                let reduction = 3 + depth / 4;
+                self.keys.push(0);
+                let score = -self.alpha_beta(&passed, depth.saturating_sub(1 + reduction), -beta, -beta + 1, ply + 1, None);
+                self.keys.pop();
+                if score >= beta {
+                    return if score >= TB_WIN - 1000 { beta } else { score };
+                }
+            }
+        }
+        let futile = options.futility && !pv_node && !in_check && depth <= 3 && eval + 100 + 150 * depth as i32 <= alpha;
+        let ordered = options.move_ordering;
+        let ply_index = ply as usize;
+        let (killers, counter_move) = if ordered {
+            (self.heuristics.killers(ply_index), self.heuristics.counter_move(previous))
+        }
+        else {
+            ([None;2], None)
+        };
+        let mut picker = MovePicker::new(entry.and_then(|e| e.best_move).filter(|_| ordered), killers, counter_move, ordered);
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut quiets_tried = Vec::new();
+        let mut count = 0;
+        while let Some((chessmove, _)) = picker.next(game, &self.heuristics) {
+            let mut game1 = *game;
+            game1.play(chessmove);
+            count += 1;
+            let quiet = is_quiet(game, chessmove);
+            let gives_check = is_attacked(game1.side_to_move, &game1);
+            if futile && quiet && !gives_check && best_move.is_some() {
+                continue;
+            }
+            let extension = u32::from(options.check_extensions && gives_check && ply_index < MAX_PLY / 2);
+            let new_depth = depth - 1 + extension;
+            //Late quiet moves are searched shallower with a null window first
+            let mut score = alpha + 1;
+            if options.lmr && depth >= 3 && count > if pv_node { 3 } else { 1 } && quiet && !in_check && !gives_check {
+                let reduction = LMR_TABLE[(depth as usize).min(63)][count.min(63)].min(new_depth - 1);
+                if reduction > 0 {
+                    score = -self.alpha_beta(&game1, new_depth - reduction, -alpha - 1, -alpha, ply + 1, Some(chessmove));
+                }
+            }
+            if score > alpha {
+                score = -self.alpha_beta(&game1, new_depth, -beta, -alpha, ply + 1, Some(chessmove));
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(chessmove);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if quiet && ordered {
+                    self.heuristics.update(game.side_to_move, chessmove, &quiets_tried, ply_index, depth, previous);
+                }
+                break;
+            }
+            if quiet {
+                quiets_tried.push(chessmove);
+            }
+        }
+        if self.stopped {
+            return 0;
+        }
+        if count == 0 {
+            return if in_check { -MATE + ply } else { 0 };
+        }
+        let bound = if best_score >= beta { Bound::Lower } else if best_score > original_alpha { Bound::Exact } else { Bound::Upper };
+        self.tt.store(TtEntry { key, best_move, score : score_to_tt(best_score, ply), depth, bound });
+        best_score
+    }
+}
+
+//The best moves stored in the table after `first`, as long as they are legal and do not repeat
+fn principal_variation(game : &Game, first : Move, tt : &TranspositionTable, length : u32) -> Vec<Move> {
+    let mut pv = vec![first];
+    let mut game = *game;
+    game.play(first);
+    let mut key = polyglot_key(&game);
+    let mut seen = vec![key];
+    while pv.len() < length as usize {
+        let Some(chessmove) = tt.probe(key).and_then(|entry| entry.best_move) else {
+            break;
+        };
+        if !legal_moves(&game).iter().any(|&(m, _)| m == chessmove) {
+            break;
+        }
+        game.play(chessmove);
+        key = polyglot_key(&game);
+        if seen.contains(&key) {
+            break;
+        }
+        seen.push(key);
+        pv.push(chessmove);
+    }
+    pv
+}
+
+impl Searcher<'_> {
+    //The best of the root moves with its score, `None` if stopped before the first move was searched
+    fn search_root(&mut self, game : &Game, moves : &[Move], depth : u32) -> Option<(usize, i32)> {
+        let mut alpha = -INFINITY;
+        let mut best = None;
+        for (i, &chessmove) in moves.iter().enumerate() {
+            let mut game1 = *game;
+            game1.play(chessmove);
+            let score = -self.alpha_beta(&game1, depth - 1, -INFINITY, -alpha, 1, Some(chessmove));
+            //Once stopped, only the moves searched to the end count
+            if self.stopped {
+                break;
+            }
+            if score > alpha || i == 0 {
+                alpha = alpha.max(score);
+                best = Some((i, score));
+            }
+        }
+        best
+    }
+
+    //Iterative deepening, the best moves of an iteration are searched first in the next one.
+    //Helper `i` skips some depths, the main thread (None) searches them all.
+    fn deepen(&mut self, game : &Game, mut moves : Vec<Move>, helper : Option<usize>, on_iteration : &mut dyn FnMut(&SearchResult)) -> SearchResult {
+        let mut result = SearchResult { best_move : None, score : -INFINITY, nodes : 0, depth : 0, pv : Vec::new(), lines : Vec::new() };
+        let multi_pv = self.options.multi_pv.clamp(1, moves.len());
+        //How often the best move changed lately, and for how many iterations it has not
+        let mut best_move_changes : f64 = 0.0;
+        let mut stable_iterations = 0;
+        let last = self.limits.depth.max(1);
+        for iteration in 1..=last {
+            if let Some(i) = helper.map(|helper| (helper - 1) % SKIP_SIZE.len()) {
+                if ((iteration + SKIP_PHASE[i]) / SKIP_SIZE[i]) % 2 == 1 && iteration < last {
+                    continue;
+                }
+            }
+            //Line k is the best of the moves not in the lines before, moved to moves[k]
+            let mut lines = Vec::new();
+            for k in 0..multi_pv {
+                let Some((best, score)) = self.search_root(game, &moves[k..], iteration) else {
+                    break;
+                };
+                let chessmove = moves.remove(k + best);
+                moves.insert(k, chessmove);
+                lines.push((chessmove, PvLine { score, pv : principal_variation(game, chessmove, self.tt, iteration) }));
+            }
+            if lines.is_empty() {
+                break;
+            }
+            //Stopped midway, the lines of the last iteration complete the ones found
+            let found = lines.len();
+            for line in &result.lines {
+                if lines.len() < multi_pv && !lines.iter().any(|(m, _)| Some(m) == line.pv.first()) {
+                    lines.push((line.pv[0], line.clone()));
+                }
+            }
+            if found == multi_pv {
+                lines.sort_by_key(|(_, line)| -line.score);
+            }
+            for (k, &(chessmove, _)) in lines.iter().enumerate() {
+                let at = moves.iter().position(|&m| m == chessmove).unwrap_or(k);
+                moves.remove(at);
+                moves.insert(k, chessmove);
+            }
+            let previous_score = result.score;
+            let (best_move, best_line) = lines[0].clone();
+            let score = best_line.score;
+            if result.best_move == Some(best_move) {
+                stable_iterations += 1;
+            }
+            else {
+                best_move_changes += 1.0;
+                stable_iterations = 0;
+            }
+            result.best_move = Some(best_move);
+            result.score = score;
+            result.depth = iteration;
+            result.nodes = self.all_nodes();
+            result.pv = best_line.pv;
+            result.lines = lines.into_iter().map(|(_, line)| line).collect();
+            on_iteration(&result);
+            if self.stopped {
+                break;
+            }
+            if let Some(time) = self.limits.time.as_ref().filter(|_| self.main && !self.limits.pondering()) {
+                //Nothing to think about with a single legal move
+                if moves.len() == 1 {
+                    break;
+                }
+                //More time while the best move is unsettled or the score falls, less when it stays the same
+                let mut scale = 1.0 + best_move_changes;
+                if iteration > 1 && previous_score - score >= 100 {
+                    scale *= 1.6;
+                }
+                else if iteration > 1 && previous_score - score >= 30 {
+                    scale *= 1.3;
+                }
+                if stable_iterations >= 4 && iteration >= 6 {
+                    scale *= 0.5;
+                }
+                if !time.start_iteration(scale.min(3.0)) {
+                    break;
+                }
+                best_move_changes /= 2.0;
+            }
+        }
+        self.all_nodes.fetch_add(self.nodes % 1024, Ordering::Relaxed);
+        result
+    }
+}
+
+//Iterative deepening within the limits, `on_iteration` is called with the result of every iteration.
+//`history` holds the keys of the positions of the game before `game`, to see the repetitions.
+//With several threads, the helpers skip depths in different patterns and stop when the main thread is done.
+pub fn search(game : &Game, history : &[u64], limits : &SearchLimits, tt : &TranspositionTable, tablebases : Option<&Tablebases>, options : &SearchOptions, on_iteration : &mut dyn FnMut(&SearchResult)) -> SearchResult {
+    //In the tablebases only the moves keeping the best result are searched
+    let moves : Vec<Move> = match tablebases.and_then(|tb| tb.root_moves(game)) {
+        Some(ranked) => ranked.into_iter().map(|(chessmove, _, _)| chessmove).collect(),
+        None => legal_moves(game).into_iter().map(|(chessmove, _)| chessmove).collect(),
+    };
+    if moves.is_empty() {
+        limits.wait_for_stop();
+        let score = if is_attacked(game.side_to_move, game) { -MATE } else { 0 };
+        return SearchResult { best_move : None, score, nodes : 0, depth : 0, pv : Vec::new(), lines : Vec::new() };
+    }
+    //Stopped before the first iteration completed, any legal move is better than none
+    let fallback = moves[0];
+    let all_nodes = AtomicU64::new(0);
+    let keys : Vec<u64> = history.iter().copied().chain([polyglot_key(game)]).collect();
+    let searcher = |main| Searcher { options, tablebases, limits, tt, heuristics : Heuristics::new(), nodes : 0, all_nodes : &all_nodes, main, stopped : false, keys : keys.clone() };
+    let mut result = std::thread::scope(|scope| {
+        for helper in 1..options.threads.max(1) {
+            let mut searcher = searcher(false);
+            let moves = moves.clone();
+            scope.spawn(move || searcher.deepen(game, moves, Some(helper), &mut |_| {}));
+        }
+        let result = searcher(true).deepen(game, moves, None, on_iteration);
+        limits.wait_for_stop();
+        limits.stop.store(true, Ordering::Relaxed);
+        result
+    });
+    result.best_move = result.best_move.or(Some(fallback));
+    result.nodes = all_nodes.load(Ordering::Relaxed);
+    result
+}
+
+//Positions of the bench, from the usual perft and test suites
+const BENCH_POSITIONS : [&str;8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+];
+
+pub struct BenchReport {
+    pub nodes : u64,
+    pub milliseconds : u128,
+}
+
+//Search every bench position to `depth`, the node count is the signature of the search
+pub fn bench(depth : u32, options : &SearchOptions) -> Result<BenchReport, String> {
+    let now = Instant::now();
+    let mut nodes = 0;
+    for fen in BENCH_POSITIONS {
+        let game = Game::from_fen(fen)?;
+        let tt = TranspositionTable::new(TT_MEGABYTES);
+        nodes += search(&game, &[], &SearchLimits::depth(depth), &tt, None, options, &mut |_| {}).nodes;
+    }
+    Ok(BenchReport { nodes, milliseconds : now.elapsed().as_millis() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_with(game : &Game, history : &[u64], depth : u32, options : &SearchOptions) -> SearchResult {
+        let tt = TranspositionTable::new(TT_MEGABYTES);
+        search(game, history, &SearchLimits::depth(depth), &tt, None, options, &mut |_| {})
+    }
+
+    fn search_depth(game : &Game, history : &[u64], depth : u32) -> SearchResult {
+        search_with(game, history, depth, &SearchOptions::default())
+    }
+
+    //Black is a queen up, its own pieces lock it in the corner, and the white queen checks forever
+    const PERPETUAL : &str = "qn4k1/pp4p1/8/8/8/8/6PP/4Q1K1 w - - 0 1";
+
+    #[test]
+    fn perpetual_check() {
+        let game = Game::from_fen(PERPETUAL).unwrap();
+        for depth in [6, 8] {
+            let result = search_depth(&game, &[], depth);
+            assert_eq!((result.best_move.map(|m| m.to_uci(&game)), result.score), (Some("e1e8".to_string()), 0), "depth {depth}");
+        }
+    }
+
+    #[test]
+    fn repetitions_of_the_game_before_the_root() {
+        let mut game = Game::from_fen(PERPETUAL).unwrap();
+        let mut history = Vec::new();
+        for m in ["e1e8", "g8h7", "e8h5", "h7g8"] {
+            history.push(polyglot_key(&game));
+            game.play(m.parse().unwrap());
+        }
+        //Checking again repeats the position after the first check
+        let result = search_depth(&game, &history, 1);
+        assert_eq!((result.best_move.map(|m| m.to_uci(&game)), result.score), (Some("h5e8".to_string()), 0));
+        assert!(search_depth(&game, &[], 1).score < -300);
+    }
+
+    #[test]
+    fn multi_pv_lines() {
+        //Taking the queen is far ahead of the other moves
+        let game = Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/4P2q/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let single = search_depth(&game, &[], 4);
+        let result = search_with(&game, &[], 4, &SearchOptions { multi_pv : 3, ..SearchOptions::default() });
+        assert_eq!(result.lines.len(), 3);
+        let firsts : Vec<Move> = result.lines.iter().map(|line| line.pv[0]).collect();
+        assert!(firsts.iter().enumerate().all(|(i, m)| !firsts[..i].contains(m)), "{firsts:?}");
+        assert!(result.lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!((result.best_move, result.score, result.pv.first()), (single.best_move, result.lines[0].score, firsts.first()));
+        assert_eq!(single.best_move.map(|m| m.to_uci(&game)), Some("f3h4".to_string()));
+        //More lines than legal moves
+        let game = Game::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let result = search_with(&game, &[], 2, &SearchOptions { multi_pv : 10, ..SearchOptions::default() });
+        assert_eq!(result.lines.len(), 3);
+    }
+
+    #[test]
+    fn one_thread_is_deterministic() {
+        let game = Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+        let run = || {
+            let result = search_depth(&game, &[], 5);
+            (result.best_move, result.score, result.nodes, result.pv)
+        };
+        let first = run();
+        assert!(first.2 > 0);
+        assert_eq!(run(), first);
+    }
+}
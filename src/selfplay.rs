@@ -0,0 +1,577 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use crate::{Game, Color, Piece, legal_moves, is_attacked};
+use crate::engine::{EngineFailure, EngineProcess};
+use crate::moves::{parse_move, Move};
+use crate::pgn::{parse_pgn, PgnGame};
+use crate::san::move_to_san;
+use crate::zobrist::polyglot_key;
+
+//Matches between two UCI engines run as child processes : openings played with both colours,
+//clocks kept by the runner, games adjudicated, an SPRT deciding when to stop
+
+//An engine of the match : the command to run and the UCI options to set
+#[derive(Clone)]
+pub struct EngineConfig {
+    pub name : String,
+    pub command : String,
+    pub args : Vec<String>,
+    pub options : Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    pub fn start(&self) -> Result<EngineProcess, String> {
+        let mut engine = EngineProcess::start(&self.command, &self.args)?;
+        for (name, value) in &self.options {
+            engine.set_option(name, value).map_err(|e| format!("{} : setoption {name} failed, {e:?}", self.name))?;
+        }
+        engine.sync().map_err(|e| format!("{} is not ready, {e:?}", self.name))?;
+        Ok(engine)
+    }
+}
+
+//"Name=Value,Name=Value", the names may contain spaces
+pub fn parse_engine_options(text : &str) -> Result<Vec<(String, String)>, String> {
+    text.split(',').filter(|option| !option.trim().is_empty()).map(|option| {
+        let (name, value) = option.split_once('=').ok_or(format!("expected Name=Value, got '{option}'"))?;
+        Ok((name.trim().to_string(), value.trim().to_string()))
+    }).collect()
+}
+
+//Base time and increment of each side
+#[derive(Debug, Clone, Copy)]
+pub struct TimeControl {
+    pub base : Duration,
+    pub increment : Duration,
+}
+
+//"10+0.1", in seconds
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text : &str) -> Result<Self, Self::Err> {
+        let seconds = |s : &str| s.parse::<f64>().ok().filter(|s| *s >= 0.0).map(Duration::from_secs_f64);
+        let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+        match (seconds(base), seconds(increment)) {
+            (Some(base), Some(increment)) => Ok(TimeControl { base, increment }),
+            _ => Err(format!("invalid time control '{text}', expected <base>+<increment> in seconds")),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}+{}", self.base.as_secs_f64(), self.increment.as_secs_f64())
+    }
+}
+
+pub struct Adjudication {
+    //Draw when both engines see at most `draw_score` for `draw_count` moves each, from move `draw_after`
+    pub draw_after : u32,
+    pub draw_count : u32,
+    pub draw_score : i32,
+    //Win when both engines agree one side is ahead by `resign_score` for `resign_count` moves each
+    pub resign_count : u32,
+    pub resign_score : i32,
+    //Draw past this length
+    pub max_plies : u32,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication { draw_after : 40, draw_count : 8, draw_score : 10, resign_count : 3, resign_score : 600, max_plies : 400 }
+    }
+}
+
+//Sequential probability ratio test of H0 : elo = elo0 against H1 : elo = elo1
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub elo0 : f64,
+    pub elo1 : f64,
+    pub alpha : f64,
+    pub beta : f64,
+}
+
+//Expected score against an opponent `elo` points weaker
+fn expected_score(elo : f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl Sprt {
+    //H0 is accepted below the first bound, H1 above the second
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    //Log-likelihood ratio of the results, with the normal approximation of the generalized SPRT
+    pub fn llr(&self, score : &MatchScore) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        //Half a game of each result when one is missing, or a clean sweep would have no variance
+        let prior = if score.wins == 0 || score.draws == 0 || score.losses == 0 { 0.5 } else { 0.0 };
+        let (wins, draws, losses) = (f64::from(score.wins) + prior, f64::from(score.draws) + prior, f64::from(score.losses) + prior);
+        let games = wins + draws + losses;
+        let (wins, draws) = (wins / games, draws / games);
+        let mean = wins + draws / 2.0;
+        let variance = (wins + draws / 4.0 - mean * mean) / games;
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    //Some(true) when H1 is accepted, Some(false) when H0 is
+    pub fn decision(&self, score : &MatchScore) -> Option<bool> {
+        let (lower, upper) = self.bounds();
+        let llr = self.llr(score);
+        if llr >= upper { Some(true) } else if llr <= lower { Some(false) } else { None }
+    }
+}
+
+//"0,5,0.05,0.05"
+impl FromStr for Sprt {
+    type Err = String;
+
+    fn from_str(text : &str) -> Result<Self, Self::Err> {
+        let values : Vec<f64> = text.split(',').map(|v| v.trim().parse::<f64>()).collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid SPRT '{text}', expected elo0,elo1,alpha,beta"))?;
+        match values[..] {
+            [elo0, elo1, alpha, beta] if elo0 < elo1 && alpha > 0.0 && alpha < 1.0 && beta > 0.0 && beta < 1.0 => Ok(Sprt { elo0, elo1, alpha, beta }),
+            _ => Err(format!("invalid SPRT '{text}', expected elo0 < elo1 and alpha, beta between 0 and 1")),
+        }
+    }
+}
+
+//Starting position and moves of an opening
+#[derive(Clone)]
+pub struct Opening {
+    pub start : Game,
+    pub moves : Vec<Move>,
+}
+
+//Openings of an EPD file, one position per line, or of a PGN file, the first `plies` moves of each game
+pub fn load_openings(path : &str, plies : usize) -> Result<Vec<Opening>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path} : {e}"))?;
+    let openings = if path.to_ascii_lowercase().ends_with(".pgn") {
+        parse_pgn(&text).iter().map(|pgn| {
+            let played = pgn.replay(plies)?;
+            Ok(Opening { start : pgn.start()?, moves : played.into_iter().map(|(_, m)| m).collect() })
+        }).collect::<Result<Vec<_>, String>>()?
+    }
+    else {
+        text.lines().filter(|line| !line.trim().is_empty()).map(|line| {
+            //EPD : the four first fields of a FEN, then operations
+            let fields : Vec<&str> = line.split_whitespace().take(4).collect();
+            Ok(Opening { start : Game::from_fen(&format!("{} 0 1", fields.join(" ")))?, moves : Vec::new() })
+        }).collect::<Result<Vec<_>, String>>()?
+    };
+    if openings.is_empty() {
+        return Err(format!("no opening in {path}"));
+    }
+    Ok(openings)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    fn win_for(color : Color) -> GameResult {
+        if color == Color::WHITE { GameResult::WhiteWins } else { GameResult::BlackWins }
+    }
+
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+pub struct GameRecord {
+    pub round : usize,
+    pub pgn : PgnGame,
+    pub result : GameResult,
+    pub reason : String,
+    //Whether the first engine had white
+    pub first_is_white : bool,
+}
+
+//Results of the first engine
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchScore {
+    pub wins : u32,
+    pub draws : u32,
+    pub losses : u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    //Fraction of the points
+    pub fn points(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games().max(1))
+    }
+
+    //Elo difference and the half width of its 95% confidence interval, none after a clean sweep
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let games = f64::from(self.games());
+        let points = self.points();
+        if points <= 0.0 || points >= 1.0 {
+            return None;
+        }
+        let elo = |score : f64| -400.0 * (1.0 / score.clamp(1e-6, 1.0 - 1e-6) - 1.0).log10();
+        let (wins, draws) = (f64::from(self.wins) / games, f64::from(self.draws) / games);
+        let deviation = ((wins + draws / 4.0 - points * points) / games).sqrt();
+        Some((elo(points), (elo(points + 1.96 * deviation) - elo(points - 1.96 * deviation)) / 2.0))
+    }
+
+    fn add(&mut self, record : &GameRecord) {
+        match (record.result, record.first_is_white) {
+            (GameResult::Draw, _) => self.draws += 1,
+            (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+}
+
+pub struct MatchSettings {
+    pub engines : [EngineConfig;2],
+    pub openings : Vec<Opening>,
+    //The most games with an SPRT
+    pub games : usize,
+    pub concurrency : usize,
+    pub time_control : TimeControl,
+    pub adjudication : Adjudication,
+    pub pgn : Option<String>,
+    pub sprt : Option<Sprt>,
+}
+
+pub struct MatchReport {
+    pub score : MatchScore,
+    pub sprt : Option<bool>,
+}
+
+//No mate can be reached : kings alone or with a single minor piece
+fn insufficient_material(game : &Game) -> bool {
+    let heavy = [Piece::PAWN, Piece::ROOK, Piece::QUEEN].iter().any(|&piece| game.pieces(Color::WHITE, piece) | game.pieces(Color::BLACK, piece) != 0);
+    !heavy && game.occupied().count_ones() <= 3
+}
+
+//The engine to move failed or the game ended, with the reason
+struct Ending {
+    result : GameResult,
+    reason : String,
+    //The engine that crashed or hung and must be restarted
+    failed : Option<usize>,
+}
+
+//Adjudication from the scores reported for each ply, from the point of view of the side that moved
+fn adjudicate(adjudication : &Adjudication, start : &Game, scores : &[Option<i32>]) -> Option<Ending> {
+    //White's point of view of the last `count` plies
+    let last = |count : usize| -> Option<Vec<i32>> {
+        if count == 0 || scores.len() < count {
+            return None;
+        }
+        let first = scores.len() - count;
+        scores[first..].iter().enumerate().map(|(i, score)| {
+            let white_moved = (start.side_to_move == Color::WHITE) == (first + i).is_multiple_of(2);
+            score.map(|s| if white_moved { s } else { -s })
+        }).collect()
+    };
+    if let Some(last) = last(2 * adjudication.resign_count as usize) {
+        let resign = adjudication.resign_score;
+        if last.iter().all(|&s| s >= resign) {
+            return Some(Ending { result : GameResult::WhiteWins, reason : "adjudication : black is lost".to_string(), failed : None });
+        }
+        if last.iter().all(|&s| s <= -resign) {
+            return Some(Ending { result : GameResult::BlackWins, reason : "adjudication : white is lost".to_string(), failed : None });
+        }
+    }
+    let moves = (start.nb_coups as usize + scores.len()) / 2 + 1;
+    if moves >= adjudication.draw_after as usize {
+        if let Some(last) = last(2 * adjudication.draw_count as usize) {
+            if last.iter().all(|&s| s.abs() <= adjudication.draw_score) {
+                return Some(Ending { result : GameResult::Draw, reason : "adjudication : draw".to_string(), failed : None });
+            }
+        }
+    }
+    None
+}
+
+//The end of the game by the rules, or by its length, before the side to move is asked for a move.
+//`keys` holds the keys of every position of the game, the current one last.
+fn game_over(game : &Game, keys : &[u64], plies : usize, adjudication : &Adjudication) -> Option<Ending> {
+    let ending = |result, reason : &str| Some(Ending { result, reason : reason.to_string(), failed : None });
+    let side = game.side_to_move;
+    if legal_moves(game).is_empty() {
+        return if is_attacked(side, game) { ending(GameResult::win_for(!side), "checkmate") } else { ending(GameResult::Draw, "stalemate") };
+    }
+    if game.halfmove >= 100 {
+        return ending(GameResult::Draw, "fifty moves rule");
+    }
+    let key = polyglot_key(game);
+    if keys.iter().filter(|&&k| k == key).count() >= 3 {
+        return ending(GameResult::Draw, "threefold repetition");
+    }
+    if insufficient_material(game) {
+        return ending(GameResult::Draw, "insufficient material");
+    }
+    if plies >= adjudication.max_plies as usize {
+        return ending(GameResult::Draw, "adjudication : maximum length");
+    }
+    None
+}
+
+//Play one game, `white` is the index of the engine with white
+fn play_game(engines : &mut [EngineProcess], white : usize, opening : &Opening, settings : &MatchSettings) -> (Vec<String>, Ending) {
+    let mut game = opening.start;
+    let start_fen = game.to_fen();
+    let position = if start_fen == Game::default().to_fen() { "startpos".to_string() } else { format!("fen {start_fen}") };
+    let mut sans = Vec::new();
+    let mut ucis = Vec::new();
+    let mut keys = vec![polyglot_key(&game)];
+    for &chessmove in &opening.moves {
+        sans.push(move_to_san(&game, chessmove));
+        ucis.push(chessmove.to_uci(&game));
+        game.play(chessmove);
+        keys.push(polyglot_key(&game));
+    }
+    let opening_start = game;
+    let time_control = settings.time_control;
+    let mut clocks = [time_control.base;2];
+    let mut scores = Vec::new();
+    let ending = |result, reason : &str, failed| Ending { result, reason : reason.to_string(), failed };
+    loop {
+        if let Some(ending) = game_over(&game, &keys, sans.len(), &settings.adjudication) {
+            return (sans, ending);
+        }
+        let side = game.side_to_move;
+        let mover = if side == Color::WHITE { white } else { 1 - white };
+        let go = format!("wtime {} btime {} winc {} binc {}", clocks[0].as_millis(), clocks[1].as_millis(),
+            time_control.increment.as_millis(), time_control.increment.as_millis());
+        let full_position = if ucis.is_empty() { position.clone() } else { format!("{position} moves {}", ucis.join(" ")) };
+        //A little more than its clock, past it the engine has lost anyway
+        let timeout = clocks[side.index()] + Duration::from_secs(1);
+        let answer = match engines[mover].go(&full_position, &go, timeout) {
+            Ok(answer) => answer,
+            Err(EngineFailure::TimedOut) => return (sans, ending(GameResult::win_for(!side), "time forfeit, no answer", Some(mover))),
+            Err(EngineFailure::Disconnected) => return (sans, ending(GameResult::win_for(!side), "engine disconnected", Some(mover))),
+        };
+        if answer.elapsed > clocks[side.index()] {
+            return (sans, ending(GameResult::win_for(!side), "time forfeit", None));
+        }
+        clocks[side.index()] = clocks[side.index()] - answer.elapsed + time_control.increment;
+        //Checked against the moves we generate, "0000" or "(none)" are illegal too as the game is not over
+        let chessmove = match parse_move(&answer.bestmove, &game) {
+            Ok(chessmove) => chessmove,
+            Err(e) => return (sans, ending(GameResult::win_for(!side), &format!("illegal move, {e}"), None)),
+        };
+        sans.push(move_to_san(&game, chessmove));
+        ucis.push(chessmove.to_uci(&game));
+        game.play(chessmove);
+        keys.push(polyglot_key(&game));
+        scores.push(answer.score);
+        if let Some(ending) = adjudicate(&settings.adjudication, &opening_start, &scores) {
+            return (sans, ending);
+        }
+    }
+}
+
+fn record(round : usize, first_is_white : bool, opening : &Opening, sans : Vec<String>, ending : Ending, settings : &MatchSettings) -> GameRecord {
+    let names = if first_is_white { [0, 1] } else { [1, 0] }.map(|i| settings.engines[i].name.clone());
+    let mut tags = vec![
+        ("Event".to_string(), "bitboard match".to_string()),
+        ("Site".to_string(), "?".to_string()),
+        ("Round".to_string(), round.to_string()),
+        ("White".to_string(), names[0].clone()),
+        ("Black".to_string(), names[1].clone()),
+        ("Result".to_string(), ending.result.to_pgn().to_string()),
+    ];
+    let fen = opening.start.to_fen();
+    if fen != Game::default().to_fen() {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), fen));
+    }
+    tags.push(("TimeControl".to_string(), settings.time_control.to_string()));
+    tags.push(("Termination".to_string(), ending.reason.clone()));
+    let pgn = PgnGame { tags, moves : sans, result : ending.result.to_pgn().to_string() };
+    GameRecord { round, pgn, result : ending.result, reason : ending.reason, first_is_white }
+}
+
+//What the games played at once share
+struct Progress {
+    score : MatchScore,
+    decision : Option<bool>,
+}
+
+//Play the match, `on_game` is called after every game with the score so far
+pub fn run_match(settings : &MatchSettings, on_game : &(dyn Fn(&GameRecord, &MatchScore) + Sync)) -> Result<MatchReport, String> {
+    let default_opening = [Opening { start : Game::default(), moves : Vec::new() }];
+    let openings = if settings.openings.is_empty() { &default_opening[..] } else { &settings.openings[..] };
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let progress = Mutex::new(Progress { score : MatchScore::default(), decision : None });
+    let pgn = Mutex::new(());
+    let worker = || -> Result<(), String> {
+        let mut engines : [Option<EngineProcess>;2] = [None, None];
+        loop {
+            let index = next_game.fetch_add(1, Ordering::Relaxed);
+            if index >= settings.games || stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            //Each opening twice, with the colours reversed
+            let opening = &openings[(index / 2) % openings.len()];
+            let first_is_white = index.is_multiple_of(2);
+            let mut started = Vec::new();
+            for (i, engine) in engines.iter_mut().enumerate() {
+                if engine.is_none() {
+                    *engine = Some(settings.engines[i].start()?);
+                }
+                let running = engine.as_mut().ok_or("no engine")?;
+                if running.new_game().is_err() {
+                    *engine = Some(settings.engines[i].start()?);
+                }
+            }
+            for engine in engines.iter_mut() {
+                started.push(engine.take().ok_or("no engine")?);
+            }
+            let (sans, ending) = play_game(&mut started, if first_is_white { 0 } else { 1 }, opening, settings);
+            let failed = ending.failed;
+            for (i, engine) in started.into_iter().enumerate() {
+                //An engine that crashed or hung is started again for the next game
+                engines[i] = Some(engine).filter(|_| failed != Some(i));
+            }
+            let record = record(index + 1, first_is_white, opening, sans, ending, settings);
+            if let Some(path) = &settings.pgn {
+                let _lock = pgn.lock().unwrap_or_else(|e| e.into_inner());
+                let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| format!("cannot write {path} : {e}"))?;
+                file.write_all(record.pgn.to_pgn().as_bytes()).map_err(|e| format!("cannot write {path} : {e}"))?;
+            }
+            let mut progress = progress.lock().unwrap_or_else(|e| e.into_inner());
+            progress.score.add(&record);
+            on_game(&record, &progress.score);
+            if let Some(decision) = settings.sprt.and_then(|sprt| sprt.decision(&progress.score)) {
+                progress.decision = Some(decision);
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    };
+    std::thread::scope(|scope| {
+        let workers : Vec<_> = (0..settings.concurrency.max(1)).map(|_| scope.spawn(worker)).collect();
+        workers.into_iter().map(|w| w.join().unwrap_or(Err("a game thread panicked".to_string()))).collect::<Result<Vec<()>, String>>()
+    })?;
+    let progress = progress.into_inner().unwrap_or_else(|e| e.into_inner());
+    Ok(MatchReport { score : progress.score, sprt : progress.decision })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Keys of every position of the game, and the game after the moves
+    fn play(fen : &str, moves : &[&str]) -> (Game, Vec<u64>) {
+        let mut game = Game::from_fen(fen).unwrap();
+        let mut keys = vec![polyglot_key(&game)];
+        for m in moves {
+            game.play(parse_move(m, &game).unwrap());
+            keys.push(polyglot_key(&game));
+        }
+        (game, keys)
+    }
+
+    fn reason(game : &Game, keys : &[u64], plies : usize) -> Option<(GameResult, String)> {
+        game_over(game, keys, plies, &Adjudication::default()).map(|ending| (ending.result, ending.reason))
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let start = Game::default().to_fen();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let (game, keys) = play(&start, &shuffle);
+        assert_eq!(reason(&game, &keys, 4), None);
+        let twice = shuffle.repeat(2);
+        let (game, keys) = play(&start, &twice);
+        assert_eq!(reason(&game, &keys, 8), Some((GameResult::Draw, "threefold repetition".to_string())));
+        //Repeating the position after 1. Nf3, not the start
+        let (game, keys) = play(&start, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "b8c6", "f3g1", "c6b8", "g1f3"]);
+        assert_eq!(reason(&game, &keys, 9), Some((GameResult::Draw, "threefold repetition".to_string())));
+        //Other moves in between
+        let (game, keys) = play(&start, &["g1f3", "g8f6", "f3g1", "f6g8", "b1c3", "b8c6", "c3b1", "c6b8"]);
+        assert_eq!(reason(&game, &keys, 8), Some((GameResult::Draw, "threefold repetition".to_string())));
+        //Castling rights lost on the way make the same squares another position
+        let (game, keys) = play(&start, &["g1f3", "g8f6", "f3g1", "f6g8", "e2e3", "e7e6", "e1e2", "e8e7", "e2e1", "e7e8", "g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(reason(&game, &keys, 14), None);
+    }
+
+    #[cfg(unix)]
+    fn sh_engine(name : &str, go : &str) -> EngineConfig {
+        let path = crate::engine::sh_engine_script(name, go);
+        EngineConfig { name : name.to_string(), command : "sh".to_string(), args : vec![path.to_string_lossy().to_string()], options : Vec::new() }
+    }
+
+    #[cfg(unix)]
+    fn settings(engines : [EngineConfig;2], time_control : &str) -> MatchSettings {
+        MatchSettings { engines, openings : Vec::new(), games : 2, concurrency : 1, time_control : time_control.parse().unwrap(),
+            adjudication : Adjudication::default(), pgn : None, sprt : None }
+    }
+
+    //Both engines shuffle their knights until the start position comes back a third time
+    #[cfg(unix)]
+    #[test]
+    fn repetition_in_a_played_game() {
+        let shuffle = "case $((plies % 4)) in 0) echo 'info score cp 0'; echo 'bestmove g1f3' ;; 1) echo 'bestmove g8f6' ;; 2) echo 'bestmove f3g1' ;; 3) echo 'bestmove f6g8' ;; esac";
+        let settings = settings([sh_engine("shuffle", shuffle), sh_engine("shuffle", shuffle)], "10+0");
+        let mut engines = [settings.engines[0].start().unwrap(), settings.engines[1].start().unwrap()];
+        let opening = Opening { start : Game::default(), moves : Vec::new() };
+        let (sans, ending) = play_game(&mut engines, 0, &opening, &settings);
+        assert_eq!(sans, ["Nf3", "Nf6", "Ng1", "Ng8"].repeat(2));
+        assert_eq!((ending.result, ending.reason.as_str(), ending.failed), (GameResult::Draw, "threefold repetition", None));
+        let report = run_match(&settings, &|_, _| {}).unwrap();
+        assert_eq!((report.score.draws, report.score.games()), (2, 2));
+        std::fs::remove_file(&settings.engines[0].args[0]).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_engines_lose() {
+        let settings = settings([sh_engine("silent", ":"), sh_engine("illegal", "echo 'bestmove e2e5'")], "0.1+0");
+        let mut engines = [settings.engines[0].start().unwrap(), settings.engines[1].start().unwrap()];
+        let opening = Opening { start : Game::default(), moves : Vec::new() };
+        let (_, ending) = play_game(&mut engines, 0, &opening, &settings);
+        assert_eq!((ending.result, ending.reason.as_str(), ending.failed), (GameResult::BlackWins, "time forfeit, no answer", Some(0)));
+        let (_, ending) = play_game(&mut engines, 1, &opening, &settings);
+        assert_eq!(ending.result, GameResult::BlackWins);
+        assert!(ending.reason.starts_with("illegal move"), "{}", ending.reason);
+        for config in &settings.engines {
+            std::fs::remove_file(&config.args[0]).ok();
+        }
+    }
+
+    #[test]
+    fn other_endings() {
+        let (game, keys) = play("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", &["d8h4"]);
+        assert_eq!(reason(&game, &keys, 1), Some((GameResult::BlackWins, "checkmate".to_string())));
+        let (game, keys) = play("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", &[]);
+        assert_eq!(reason(&game, &keys, 0), Some((GameResult::Draw, "stalemate".to_string())));
+        let (game, keys) = play("7k/8/8/8/8/8/8/R6K w - - 99 80", &["a1a2"]);
+        assert_eq!(reason(&game, &keys, 1), Some((GameResult::Draw, "fifty moves rule".to_string())));
+        let (game, keys) = play("7k/8/8/8/8/8/8/N6K w - - 0 1", &[]);
+        assert_eq!(reason(&game, &keys, 0), Some((GameResult::Draw, "insufficient material".to_string())));
+        let (game, keys) = play(&Game::default().to_fen(), &[]);
+        assert_eq!(reason(&game, &keys, 400), Some((GameResult::Draw, "adjudication : maximum length".to_string())));
+        assert_eq!(reason(&game, &keys, 399), None);
+    }
+}
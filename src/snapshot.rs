@@ -0,0 +1,9 @@
+//Compare with data/<file>, written instead when UPDATE_SNAPSHOTS is set
+pub fn check_snapshot(file : &str, content : &str) {
+    let path = format!("{}/data/{file}", env!("CARGO_MANIFEST_DIR"));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, content).unwrap();
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {path} : {e}"));
+    assert!(content == expected, "{file} differs from the snapshot, set UPDATE_SNAPSHOTS to accept the change");
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::str::FromStr;
+
+//A square of the board, a1 = 0, b1 = 1, ..., h8 = 63
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Square(u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquareParseError {
+    //Not a letter followed by a digit
+    BadSyntax,
+    //A letter and a digit outside a1..h8
+    OffBoard,
+}
+
+impl Square {
+    #[inline(always)]
+    pub const fn new(index : u8) -> Square {
+        debug_assert!(index < 64);
+        Square(index)
+    }
+    #[inline(always)]
+    pub const fn from_coords(file : u8, rank : u8) -> Square {
+        Square::new(rank * 8 + file)
+    }
+    #[inline(always)]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+    #[inline(always)]
+    pub const fn bit(self) -> u64 {
+        1u64 << self.0
+    }
+    //0 for the a-file, 7 for the h-file
+    #[inline(always)]
+    pub const fn file(self) -> u8 {
+        self.0 % 8
+    }
+    //0 for the first rank, 7 for the eighth
+    #[inline(always)]
+    pub const fn rank(self) -> u8 {
+        self.0 / 8
+    }
+    //Same square seen from the other side of the board (a1 <-> a8)
+    #[inline(always)]
+    pub const fn flip_rank(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+    //The square `delta` steps further in index, None when leaving the board
+    pub fn offset(self, delta : i8) -> Option<Square> {
+        let index = self.0 as i8 + delta;
+        if (0..64).contains(&index) { Some(Square(index as u8)) } else { None }
+    }
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+}
+
+macro_rules! square_constants {
+    ($($name : ident = $index : expr),* $(,)?) => {
+        impl Square {
+            $(pub const $name : Square = Square($index);)*
+        }
+    };
+}
+
+square_constants!(
+    A1 = 0, B1 = 1, C1 = 2, D1 = 3, E1 = 4, F1 = 5, G1 = 6, H1 = 7,
+    A2 = 8, B2 = 9, C2 = 10, D2 = 11, E2 = 12, F2 = 13, G2 = 14, H2 = 15,
+    A3 = 16, B3 = 17, C3 = 18, D3 = 19, E3 = 20, F3 = 21, G3 = 22, H3 = 23,
+    A4 = 24, B4 = 25, C4 = 26, D4 = 27, E4 = 28, F4 = 29, G4 = 30, H4 = 31,
+    A5 = 32, B5 = 33, C5 = 34, D5 = 35, E5 = 36, F5 = 37, G5 = 38, H5 = 39,
+    A6 = 40, B6 = 41, C6 = 42, D6 = 43, E6 = 44, F6 = 45, G6 = 46, H6 = 47,
+    A7 = 48, B7 = 49, C7 = 50, D7 = 51, E7 = 52, F7 = 53, G7 = 54, H7 = 55,
+    A8 = 56, B8 = 57, C8 = 58, D8 = 59, E8 = 60, F8 = 61, G8 = 62, H8 = 63,
+);
+
+impl fmt::Display for Square {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file()) as char, (b'1' + self.rank()) as char)
+    }
+}
+
+impl FromStr for Square {
+    type Err = SquareParseError;
+
+    fn from_str(square : &str) -> Result<Square, SquareParseError> {
+        let bytes = square.as_bytes();
+        if bytes.len() != 2 || !bytes[0].is_ascii_lowercase() || !bytes[1].is_ascii_digit() {
+            return Err(SquareParseError::BadSyntax);
+        }
+        if !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+            return Err(SquareParseError::OffBoard);
+        }
+        Ok(Square::from_coords(bytes[0] - b'a', bytes[1] - b'1'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squares_round_trip() {
+        for (index, square) in Square::all().enumerate() {
+            let name = square.to_string();
+            assert_eq!(name.parse(), Ok(square));
+            assert_eq!(square.index(), index);
+            assert_eq!(Square::from_coords(square.file(), square.rank()), square);
+            assert_eq!(name.as_bytes(), [b'a' + square.file(), b'1' + square.rank()]);
+        }
+        assert_eq!((Square::A1.to_string(), Square::H8.to_string()), ("a1".to_string(), "h8".to_string()));
+        assert_eq!((Square::E4.file(), Square::E4.rank()), (4, 3));
+        assert_eq!("i1".parse::<Square>(), Err(SquareParseError::OffBoard));
+        assert_eq!("a9".parse::<Square>(), Err(SquareParseError::OffBoard));
+        assert_eq!("e".parse::<Square>(), Err(SquareParseError::BadSyntax));
+        assert_eq!("E4".parse::<Square>(), Err(SquareParseError::BadSyntax));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{Game, piece_char};
+use crate::render::piece_glyph;
+use crate::square::Square;
+
+const LIGHT_SQUARE : &str = "#f0d9b5";
+const DARK_SQUARE : &str = "#b58863";
+const HIGHLIGHT : &str = "#9bc700";
+const ARROW : &str = "#15781b";
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    //Black at the bottom
+    pub flipped : bool,
+    //File letters and rank numbers around the board
+    pub coordinates : bool,
+    //Squares to highlight
+    pub highlight : u64,
+    //Arrows as (from, to) squares
+    pub arrows : Vec<(Square, Square)>,
+    //Side of a square in pixels
+    pub square_size : u32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions { flipped : false, coordinates : true, highlight : 0, arrows : Vec::new(), square_size : 45 }
+    }
+}
+
+//Top left corner of a square in the drawing
+fn square_origin(square : Square, options : &SvgOptions, margin : u32) -> (u32, u32) {
+    let (file, rank) = (square.file() as u32, square.rank() as u32);
+    let (col, row) = if options.flipped { (7 - file, rank) } else { (file, 7 - rank) };
+    (margin + col * options.square_size, margin + row * options.square_size)
+}
+
+//Render the position as a standalone SVG document
+pub fn render_svg(game : &Game, options : &SvgOptions) -> String {
+    let size = options.square_size;
+    let margin = if options.coordinates { size / 2 } else { 0 };
+    let total = 8 * size + 2 * margin;
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{total}\" height=\"{total}\" viewBox=\"0 0 {total} {total}\">\n");
+    svg.push_str(&format!("<defs><marker id=\"arrowhead\" markerWidth=\"4\" markerHeight=\"4\" refX=\"2\" refY=\"2\" orient=\"auto\"><path d=\"M0,0 L4,2 L0,4 z\" fill=\"{ARROW}\"/></marker></defs>\n"));
+    svg.push_str(&format!("<rect x=\"0\" y=\"0\" width=\"{total}\" height=\"{total}\" fill=\"#ffffff\"/>\n"));
+    for square in Square::all() {
+        let (x, y) = square_origin(square, options, margin);
+        let fill = if options.highlight & square.bit() != 0 { HIGHLIGHT }
+            else if (square.rank() + square.file()) % 2 == 1 { LIGHT_SQUARE }
+            else { DARK_SQUARE };
+        svg.push_str(&format!("<rect x=\"{x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\" fill=\"{fill}\"/>\n"));
+    }
+    if options.coordinates {
+        for i in 0..8u32 {
+            let file = if options.flipped { 7 - i } else { i };
+            let rank = if options.flipped { i + 1 } else { 8 - i };
+            let center = margin + i * size + size / 2;
+            let letter = (b'a' + file as u8) as char;
+            svg.push_str(&format!("<text x=\"{center}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\">{letter}</text>\n", total - margin / 3, margin / 2));
+            svg.push_str(&format!("<text x=\"{}\" y=\"{center}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" font-family=\"sans-serif\">{rank}</text>\n", margin / 2, margin / 2));
+        }
+    }
+    for square in Square::all() {
+        let c = piece_char(game, square);
+        if c == ' ' {
+            continue;
+        }
+        let (x, y) = square_origin(square, options, margin);
+        svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" font-family=\"serif\">{}</text>\n",
+            x + size / 2, y + size / 2, size * 4 / 5, piece_glyph(c)));
+    }
+    for (from, to) in &options.arrows {
+        let (x1, y1) = square_origin(*from, options, margin);
+        let (x2, y2) = square_origin(*to, options, margin);
+        svg.push_str(&format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{ARROW}\" stroke-width=\"{}\" stroke-opacity=\"0.8\" marker-end=\"url(#arrowhead)\"/>\n",
+            x1 + size / 2, y1 + size / 2, x2 + size / 2, y2 + size / 2, size / 6));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::check_snapshot;
+
+    #[test]
+    fn start_position() {
+        check_snapshot("svg/start.svg", &render_svg(&Game::default(), &SvgOptions::default()));
+    }
+
+    #[test]
+    fn flipped_board() {
+        let game = Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        check_snapshot("svg/flipped.svg", &render_svg(&game, &SvgOptions { flipped : true, ..SvgOptions::default() }));
+    }
+
+    #[test]
+    fn highlighted_last_move() {
+        let mut game = Game::default();
+        let chessmove = "e2e4".parse().unwrap();
+        game.play(chessmove);
+        let highlight = Square::E2.bit() | Square::E4.bit();
+        let options = SvgOptions { highlight, arrows : vec![(Square::G8, Square::F6)], coordinates : false, ..SvgOptions::default() };
+        check_snapshot("svg/last_move.svg", &render_svg(&game, &options));
+    }
+}
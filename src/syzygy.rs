@@ -0,0 +1,991 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use lazy_static::lazy_static;
+use crate::{Game, Color, Piece, ColoredPiece, KING_MOVE, legal_moves, is_attacked};
+use crate::bitboard::Bitboard;
+use crate::moves::Move;
+
+//Probing of Syzygy WDL (.rtbw) and DTZ (.rtbz) tablebase files, following the layout of the
+//generator of Ronald de Man. Files are read in memory the first time they are needed.
+
+const TB_PIECES : usize = 7;
+const WDL_MAGIC : [u8;4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC : [u8;4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+//Flags of a PairsData
+const STM : u8 = 1;
+const MAPPED : u8 = 2;
+const WIN_PLIES : u8 = 4;
+const LOSS_PLIES : u8 = 8;
+const WIDE : u8 = 16;
+const SINGLE_VALUE : u8 = 128;
+
+//Win/draw/loss from the side to move, cursed wins and blessed losses are drawn by the 50 moves rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value : i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Wdl;
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+struct Encoding {
+    map_pawns : [u64;64],
+    map_b1h1h7 : [u64;64],
+    map_a1d1d4 : [u64;64],
+    map_kk : [[u64;64];10],
+    binomial : [[u64;64];TB_PIECES],
+    lead_pawn_idx : [[u64;64];TB_PIECES],
+    lead_pawns_size : [[u64;4];TB_PIECES],
+}
+
+fn off_a1h8(square : usize) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+lazy_static! {
+    static ref ENCODING : Encoding = {
+        let mut e = Encoding {
+            map_pawns : [0;64], map_b1h1h7 : [0;64], map_a1d1d4 : [0;64], map_kk : [[0;64];10],
+            binomial : [[0;64];TB_PIECES], lead_pawn_idx : [[0;64];TB_PIECES], lead_pawns_size : [[0;4];TB_PIECES],
+        };
+        //Squares below the a1-h8 diagonal
+        let mut code = 0;
+        for s in 0..64 {
+            if off_a1h8(s) < 0 {
+                e.map_b1h1h7[s] = code;
+                code += 1;
+            }
+        }
+        //The a1-d1-d4 triangle, the diagonal squares last
+        let mut diagonal = Vec::new();
+        code = 0;
+        for s in 0..28 {
+            if off_a1h8(s) < 0 && s & 7 <= 3 {
+                e.map_a1d1d4[s] = code;
+                code += 1;
+            }
+            else if off_a1h8(s) == 0 && s & 7 <= 3 {
+                diagonal.push(s);
+            }
+        }
+        for s in diagonal {
+            e.map_a1d1d4[s] = code;
+            code += 1;
+        }
+        //The 462 legal placements of two kings with the first one in the triangle
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..28 {
+                if e.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    if (KING_MOVE[s1] | 1 << s1) & 1 << s2 != 0 || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                        continue;
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx as usize, s2));
+                    }
+                    else {
+                        e.map_kk[idx as usize][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            e.map_kk[idx][s2] = code;
+            code += 1;
+        }
+        e.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..TB_PIECES.min(n + 1) {
+                e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 } + if k < n { e.binomial[k][n - 1] } else { 0 };
+            }
+        }
+        //a2-h7 mapped to 47..0, the leading pawn is the one with the highest value
+        let mut available = 47i64;
+        for lead_pawns in 1..TB_PIECES - 1 {
+            for f in 0..4 {
+                let mut idx = 0;
+                for r in 1..7 {
+                    let sq = r * 8 + f;
+                    if lead_pawns == 1 {
+                        e.map_pawns[sq] = available as u64;
+                        e.map_pawns[sq ^ 7] = (available - 1) as u64;
+                        available -= 2;
+                    }
+                    e.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += e.binomial[lead_pawns - 1][e.map_pawns[sq] as usize];
+                }
+                e.lead_pawns_size[lead_pawns][f] = idx;
+            }
+        }
+        e
+    };
+}
+
+//Material of a table deduced from its name, e.g. "KRPvKR", the first side being white
+struct Material {
+    name : String,
+    piece_count : usize,
+    has_pawns : bool,
+    has_unique_pieces : bool,
+    //Pawns of the leading colour and of the other one
+    pawn_count : [usize;2],
+    symmetric : bool,
+}
+
+impl Material {
+    fn from_name(name : &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        if !white.starts_with('K') || !black.starts_with('K') || !name.chars().all(|c| "KQRBNPv".contains(c)) {
+            return None;
+        }
+        let count = |side : &str, c : char| side.chars().filter(|&p| p == c).count();
+        let piece_count = white.len() + black.len();
+        if piece_count > TB_PIECES || count(white, 'K') != 1 || count(black, 'K') != 1 {
+            return None;
+        }
+        let has_unique_pieces = [white, black].iter().any(|side| "QRBNP".chars().any(|p| count(side, p) == 1));
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        //The leading colour is the one with less pawns, it compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Some(Material {
+            name : name.to_string(),
+            piece_count,
+            has_pawns : white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count : if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] },
+            symmetric : white == black,
+        })
+    }
+}
+
+//Signature of the material of `game` with the pieces of `first` before the 'v'
+fn signature(game : &Game, first : Color) -> String {
+    let mut name = String::new();
+    for color in [first, !first] {
+        for (piece, letter) in [(Piece::KING, 'K'), (Piece::QUEEN, 'Q'), (Piece::ROOK, 'R'), (Piece::BISHOP, 'B'), (Piece::KNIGHT, 'N'), (Piece::PAWN, 'P')] {
+            for _ in 0..game.pieces(color, piece).count_ones() {
+                name.push(letter);
+            }
+        }
+        if color == first {
+            name.push('v');
+        }
+    }
+    name
+}
+
+//Decoding information of one sub-table, offsets point into the bytes of the file
+#[derive(Clone, Default)]
+struct PairsData {
+    flags : u8,
+    min_sym_len : usize,
+    block_size : usize,
+    span : u64,
+    num_blocks : usize,
+    lowest_sym : usize,
+    btree : usize,
+    block_length : usize,
+    block_length_size : usize,
+    sparse_index : usize,
+    sparse_index_size : usize,
+    data : usize,
+    base64 : Vec<u64>,
+    symlen : Vec<u8>,
+    pieces : [u8;TB_PIECES],
+    group_idx : [u64;TB_PIECES + 1],
+    group_len : [usize;TB_PIECES + 1],
+    map_idx : [usize;4],
+}
+
+fn byte(bytes : &[u8], pos : usize) -> u8 {
+    bytes.get(pos).copied().unwrap_or(0)
+}
+fn u16_le(bytes : &[u8], pos : usize) -> u16 {
+    u16::from_le_bytes([byte(bytes, pos), byte(bytes, pos + 1)])
+}
+fn u32_le(bytes : &[u8], pos : usize) -> u32 {
+    u32::from_le_bytes([byte(bytes, pos), byte(bytes, pos + 1), byte(bytes, pos + 2), byte(bytes, pos + 3)])
+}
+fn u32_be(bytes : &[u8], pos : usize) -> u32 {
+    u32::from_be_bytes([byte(bytes, pos), byte(bytes, pos + 1), byte(bytes, pos + 2), byte(bytes, pos + 3)])
+}
+
+impl PairsData {
+    //Left and right halves of the pair `sym`, 12 bits each
+    fn left(&self, bytes : &[u8], sym : usize) -> usize {
+        let at = self.btree + 3 * sym;
+        (usize::from(byte(bytes, at + 1) & 0xF) << 8) | usize::from(byte(bytes, at))
+    }
+    fn right(&self, bytes : &[u8], sym : usize) -> usize {
+        let at = self.btree + 3 * sym;
+        (usize::from(byte(bytes, at + 2)) << 4) | usize::from(byte(bytes, at + 1) >> 4)
+    }
+}
+
+//Number of values minus one represented by `sym` once fully expanded
+fn set_symlen(bytes : &[u8], d : &mut PairsData, sym : usize, visited : &mut [bool]) -> u8 {
+    visited[sym] = true;
+    let right = d.right(bytes, sym);
+    if right == 0xFFF {
+        return 0;
+    }
+    let left = d.left(bytes, sym);
+    for child in [left, right] {
+        if child < visited.len() && !visited[child] {
+            d.symlen[child] = set_symlen(bytes, d, child, visited);
+        }
+    }
+    let len = |child : usize| d.symlen.get(child).copied().unwrap_or(0);
+    len(left).wrapping_add(len(right)).wrapping_add(1)
+}
+
+//Read the Huffman code description of a sub-table, return the position after it
+fn set_sizes(bytes : &[u8], d : &mut PairsData, mut pos : usize) -> Option<usize> {
+    d.flags = *bytes.get(pos)?;
+    pos += 1;
+    if d.flags & SINGLE_VALUE != 0 {
+        d.min_sym_len = usize::from(*bytes.get(pos)?);
+        return Some(pos + 1);
+    }
+    let groups = d.group_len.iter().position(|&len| len == 0)?;
+    let tb_size = d.group_idx[groups];
+    d.block_size = 1 << bytes.get(pos)?;
+    d.span = 1 << bytes.get(pos + 1)?;
+    d.sparse_index_size = tb_size.div_ceil(d.span) as usize;
+    let padding = usize::from(*bytes.get(pos + 2)?);
+    d.num_blocks = u32_le(bytes, pos + 3) as usize;
+    d.block_length_size = d.num_blocks + padding;
+    let max_sym_len = usize::from(*bytes.get(pos + 7)?);
+    d.min_sym_len = usize::from(*bytes.get(pos + 8)?);
+    pos += 9;
+    if max_sym_len < d.min_sym_len || d.min_sym_len == 0 || max_sym_len > 32 {
+        return None;
+    }
+    d.lowest_sym = pos;
+    let lengths = max_sym_len - d.min_sym_len + 1;
+    //Longer codes have lower values, base64[l] is the lowest code of length l padded to 64 bits
+    d.base64 = vec![0;lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = |i : usize| u64::from(u16_le(bytes, d.lowest_sym + 2 * i));
+        d.base64[i] = d.base64[i + 1].wrapping_add(lowest(i)).wrapping_sub(lowest(i + 1)) / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl((64 - i - d.min_sym_len) as u32).unwrap_or(0);
+    }
+    pos += 2 * lengths;
+    let symbols = usize::from(u16_le(bytes, pos));
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0;symbols];
+    let mut visited = vec![false;symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(bytes, d, sym, &mut visited);
+        }
+    }
+    Some(pos + 3 * symbols + (symbols & 1))
+}
+
+//Split the pieces in groups encoded together and compute the index factor of each group
+fn set_groups(m : &Material, d : &mut PairsData, order : [usize;2], f : usize) {
+    let e = &*ENCODING;
+    let mut n = 0;
+    let mut first_len : i32 = if m.has_pawns { 0 } else if m.has_unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+    for i in 1..m.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        }
+        else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+    let pawns_on_both_sides = m.has_pawns && m.pawn_count[1] > 0;
+    let mut next = if pawns_on_both_sides { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pawns_on_both_sides { d.group_len[1] } else { 0 };
+    let mut idx = 1u64;
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if m.has_pawns { e.lead_pawns_size[d.group_len[0]][f] } else if m.has_unique_pieces { 31332 } else { 462 };
+        }
+        else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= e.binomial[d.group_len[1]][48 - d.group_len[0]];
+        }
+        else {
+            d.group_idx[next] = idx;
+            idx *= e.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+//A table read from its file, one sub-table per side to move (WDL only) and per file of the leading pawn
+struct TableData {
+    bytes : Vec<u8>,
+    items : Vec<Vec<PairsData>>,
+}
+
+impl TableData {
+    fn get(&self, stm : usize, file : usize) -> &PairsData {
+        &self.items[if self.items.len() == 2 { stm } else { 0 }][file.min(self.items[0].len() - 1)]
+    }
+
+    fn parse(bytes : Vec<u8>, m : &Material, dtz : bool) -> Option<TableData> {
+        if bytes.get(0..4)? != if dtz { DTZ_MAGIC } else { WDL_MAGIC } {
+            return None;
+        }
+        let flags = *bytes.get(4)?;
+        if (flags & 2 != 0) != m.has_pawns || (!dtz && (flags & 1 != 0) == m.symmetric) {
+            return None;
+        }
+        let mut pos = 5;
+        let sides = if !dtz && !m.symmetric { 2 } else { 1 };
+        let files = if m.has_pawns { 4 } else { 1 };
+        let pawns_on_both_sides = m.has_pawns && m.pawn_count[1] > 0;
+        let mut items = vec![vec![PairsData::default();files];sides];
+        for f in 0..files {
+            let (first, second) = (*bytes.get(pos)?, if pawns_on_both_sides { *bytes.get(pos + 1)? } else { 0xFF });
+            let order = [[usize::from(first & 0xF), usize::from(second & 0xF)], [usize::from(first >> 4), usize::from(second >> 4)]];
+            pos += 1 + usize::from(pawns_on_both_sides);
+            for k in 0..m.piece_count {
+                let pieces = *bytes.get(pos)?;
+                for (i, side) in items.iter_mut().enumerate() {
+                    side[f].pieces[k] = if i == 1 { pieces >> 4 } else { pieces & 0xF };
+                }
+                pos += 1;
+            }
+            for (i, side) in items.iter_mut().enumerate() {
+                set_groups(m, &mut side[f], order[i], f);
+            }
+        }
+        pos += pos & 1;
+        for f in 0..files {
+            for side in items.iter_mut() {
+                pos = set_sizes(&bytes, &mut side[f], pos)?;
+            }
+        }
+        if dtz {
+            for d in items[0].iter_mut() {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                //Four maps, for wins, losses, cursed wins and blessed losses, each prefixed by its length
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = pos + 2;
+                        pos += 2 * usize::from(u16_le(&bytes, pos)) + 2;
+                    }
+                }
+                else {
+                    for i in 0..4 {
+                        d.map_idx[i] = pos + 1;
+                        pos += usize::from(*bytes.get(pos)?) + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+        for f in 0..files {
+            for side in items.iter_mut() {
+                side[f].sparse_index = pos;
+                pos += 6 * side[f].sparse_index_size;
+            }
+        }
+        for f in 0..files {
+            for side in items.iter_mut() {
+                side[f].block_length = pos;
+                pos += 2 * side[f].block_length_size;
+            }
+        }
+        for f in 0..files {
+            for side in items.iter_mut() {
+                pos = (pos + 0x3F) & !0x3F;
+                side[f].data = pos;
+                pos += side[f].num_blocks * side[f].block_size;
+            }
+        }
+        if pos > bytes.len() {
+            return None;
+        }
+        Some(TableData { bytes, items })
+    }
+
+    //Value stored at index `idx` of the sub-table `d`
+    fn decompress(&self, d : &PairsData, idx : u64) -> i32 {
+        if d.flags & SINGLE_VALUE != 0 {
+            return d.min_sym_len as i32;
+        }
+        let bytes = &self.bytes;
+        //The sparse index gives the block and the offset of every span-th value, walk from there
+        let k = (idx / d.span) as usize;
+        let mut block = u32_le(bytes, d.sparse_index + 6 * k) as i64;
+        let mut offset = i64::from(u16_le(bytes, d.sparse_index + 6 * k + 4));
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+        let block_length = |block : i64| i64::from(u16_le(bytes, d.block_length + 2 * block.max(0) as usize));
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+        //Read the canonical Huffman symbols of the block until the one holding our value
+        let mut ptr = d.data + block.max(0) as usize * d.block_size;
+        let mut buf64 = (u64::from(u32_be(bytes, ptr)) << 32) | u64::from(u32_be(bytes, ptr + 4));
+        ptr += 8;
+        let mut buf64_size = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < d.base64.len() && buf64 < d.base64[len] {
+                len += 1;
+            }
+            sym = ((buf64 - d.base64[len]).checked_shr((64 - len - d.min_sym_len) as u32).unwrap_or(0)) as u16;
+            sym = sym.wrapping_add(u16_le(bytes, d.lowest_sym + 2 * len));
+            let sym_len = i64::from(d.symlen.get(usize::from(sym)).copied().unwrap_or(0));
+            if offset < sym_len + 1 {
+                break;
+            }
+            offset -= sym_len + 1;
+            len += d.min_sym_len;
+            buf64 <<= len;
+            buf64_size -= len;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= u64::from(u32_be(bytes, ptr)) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+        //Expand the pairs down to the leaf holding the value
+        let mut sym = usize::from(sym);
+        while d.symlen.get(sym).is_some_and(|&len| len != 0) {
+            let left = d.left(bytes, sym);
+            let left_len = i64::from(d.symlen.get(left).copied().unwrap_or(0));
+            if offset < left_len + 1 {
+                sym = left;
+            }
+            else {
+                offset -= left_len + 1;
+                sym = d.right(bytes, sym);
+            }
+        }
+        d.left(bytes, sym) as i32
+    }
+}
+
+struct Table {
+    material : Material,
+    path : PathBuf,
+    data : OnceLock<Option<TableData>>,
+}
+
+impl Table {
+    fn data(&self, dtz : bool) -> Option<&TableData> {
+        self.data.get_or_init(|| {
+            let bytes = std::fs::read(&self.path).ok()?;
+            TableData::parse(bytes, &self.material, dtz)
+        }).as_ref()
+    }
+}
+
+enum DtzProbe {
+    Value(i32),
+    //The table only stores the other side to move
+    ChangeStm,
+}
+
+#[derive(PartialEq)]
+enum WdlState {
+    Ok,
+    //The best move is a capture or a pawn move, the DTZ table cannot be trusted for it
+    ZeroingBestMove,
+}
+
+//Code of a piece in the tables : pawn 1 to king 6, +8 for black
+fn tb_code(piece : ColoredPiece) -> u8 {
+    piece.piece.index() as u8 + 1 + if piece.color == Color::BLACK { 8 } else { 0 }
+}
+
+fn is_capture(game : &Game, chessmove : Move, piece : Piece) -> bool {
+    game.side(!game.side_to_move) & chessmove.to.bit() != 0 || (piece == Piece::PAWN && chessmove.to.bit() == game.en_passant)
+}
+
+fn dtz_before_zeroing(wdl : Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+#[derive(Default)]
+pub struct Tablebases {
+    wdl : HashMap<String, Table>,
+    dtz : HashMap<String, Table>,
+    pub max_pieces : u32,
+}
+
+impl Tablebases {
+    //Look for the tables in the directories of `paths`, separated like in the PATH variable
+    pub fn open(paths : &str) -> Tablebases {
+        let mut tablebases = Tablebases::default();
+        for dir in std::env::split_paths(paths) {
+            let Ok(files) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in files.flatten().map(|entry| entry.path()) {
+                let (Some(stem), Some(extension)) = (path.file_stem().and_then(|s| s.to_str()), path.extension().and_then(|s| s.to_str())) else {
+                    continue;
+                };
+                let Some(material) = Material::from_name(stem) else {
+                    continue;
+                };
+                let tables = match extension {
+                    "rtbw" => &mut tablebases.wdl,
+                    "rtbz" => &mut tablebases.dtz,
+                    _ => continue,
+                };
+                tablebases.max_pieces = tablebases.max_pieces.max(material.piece_count as u32);
+                tables.entry(stem.to_string()).or_insert(Table { material, path, data : OnceLock::new() });
+            }
+        }
+        tablebases
+    }
+
+    pub fn wdl_count(&self) -> usize {
+        self.wdl.len()
+    }
+    pub fn dtz_count(&self) -> usize {
+        self.dtz.len()
+    }
+
+    //Positions the tables can answer : few enough pieces and no castling right
+    fn covers(&self, game : &Game) -> bool {
+        game.occupied().count_ones() <= self.max_pieces && game.castling_rights() == (false, false, false, false)
+    }
+
+    //Read the value of `game` in its table, kings alone are a draw
+    fn probe_table(&self, game : &Game, dtz : bool, wdl : Wdl) -> Option<DtzProbe> {
+        if game.occupied().count_ones() == 2 {
+            return Some(DtzProbe::Value(0));
+        }
+        let tables = if dtz { &self.dtz } else { &self.wdl };
+        let white_first = signature(game, Color::WHITE);
+        let table = tables.get(&white_first).or_else(|| tables.get(&signature(game, Color::BLACK)))?;
+        let m = &table.material;
+        let data = table.data(dtz)?;
+        let e = &*ENCODING;
+        //Tables are stored with the stronger side as white, and only with white to move when both sides have the same material
+        let symmetric_black_to_move = m.symmetric && game.side_to_move == Color::BLACK;
+        let black_stronger = white_first != m.name;
+        let flip = symmetric_black_to_move || black_stronger;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let stm = usize::from(flip) ^ usize::from(game.side_to_move == Color::BLACK);
+        let mut squares = [0usize;TB_PIECES];
+        let mut pieces = [0u8;TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut lead_pawns_count = 0;
+        let mut tb_file = 0;
+        if m.has_pawns {
+            //The pawns of the leading colour come first, led by the one nearest the edge and the first rank
+            let pawn = data.get(0, 0).pieces[0] ^ flip_color;
+            let color = if pawn & 8 != 0 { Color::BLACK } else { Color::WHITE };
+            lead_pawns = game.pieces(color, Piece::PAWN);
+            for square in Bitboard(lead_pawns) {
+                squares[size] = square.index() ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+            let lead = (0..lead_pawns_count).max_by_key(|&i| e.map_pawns[squares[i]])?;
+            squares.swap(0, lead);
+            tb_file = (squares[0] & 7).min(7 - (squares[0] & 7));
+        }
+        if dtz {
+            let flags = data.get(stm, tb_file).flags;
+            if usize::from(flags & STM) != stm && (m.has_pawns || !m.symmetric) {
+                return Some(DtzProbe::ChangeStm);
+            }
+        }
+        for square in Bitboard(game.occupied() ^ lead_pawns) {
+            squares[size] = square.index() ^ flip_squares;
+            pieces[size] = tb_code(game.piece_at(square)?) ^ flip_color;
+            size += 1;
+        }
+        let d = data.get(stm, tb_file);
+        //Same order of the pieces as in the table
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        if squares[0] & 7 > 3 {
+            for square in squares.iter_mut().take(size) {
+                *square ^= 7;
+            }
+        }
+        let mut idx;
+        if m.has_pawns {
+            idx = e.lead_pawn_idx[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|&s| e.map_pawns[s]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += e.binomial[i][e.map_pawns[square] as usize];
+            }
+        }
+        else {
+            if squares[0] >> 3 > 3 {
+                for square in squares.iter_mut().take(size) {
+                    *square ^= 56;
+                }
+            }
+            //The first piece of the leading group off the a1-h8 diagonal goes below it
+            for i in 0..d.group_len[0] {
+                let off = off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares.iter_mut().take(size).skip(i) {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+            let rank = |s : usize| (s >> 3) as u64;
+            if m.has_unique_pieces {
+                let adjust1 = u64::from(s1 > s0);
+                let adjust2 = u64::from(s2 > s0) + u64::from(s2 > s1);
+                idx = if off_a1h8(s0) != 0 {
+                    (e.map_a1d1d4[s0] * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64 - adjust2
+                }
+                else if off_a1h8(s1) != 0 {
+                    (6 * 63 + rank(s0) * 28 + e.map_b1h1h7[s1]) * 62 + s2 as u64 - adjust2
+                }
+                else if off_a1h8(s2) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 + rank(s0) * 7 * 28 + (rank(s1) - adjust1) * 28 + e.map_b1h1h7[s2]
+                }
+                else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(s0) * 7 * 6 + (rank(s1) - adjust1) * 6 + (rank(s2) - adjust2)
+                };
+            }
+            else {
+                idx = e.map_kk[e.map_a1d1d4[s0] as usize][s1];
+            }
+        }
+        idx *= d.group_idx[0];
+        //The other groups, each one in ascending order of squares
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = m.has_pawns && m.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                n += e.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        let value = data.decompress(d, idx);
+        if !dtz {
+            return Some(DtzProbe::Value(value - 2));
+        }
+        Some(DtzProbe::Value(Self::map_dtz(data, data.get(0, tb_file), value, wdl)))
+    }
+
+    //DTZ values are stored remapped by frequency and in moves or plies, bring them back to plies
+    fn map_dtz(data : &TableData, d : &PairsData, value : i32, wdl : Wdl) -> i32 {
+        let map = match wdl {
+            Wdl::Win | Wdl::Draw => 0,
+            Wdl::Loss => 1,
+            Wdl::CursedWin => 2,
+            Wdl::BlessedLoss => 3,
+        };
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            let at = d.map_idx[map];
+            value = if d.flags & WIDE != 0 {
+                i32::from(u16_le(&data.bytes, at + 2 * value as usize))
+            }
+            else {
+                i32::from(byte(&data.bytes, at + value as usize))
+            };
+        }
+        if (wdl == Wdl::Win && d.flags & WIN_PLIES == 0) || (wdl == Wdl::Loss && d.flags & LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+        value + 1
+    }
+
+    //WDL of the position, captures are searched because the tables ignore en passant and positions
+    //where the best move is a capture may hold a "don't care" value
+    fn search_wdl(&self, game : &Game, check_zeroing : bool) -> Option<(Wdl, WdlState)> {
+        let moves = legal_moves(game);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &(chessmove, piece) in &moves {
+            if !is_capture(game, chessmove, piece) && (!check_zeroing || piece != Piece::PAWN) {
+                continue;
+            }
+            searched += 1;
+            let mut next = *game;
+            next.play(chessmove);
+            let value = -self.search_wdl(&next, false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, WdlState::ZeroingBestMove));
+                }
+            }
+        }
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        }
+        else {
+            match self.probe_table(game, false, Wdl::Draw)? {
+                DtzProbe::Value(value) => Wdl::from_value(value),
+                DtzProbe::ChangeStm => return None,
+            }
+        };
+        if best >= value {
+            let state = if best > Wdl::Draw || no_more_moves { WdlState::ZeroingBestMove } else { WdlState::Ok };
+            return Some((best, state));
+        }
+        Some((value, WdlState::Ok))
+    }
+
+    pub fn probe_wdl(&self, game : &Game) -> Option<Wdl> {
+        if !self.covers(game) {
+            return None;
+        }
+        self.search_wdl(game, false).map(|(wdl, _)| wdl)
+    }
+
+    //Distance to the next capture or pawn move in plies, positive for a win, negative for a loss, 0 for a draw
+    pub fn probe_dtz(&self, game : &Game) -> Option<i32> {
+        if !self.covers(game) {
+            return None;
+        }
+        let (wdl, state) = self.search_wdl(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == WdlState::ZeroingBestMove {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        let sign = (wdl as i32).signum();
+        match self.probe_table(game, true, wdl)? {
+            DtzProbe::Value(dtz) => {
+                let cursed = if wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss { 100 } else { 0 };
+                Some((dtz + cursed) * sign)
+            },
+            DtzProbe::ChangeStm => {
+                //The table holds the other side to move, find the best reply one ply deeper
+                let mut min_dtz = 0xFFFF;
+                for (chessmove, piece) in legal_moves(game) {
+                    let zeroing = is_capture(game, chessmove, piece) || piece == Piece::PAWN;
+                    let mut next = *game;
+                    next.play(chessmove);
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search_wdl(&next, false)?.0)
+                    }
+                    else {
+                        -self.probe_dtz(&next)?
+                    };
+                    if dtz == 1 && is_attacked(next.side_to_move, &next) && legal_moves(&next).is_empty() {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == sign {
+                        min_dtz = dtz;
+                    }
+                }
+                Some(if min_dtz == 0xFFFF { -1 } else { min_dtz })
+            },
+        }
+    }
+
+    //Root moves that keep the best result : the quickest wins by DTZ, any draw, or the slowest losses.
+    //The DTZ is counted from the root, a zeroing move is 1 ply from its result, and a win or a loss
+    //that the 50 moves rule catches before it comes is cursed or blessed.
+    pub fn root_moves(&self, game : &Game) -> Option<Vec<(Move, Wdl, i32)>> {
+        if !self.covers(game) {
+            return None;
+        }
+        let halfmove = i32::from(game.halfmove);
+        let mut ranked = Vec::new();
+        for (chessmove, piece) in legal_moves(game) {
+            let mut next = *game;
+            next.play(chessmove);
+            let dtz = if is_capture(game, chessmove, piece) || piece == Piece::PAWN {
+                dtz_before_zeroing(-self.probe_wdl(&next)?)
+            }
+            //The side to move after the move is mated
+            else if legal_moves(&next).is_empty() && is_attacked(next.side_to_move, &next) {
+                1
+            }
+            else {
+                let dtz = -self.probe_dtz(&next)?;
+                dtz + dtz.signum()
+            };
+            let wdl = match dtz {
+                0 => Wdl::Draw,
+                _ if dtz.abs() + halfmove > 100 => if dtz > 0 { Wdl::CursedWin } else { Wdl::BlessedLoss },
+                _ if dtz > 0 => Wdl::Win,
+                _ => Wdl::Loss,
+            };
+            ranked.push((chessmove, wdl, dtz));
+        }
+        let best = ranked.iter().map(|&(_, wdl, _)| wdl).max()?;
+        ranked.retain(|&(_, wdl, _)| wdl == best);
+        //Wins have a positive DTZ and losses a negative one, so the smallest is the one to keep
+        if let Some(target) = ranked.iter().map(|&(_, _, dtz)| dtz).min() {
+            ranked.retain(|&(_, _, dtz)| dtz == target);
+        }
+        Some(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endgame::{Endgames, Dtm};
+
+    //KQvK and KRvK, small enough to ship with the sources
+    fn real_tables() -> Tablebases {
+        let paths = concat!(env!("CARGO_MANIFEST_DIR"), "/data/syzygy");
+        let tablebases = Tablebases::open(paths);
+        for name in ["KQvK", "KRvK"] {
+            assert!(tablebases.wdl.contains_key(name) && tablebases.dtz.contains_key(name), "no {name}.rtbw and {name}.rtbz in {paths}");
+        }
+        tablebases
+    }
+
+    #[test]
+    fn missing_or_broken_tables() {
+        let tablebases = Tablebases::open("/nonexistent/syzygy");
+        assert_eq!((tablebases.wdl_count(), tablebases.dtz_count(), tablebases.max_pieces), (0, 0, 0));
+        let kqk = Game::from_fen("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&kqk), None);
+        assert!(tablebases.root_moves(&kqk).is_none());
+
+        let dir = std::env::temp_dir().join(format!("bitboard-syzygy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("KQvK.rtbw"), b"not a table").unwrap();
+        std::fs::write(dir.join("KQvK.txt"), b"").unwrap();
+        let tablebases = Tablebases::open(dir.to_str().unwrap());
+        assert_eq!((tablebases.wdl_count(), tablebases.dtz_count(), tablebases.max_pieces), (1, 0, 3));
+        assert_eq!(tablebases.probe_wdl(&kqk), None);
+        assert_eq!(tablebases.probe_dtz(&kqk), None);
+        //Bare kings need no table
+        let kk = Game::from_fen("8/8/8/4k3/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_wdl(&kk), Some(Wdl::Draw));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tables_agree_with_the_solver() {
+        let tablebases = real_tables();
+        let endgames = Endgames::solve(&["KQK", "KRK"]).unwrap();
+        let fens = [
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "8/8/8/4k3/8/8/8/KQ6 b - - 0 1",
+            "8/8/8/8/8/2k5/1R6/7K b - - 0 1",
+            "8/8/8/8/8/2k5/1R6/7K w - - 0 1",
+            "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+            "7k/8/8/8/8/8/8/R3K3 b - - 0 1",
+            "2k5/8/8/8/8/8/8/3QK3 b - - 0 1",
+        ];
+        for fen in fens {
+            let game = Game::from_fen(fen).unwrap();
+            let wdl = tablebases.probe_wdl(&game).unwrap();
+            let dtz = tablebases.probe_dtz(&game).unwrap();
+            match endgames.probe(&game).unwrap() {
+                Dtm::Win(plies) => assert!(wdl == Wdl::Win && dtz > 0 && dtz as u32 <= plies, "{fen}"),
+                Dtm::Loss(plies) => assert!(wdl == Wdl::Loss && dtz < 0 && dtz.unsigned_abs() <= plies, "{fen}"),
+                Dtm::Draw => assert!(wdl == Wdl::Draw && dtz == 0, "{fen}"),
+            }
+        }
+        //Mate in one, and capturing the rook is the only draw
+        let game = Game::from_fen("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        let moves = tablebases.root_moves(&game).unwrap();
+        assert_eq!(moves.iter().map(|&(m, wdl, dtz)| (m.to_uci(&game), wdl, dtz)).collect::<Vec<_>>(), [("h1h8".to_string(), Wdl::Win, 1)]);
+        let game = Game::from_fen("8/8/8/8/8/2k5/1R6/7K b - - 0 1").unwrap();
+        let moves = tablebases.root_moves(&game).unwrap();
+        assert_eq!(moves.iter().map(|&(m, wdl, dtz)| (m.to_uci(&game), wdl, dtz)).collect::<Vec<_>>(), [("c3b2".to_string(), Wdl::Draw, 0)]);
+    }
+
+    #[test]
+    fn fifty_moves_rule_at_the_root() {
+        let tablebases = real_tables();
+        let fen = "8/8/8/4k3/8/8/8/KQ6 w - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        let moves = tablebases.root_moves(&game).unwrap();
+        let dtz = moves[0].2;
+        assert!(moves.iter().all(|&(_, wdl, d)| wdl == Wdl::Win && d == dtz) && dtz > 1);
+        //The win still comes in time, then the rule catches it first
+        let mut late = game;
+        late.halfmove = (100 - dtz) as u16;
+        assert!(tablebases.root_moves(&late).unwrap().iter().all(|&(_, wdl, _)| wdl == Wdl::Win));
+        late.halfmove += 1;
+        assert!(tablebases.root_moves(&late).unwrap().iter().all(|&(_, wdl, _)| wdl == Wdl::CursedWin));
+        //The losing side now holds a blessed loss
+        let mut black = Game::from_fen("8/8/8/4k3/8/8/8/KQ6 b - - 0 1").unwrap();
+        let dtz = tablebases.root_moves(&black).unwrap()[0].2;
+        assert!(dtz < 0);
+        black.halfmove = (101 + dtz) as u16;
+        assert!(tablebases.root_moves(&black).unwrap().iter().all(|&(_, wdl, _)| wdl == Wdl::BlessedLoss));
+    }
+}
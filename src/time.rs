@@ -0,0 +1,105 @@
+use std::time::Duration;
+use crate::Instant;
+
+//Moves assumed left in the game when the GUI does not say
+const DEFAULT_MOVES_TO_GO : u32 = 30;
+
+//Time allotted to one move
+pub struct TimeManager {
+    start : Instant,
+    //No new iteration is started past this, scaled by how stable the search is
+    soft : Duration,
+    //The search stops at once past this
+    hard : Duration,
+    //"go movetime" : the soft limit is not scaled
+    fixed : bool,
+}
+
+impl TimeManager {
+    //A share of the clock, `overhead` is kept aside for the latency of the GUI
+    pub fn new(time_left : Duration, increment : Duration, moves_to_go : Option<u32>, overhead : Duration) -> TimeManager {
+        let available = time_left.saturating_sub(overhead);
+        let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+        let share = available / moves_to_go + increment * 3 / 4;
+        //With the last move before the time control almost everything can be used
+        let ceiling = if moves_to_go == 1 { available * 9 / 10 } else { available / 2 };
+        let hard = (share * 4).min(ceiling).max(Duration::from_millis(1));
+        TimeManager { start : Instant::now(), soft : share.min(hard), hard, fixed : false }
+    }
+
+    //"go movetime", the whole time is used
+    pub fn fixed(movetime : Duration, overhead : Duration) -> TimeManager {
+        let hard = movetime.saturating_sub(overhead).max(Duration::from_millis(1));
+        TimeManager { start : Instant::now(), soft : hard, hard, fixed : true }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.elapsed() >= self.hard
+    }
+
+    //`scale` above 1 when the best move changes or the score drops, below 1 when one move dominates
+    pub fn start_iteration(&self, scale : f64) -> bool {
+        let scale = if self.fixed { 1.0 } else { scale };
+        self.elapsed() < self.soft.mul_f64(scale).min(self.hard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis : u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn limits_stay_within_the_clock() {
+        for time_left in [5, 50, 1000, 60_000, 600_000] {
+            for increment in [0, 100, 2000] {
+                for moves_to_go in [None, Some(0), Some(1), Some(5), Some(40), Some(200)] {
+                    for overhead in [0, 10, 100] {
+                        let time = TimeManager::new(ms(time_left), ms(increment), moves_to_go, ms(overhead));
+                        let case = format!("{time_left} {increment} {moves_to_go:?} {overhead}");
+                        assert!(time.soft <= time.hard, "{case}");
+                        assert!(time.hard <= ms(time_left.saturating_sub(overhead)).max(ms(1)), "{case}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shares_of_the_clock() {
+        let share = |time_left, increment, moves_to_go, overhead| {
+            let time = TimeManager::new(ms(time_left), ms(increment), moves_to_go, ms(overhead));
+            (time.soft.as_millis(), time.hard.as_millis())
+        };
+        //30 moves to go by default, the hard limit four times the soft one
+        assert_eq!(share(60_000, 0, None, 0), (2000, 8000));
+        assert_eq!(share(60_000, 0, Some(10), 0), (6000, 24000));
+        //Three quarters of the increment, the Move Overhead taken off the clock first
+        assert_eq!(share(60_000, 1000, None, 0), (2750, 11000));
+        assert_eq!(share(60_000, 0, None, 3000), (1900, 7600));
+        //Half of the clock at most, nine tenths for the last move before the time control
+        assert_eq!(share(1000, 2000, None, 0), (500, 500));
+        assert_eq!(share(1000, 0, Some(1), 0), (900, 900));
+        let fixed = TimeManager::fixed(ms(1000), ms(50));
+        assert_eq!((fixed.soft, fixed.hard), (ms(950), ms(950)));
+        assert_eq!(TimeManager::fixed(ms(10), ms(50)).hard, ms(1));
+    }
+
+    #[test]
+    fn iterations_scale_with_the_soft_limit() {
+        let time = TimeManager::new(ms(60_000), ms(0), None, ms(0));
+        assert!(time.start_iteration(1.0) && time.start_iteration(0.1) && !time.hard_limit_reached());
+        let spent = TimeManager { start : Instant::now(), soft : ms(0), hard : ms(0), fixed : false };
+        assert!(!spent.start_iteration(2.0) && spent.hard_limit_reached());
+        //A fixed move time ignores the scale
+        let fixed = TimeManager { start : Instant::now(), soft : ms(60_000), hard : ms(60_000), fixed : true };
+        assert!(fixed.start_iteration(0.0));
+    }
+}
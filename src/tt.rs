@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::Piece;
+use crate::moves::Move;
+use crate::square::Square;
+use crate::search::TB_WIN;
+
+//How the stored score bounds the real one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    //The search failed high, the score is at least this
+    Lower,
+    //The search failed low, the score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub key : u64,
+    pub best_move : Option<Move>,
+    pub score : i32,
+    pub depth : u32,
+    pub bound : Bound,
+}
+
+//Hash table of searched positions indexed by the Zobrist key, a new entry always replaces the old one.
+//It is shared by the threads without locks : an entry is two words, the key xored with the data and
+//the data, so an entry torn by two threads writing at once no longer matches its key.
+pub struct TranspositionTable {
+    entries : Vec<[AtomicU64;2]>,
+}
+
+//Data bits : from 0-5, to 6-11, promotion 12-14, has a move 15, score 16-31, depth 32-39, bound 40-41, used 42
+fn pack(entry : &TtEntry) -> u64 {
+    let chessmove = entry.best_move.map_or(0, |m| m.from.index() as u64 | (m.to.index() as u64) << 6 | (m.promotion as u64) << 12 | 1 << 15);
+    let bound = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    chessmove | u64::from(entry.score as i16 as u16) << 16 | u64::from(entry.depth.min(255)) << 32 | bound << 40 | 1 << 42
+}
+
+fn unpack(key : u64, data : u64) -> TtEntry {
+    let square = |shift : u64| Square::new((data >> shift & 63) as u8);
+    let promotion = match data >> 12 & 7 {
+        2 => Piece::KNIGHT,
+        3 => Piece::BISHOP,
+        4 => Piece::ROOK,
+        5 => Piece::QUEEN,
+        _ => Piece::NONE,
+    };
+    let best_move = (data & 1 << 15 != 0).then(|| Move::new(square(0), square(6), promotion));
+    let bound = match data >> 40 & 3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    TtEntry { key, best_move, score : i32::from((data >> 16) as u16 as i16), depth : (data >> 32 & 255) as u32, bound }
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes : usize) -> TranspositionTable {
+        let wanted = megabytes * 1024 * 1024 / std::mem::size_of::<[AtomicU64;2]>();
+        let size = if wanted.is_power_of_two() { wanted } else { wanted.next_power_of_two() / 2 }.max(1);
+        TranspositionTable { entries : (0..size).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect() }
+    }
+
+    pub fn clear(&self) {
+        for [key, data] in &self.entries {
+            key.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key : u64) -> &[AtomicU64;2] {
+        &self.entries[key as usize & (self.entries.len() - 1)]
+    }
+
+    pub fn probe(&self, key : u64) -> Option<TtEntry> {
+        let [stored, data] = self.slot(key);
+        let data = data.load(Ordering::Relaxed);
+        let used = data & 1 << 42 != 0;
+        (used && stored.load(Ordering::Relaxed) ^ data == key).then(|| unpack(key, data))
+    }
+
+    pub fn store(&self, entry : TtEntry) {
+        let [key, data] = self.slot(entry.key);
+        let packed = pack(&entry);
+        key.store(entry.key ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+}
+
+//Mate and tablebase scores are stored relative to the position, not to the root
+pub fn score_to_tt(score : i32, ply : i32) -> i32 {
+    if score >= TB_WIN - 1000 { score + ply } else if score <= -TB_WIN + 1000 { score - ply } else { score }
+}
+
+pub fn score_from_tt(score : i32, ply : i32) -> i32 {
+    if score >= TB_WIN - 1000 { score - ply } else if score <= -TB_WIN + 1000 { score + ply } else { score }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key : u64, best_move : &str, score : i32, depth : u32, bound : Bound) -> TtEntry {
+        TtEntry { key, best_move : best_move.parse().ok(), score, depth, bound }
+    }
+
+    fn fields(entry : TtEntry) -> (u64, Option<String>, i32, u32, Bound) {
+        (entry.key, entry.best_move.map(|m| m.to_string()), entry.score, entry.depth, entry.bound)
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        assert!(tt.probe(0x1234).is_none());
+        for stored in [entry(0x1234, "e7e8n", -350, 12, Bound::Lower), entry(0xABCD, "a2a1q", TB_WIN - 3, 300, Bound::Upper), entry(7, "none", 0, 0, Bound::Exact)] {
+            tt.store(stored);
+            let depth = stored.depth.min(255);
+            assert_eq!(tt.probe(stored.key).map(fields), Some(fields(TtEntry { depth, ..stored })));
+        }
+        tt.clear();
+        assert!(tt.probe(0x1234).is_none());
+    }
+
+    #[test]
+    fn replaced_and_torn_entries_are_rejected() {
+        let tt = TranspositionTable::new(1);
+        let size = tt.entries.len() as u64;
+        //Same slot, the new entry always replaces the old one
+        tt.store(entry(5, "e2e4", 10, 3, Bound::Exact));
+        tt.store(entry(5 + size, "d2d4", 20, 1, Bound::Exact));
+        assert!(tt.probe(5).is_none());
+        assert_eq!(tt.probe(5 + size).map(fields), Some(fields(entry(5 + size, "d2d4", 20, 1, Bound::Exact))));
+        //The data word of another entry written over the key of this one, as two threads would leave it
+        let [_, data] = tt.slot(5 + size);
+        data.store(pack(&entry(5, "g1f3", 30, 8, Bound::Lower)), Ordering::Relaxed);
+        assert!(tt.probe(5).is_none() && tt.probe(5 + size).is_none());
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_position() {
+        for score in [TB_WIN - 10, -TB_WIN + 10, 150, -150] {
+            assert_eq!(score_from_tt(score_to_tt(score, 7), 7), score);
+        }
+        assert_eq!(score_to_tt(TB_WIN - 10, 4), TB_WIN - 6);
+        assert_eq!(score_from_tt(-TB_WIN + 6, 4), -TB_WIN + 10);
+    }
+}
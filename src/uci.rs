@@ -0,0 +1,439 @@
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::{Game, Color, Instant, perft};
+use crate::book::{Book, BookSelection};
+use crate::chess960::chess960_position;
+use crate::moves::parse_move;
+use crate::mate::find_mate_until;
+use crate::mcts::{Mcts, MctsOptions};
+use crate::search::{search, uci_score, PvLine, SearchLimits, SearchOptions, SearchResult, MATE, TT_MEGABYTES};
+use crate::syzygy::Tablebases;
+use crate::zobrist::polyglot_key;
+use crate::time::TimeManager;
+use crate::tt::TranspositionTable;
+
+//Default of the Move Overhead option, in milliseconds
+const MOVE_OVERHEAD : u64 = 10;
+const MAX_THREADS : usize = 256;
+const MAX_HASH : usize = 65536;
+const MAX_MULTI_PV : usize = 256;
+//The playouts of the Monte Carlo search are the same from one run to the next
+const MCTS_SEED : u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub struct Uci {
+    game : Game,
+    //Keys of the positions before `game`, for the repetitions
+    history : Vec<u64>,
+    chess960 : bool,
+    own_book : bool,
+    book : Option<Book>,
+    tablebases : Option<Arc<Tablebases>>,
+    options : SearchOptions,
+    //Kept from move to move, cleared by ucinewgame
+    tt : Arc<TranspositionTable>,
+    //Time lost between the GUI and the engine on each move
+    move_overhead : Duration,
+    //Monte Carlo tree search instead of alpha-beta, its tree is kept from move to move
+    use_mcts : bool,
+    mcts_options : MctsOptions,
+    mcts : Arc<Mutex<Mcts>>,
+    //The search running in the background, its stop and ponder flags
+    searching : Option<(JoinHandle<()>, Arc<AtomicBool>, Arc<AtomicBool>)>,
+}
+
+impl Uci {
+    pub fn new() -> Uci {
+        Uci { game : Game::default(), history : Vec::new(), chess960 : false, own_book : false, book : None, tablebases : None, options : SearchOptions::default(),
+            tt : Arc::new(TranspositionTable::new(TT_MEGABYTES)), move_overhead : Duration::from_millis(MOVE_OVERHEAD),
+            use_mcts : false, mcts_options : MctsOptions::default(), mcts : Arc::new(Mutex::new(Mcts::new(MCTS_SEED))), searching : None }
+    }
+
+    fn set_option(&mut self, name : &str, value : &str) -> Result<(), String> {
+        match name.to_ascii_lowercase().as_str() {
+            "uci_chess960" => {
+                self.chess960 = value == "true";
+                self.game.chess960 = self.chess960 || self.game.has_chess960_castling();
+            },
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" if value.is_empty() || value == "<empty>" => self.book = None,
+            "bookfile" => self.book = Some(Book::open(value).map_err(|e| format!("cannot open the book {value} : {e}"))?),
+            "syzygypath" if value.is_empty() || value == "<empty>" => self.tablebases = None,
+            "syzygypath" => {
+                let tablebases = Tablebases::open(value);
+                println!("info string found {} WDL and {} DTZ tables", tablebases.wdl_count(), tablebases.dtz_count());
+                self.tablebases = Some(Arc::new(tablebases)).filter(|tb| tb.wdl_count() > 0);
+            },
+            //The GUI decides when to ponder, the option only tells it the engine can
+            "ponder" => {},
+            "multipv" => {
+                let lines : usize = value.parse().map_err(|_| format!("invalid number of lines '{value}'"))?;
+                self.options.multi_pv = lines.clamp(1, MAX_MULTI_PV);
+            },
+            //Megabytes of the transposition table, and of the tree of the Monte Carlo search
+            "hash" => {
+                let megabytes : usize = value.parse().map_err(|_| format!("invalid hash size '{value}'"))?;
+                let megabytes = megabytes.clamp(1, MAX_HASH);
+                self.tt = Arc::new(TranspositionTable::new(megabytes));
+                self.mcts_options.max_nodes = Mcts::nodes_in(megabytes);
+            },
+            "threads" => {
+                let threads : usize = value.parse().map_err(|_| format!("invalid number of threads '{value}'"))?;
+                self.options.threads = threads.clamp(1, MAX_THREADS);
+            },
+            "searchmode" => self.use_mcts = value.eq_ignore_ascii_case("mcts"),
+            "mctsiterations" => self.mcts_options.iterations = value.parse().map_err(|_| format!("invalid number of iterations '{value}'"))?,
+            //In hundredths, UCI has no decimal options
+            "mctsexploration" => {
+                let hundredths : u32 = value.parse().map_err(|_| format!("invalid exploration '{value}'"))?;
+                self.mcts_options.exploration = f64::from(hundredths) / 100.0;
+            },
+            "mctsbiased" => self.mcts_options.biased = value == "true",
+            "move overhead" => {
+                let milliseconds : u64 = value.parse().map_err(|_| format!("invalid move overhead '{value}'"))?;
+                self.move_overhead = Duration::from_millis(milliseconds.min(5000));
+            },
+            _ if SearchOptions::NAMES.iter().any(|n| n.eq_ignore_ascii_case(name)) => self.options.set(name, value == "true")?,
+            _ => return Err(format!("unknown option '{name}'")),
+        }
+        Ok(())
+    }
+
+    //position [startpos | fen <fen> | chess960 <n>] [moves <move>...]
+    fn position(&mut self, args : &[&str]) -> Result<(), String> {
+        let moves_at = args.iter().position(|&a| a == "moves").unwrap_or(args.len());
+        let mut game = match args[..moves_at] {
+            ["startpos"] => Game::default(),
+            ["fen", ref fen @ ..] => Game::from_fen(&fen.join(" "))?,
+            ["chess960", n] => n.parse().ok().and_then(chess960_position).ok_or(format!("no Chess960 position '{n}'"))?,
+            _ => return Err("usage : position [startpos | fen <fen>] [moves <move>...]".to_string()),
+        };
+        game.chess960 |= self.chess960;
+        let mut history = Vec::new();
+        for input in args.iter().skip(moves_at + 1) {
+            let chessmove = parse_move(input, &game).map_err(|e| e.to_string())?;
+            history.push(polyglot_key(&game));
+            game.play(chessmove);
+        }
+        self.game = game;
+        self.history = history;
+        Ok(())
+    }
+
+    fn go(&mut self, args : &[&str]) -> Result<(), String> {
+        let now = Instant::now();
+        match args {
+            ["perft", n] => {
+                let depth = n.parse().map_err(|_| format!("invalid depth '{n}'"))?;
+                let nodes = perft(&self.game, depth);
+                println!("info nodes {nodes} time {}", now.elapsed().as_millis());
+            },
+            _ => {
+                //A book move would be sent at once, while pondering or infinite the GUI waits for stop or ponderhit
+                let waits = args.iter().any(|&a| a == "ponder" || a == "infinite");
+                if let Some(book) = self.book.as_ref().filter(|_| self.own_book && !waits) {
+                    if let Some(m) = book.pick(&self.game, BookSelection::Weighted) {
+                        println!("info string book move");
+                        println!("bestmove {}", m.to_uci(&self.game));
+                        return Ok(());
+                    }
+                }
+                let limits = self.limits(args)?;
+                let mate = match args.iter().position(|&a| a == "mate") {
+                    Some(i) => Some(args.get(i + 1).and_then(|n| n.parse::<u32>().ok()).ok_or("usage : go mate <n>")?),
+                    None => None,
+                };
+                let (stop, ponder) = (limits.stop.clone(), limits.ponder.clone());
+                let (game, history, tt, tablebases, options) = (self.game, self.history.clone(), self.tt.clone(), self.tablebases.clone(), self.options.clone());
+                let mcts = self.mcts.clone();
+                let mcts_options = self.use_mcts.then(|| self.mcts_options.clone());
+                //Searched on its own thread so that "stop" can be read meanwhile
+                let handle = std::thread::spawn(move || {
+                    if let Some(moves) = mate {
+                        match find_mate_until(&game, moves, &limits) {
+                            Some(line) => {
+                                let pv = PvLine { score : MATE - line.len() as i32, pv : line };
+                                let result = SearchResult { best_move : pv.pv.first().copied(), score : pv.score, nodes : 0, depth : pv.pv.len() as u32, pv : pv.pv.clone(), lines : vec![pv] };
+                                println!("{}", info(&game, &result, now));
+                                limits.wait_for_stop();
+                                print_best_move(&game, &result);
+                                return;
+                            },
+                            //Out of time, the search only finds a move to play
+                            None if limits.time.as_ref().is_some_and(TimeManager::hard_limit_reached) => {},
+                            //Still a move to play, searched within the limits of the GUI
+                            None if !limits.stop.load(Ordering::Relaxed) => println!("info string no mate in {moves}"),
+                            None => {},
+                        }
+                    }
+                    let mut report = |result : &SearchResult| {
+                        println!("{}", info(&game, result, now));
+                        io::stdout().flush().ok();
+                    };
+                    let result = match mcts_options {
+                        Some(mcts_options) => {
+                            //A search that panicked left the tree as good as any
+                            let mut tree = mcts.lock().unwrap_or_else(|e| e.into_inner());
+                            tree.search(&game, &mcts_options, &limits, &mut report)
+                        },
+                        None => search(&game, &history, &limits, &tt, tablebases.as_deref(), &options, &mut report),
+                    };
+                    print_best_move(&game, &result);
+                });
+                self.searching = Some((handle, stop, ponder));
+            },
+        }
+        Ok(())
+    }
+
+    //Let the search in progress finish
+    fn wait(&mut self) {
+        if let Some((handle, _, _)) = self.searching.take() {
+            handle.join().ok();
+        }
+    }
+
+    //End the search in progress, it still answers with its best move
+    fn stop(&mut self) {
+        if let Some((_, stop, _)) = &self.searching {
+            stop.store(true, Ordering::Relaxed);
+        }
+        self.wait();
+    }
+
+    //go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>] [movetime <ms>] [depth <n>] [nodes <n>] [mate <n>] [infinite] [ponder]
+    fn limits(&self, args : &[&str]) -> Result<SearchLimits, String> {
+        let value = |name : &str| -> Result<Option<u64>, String> {
+            match args.iter().position(|&a| a == name) {
+                Some(i) => args.get(i + 1).and_then(|n| n.parse().ok()).map(Some).ok_or(format!("usage : go {name} <n>")),
+                None => Ok(None),
+            }
+        };
+        let milliseconds = |name : &str| value(name).map(|v| v.map(Duration::from_millis));
+        let (time_left, increment) = match self.game.side_to_move {
+            Color::WHITE => (milliseconds("wtime")?, milliseconds("winc")?),
+            Color::BLACK => (milliseconds("btime")?, milliseconds("binc")?),
+        };
+        let time = match (milliseconds("movetime")?, time_left) {
+            (Some(movetime), _) => Some(TimeManager::fixed(movetime, self.move_overhead)),
+            (None, Some(time_left)) => {
+                let moves_to_go = value("movestogo")?.map(|n| n as u32);
+                Some(TimeManager::new(time_left, increment.unwrap_or_default(), moves_to_go, self.move_overhead))
+            },
+            (None, None) => None,
+        };
+        let depth = value("depth")?.map(|n| n as u32);
+        let mut limits = match time {
+            Some(time) => SearchLimits::time(time),
+            //Until "stop"
+            None if args.contains(&"infinite") => SearchLimits::infinite(),
+            None => SearchLimits::depth(4),
+        };
+        if let Some(depth) = depth {
+            limits.depth = depth;
+        }
+        limits.nodes = value("nodes")?;
+        limits.ponder.store(args.contains(&"ponder"), Ordering::Relaxed);
+        Ok(limits)
+    }
+
+    //Execute one command of the protocol, return false on quit
+    pub fn execute(&mut self, line : &str) -> Result<bool, String> {
+        let words : Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(true);
+        };
+        //The GUI stops the search before anything but isready, stop and ponderhit, if not it is waited for
+        if !matches!(command, "isready" | "stop" | "ponderhit" | "quit") {
+            self.wait();
+        }
+        match command {
+            "uci" => {
+                println!("id name bitboard {}", env!("CARGO_PKG_VERSION"));
+                println!("id author the bitboard developers");
+                println!("option name UCI_Chess960 type check default false");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name Hash type spin default {TT_MEGABYTES} min 1 max {MAX_HASH}");
+                println!("option name Threads type spin default 1 min 1 max {MAX_THREADS}");
+                println!("option name Ponder type check default false");
+                println!("option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}");
+                println!("option name SearchMode type combo default AlphaBeta var AlphaBeta var MCTS");
+                println!("option name MCTSIterations type spin default {} min 1 max 100000000", MctsOptions::default().iterations);
+                println!("option name MCTSExploration type spin default {} min 0 max 1000", (MctsOptions::default().exploration * 100.0) as u32);
+                println!("option name MCTSBiased type check default true");
+                println!("option name Move Overhead type spin default {MOVE_OVERHEAD} min 0 max 5000");
+                for name in SearchOptions::NAMES {
+                    println!("option name {name} type check default true");
+                }
+                println!("uciok");
+            },
+            "isready" => println!("readyok"),
+            "setoption" => {
+                //setoption name <name> [value <value>], names may contain spaces
+                let value_at = args.iter().position(|&a| a == "value").unwrap_or(args.len());
+                let name = args.get(1..value_at).ok_or("usage : setoption name <name> value <value>")?.join(" ");
+                let value = args.get(value_at + 1..).map(|v| v.join(" ")).unwrap_or_default();
+                self.set_option(&name, &value)?;
+            },
+            "ucinewgame" => {
+                self.game = Game::default();
+                self.history.clear();
+                self.tt.clear();
+                self.mcts = Arc::new(Mutex::new(Mcts::new(MCTS_SEED)));
+            },
+            "position" => self.position(args)?,
+            "go" => self.go(args)?,
+            "stop" => self.stop(),
+            //The opponent played the expected move, the search goes on with the time limits
+            "ponderhit" => {
+                if let Some((_, _, ponder)) = &self.searching {
+                    ponder.store(false, Ordering::Relaxed);
+                }
+            },
+            "d" => print!("{}", self.game),
+            "quit" => {
+                self.stop();
+                return Ok(false);
+            },
+            _ => return Err(format!("unknown command '{command}'")),
+        }
+        Ok(true)
+    }
+}
+
+//The info lines of an iteration, one per line of a multi-PV search
+fn info(game : &Game, result : &SearchResult, start : Instant) -> String {
+    let milliseconds = start.elapsed().as_millis();
+    let nps = u128::from(result.nodes) * 1000 / milliseconds.max(1);
+    let lines : Vec<String> = result.lines.iter().enumerate().map(|(k, line)| {
+        let mut pv_game = *game;
+        let pv : Vec<String> = line.pv.iter().map(|&m| {
+            let uci = m.to_uci(&pv_game);
+            pv_game.play(m);
+            uci
+        }).collect();
+        format!("info depth {} multipv {} score {} nodes {} nps {nps} time {milliseconds} pv {}",
+            result.depth, k + 1, uci_score(line.score), result.nodes, pv.join(" "))
+    }).collect();
+    lines.join("\n")
+}
+
+fn print_best_move(game : &Game, result : &SearchResult) {
+    match result.best_move {
+        //The expected reply, to ponder on
+        Some(m) => match result.pv.get(1) {
+            Some(&reply) => {
+                let mut next = *game;
+                next.play(m);
+                println!("bestmove {} ponder {}", m.to_uci(game), reply.to_uci(&next));
+            },
+            None => println!("bestmove {}", m.to_uci(game)),
+        },
+        None => println!("bestmove 0000"),
+    }
+    io::stdout().flush().ok();
+}
+
+//Speak UCI on stdin and stdout, `first` is a line the caller already read from the GUI, if any
+pub fn run(first : &str) {
+    let mut uci = Uci::new();
+    let stdin = io::stdin();
+    let mut line = String::from(first);
+    loop {
+        match uci.execute(line.trim()) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(e) => println!("info string {e}"),
+        }
+        io::stdout().flush().ok();
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+    }
+    //Input closed, the search still gets to answer
+    uci.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The search thread prints bestmove as it ends
+    fn answered(uci : &Uci) -> bool {
+        uci.searching.as_ref().is_none_or(|(handle, _, _)| handle.is_finished())
+    }
+
+    fn answered_within(uci : &Uci, limit : Duration) -> bool {
+        let start = Instant::now();
+        while !answered(uci) && start.elapsed() < limit {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        answered(uci)
+    }
+
+    #[test]
+    fn infinite_waits_for_stop() {
+        for mode in ["AlphaBeta", "MCTS"] {
+            let mut uci = Uci::new();
+            uci.execute(&format!("setoption name SearchMode value {mode}")).unwrap();
+            uci.execute("setoption name MCTSIterations value 100").unwrap();
+            uci.execute("position startpos moves e2e4").unwrap();
+            //The last depth is reached at once, the answer still waits
+            uci.execute("go infinite depth 2").unwrap();
+            assert!(!answered_within(&uci, Duration::from_millis(300)), "{mode}");
+            uci.execute("stop").unwrap();
+            assert!(uci.searching.is_none());
+        }
+        //Even without any legal move
+        let mut uci = Uci::new();
+        uci.execute("position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        uci.execute("go infinite").unwrap();
+        assert!(!answered_within(&uci, Duration::from_millis(100)));
+        uci.execute("stop").unwrap();
+    }
+
+    #[test]
+    fn ponder_waits_for_ponderhit() {
+        let mut uci = Uci::new();
+        uci.execute("position startpos moves e2e4 e7e5").unwrap();
+        uci.execute("go ponder depth 2").unwrap();
+        assert!(!answered_within(&uci, Duration::from_millis(300)));
+        //The opponent played the expected move, the search ends with its own limits
+        uci.execute("ponderhit").unwrap();
+        assert!(answered_within(&uci, Duration::from_secs(10)));
+        //A depth limited search answers by itself
+        uci.execute("go depth 2").unwrap();
+        assert!(answered_within(&uci, Duration::from_secs(10)));
+        uci.execute("quit").unwrap();
+    }
+
+    #[test]
+    fn mate_search_keeps_to_the_clock() {
+        let mut uci = Uci::new();
+        uci.execute("position startpos").unwrap();
+        let start = Instant::now();
+        uci.execute("go mate 10 movetime 300").unwrap();
+        assert!(answered_within(&uci, Duration::from_secs(2)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        //The clock of the side to move is a limit too
+        uci.execute("go mate 10 wtime 1000 btime 1000").unwrap();
+        assert!(answered_within(&uci, Duration::from_secs(5)));
+        uci.execute("quit").unwrap();
+    }
+
+    #[test]
+    fn no_mate_still_answers() {
+        //Without a clock, the move comes from a search as deep as a plain "go"
+        let mut uci = Uci::new();
+        uci.execute("position startpos").unwrap();
+        uci.execute("go mate 3").unwrap();
+        assert!(answered_within(&uci, Duration::from_secs(10)));
+        uci.execute("quit").unwrap();
+    }
+}
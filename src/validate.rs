@@ -0,0 +1,206 @@
+use std::fmt;
+use crate::{Game, Color, ColoredPiece, Piece, PIECES, RANK_MASK, is_attacked};
+use crate::bitboard::Bitboard;
+use crate::square::Square;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    //A side without exactly one king
+    KingCount(Color, u32),
+    //More than 8 pawns or 16 pieces for one side
+    TooManyPawns(Color),
+    TooManyPieces(Color),
+    //A pawn on the first or the last rank
+    PawnOnBackRank(Square),
+    //A square set in more than one bitboard
+    OverlappingPieces(Square),
+    //The mailbox disagrees with the bitboards
+    MailboxMismatch(Square),
+    //The side that just moved left its king in check
+    OpponentInCheck(Color),
+    //A castling right while the king or the rook left its square
+    InvalidCastling(Color, bool),
+    //No pawn could just have made a double push to this square
+    InvalidEnPassant(Square),
+    //A fullmove number past what the move counter holds
+    FullmoveOutOfRange(u16),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::KingCount(color, count) => write!(f, "{color:?} has {count} kings instead of one"),
+            PositionError::TooManyPawns(color) => write!(f, "{color:?} has more than 8 pawns"),
+            PositionError::TooManyPieces(color) => write!(f, "{color:?} has more than 16 pieces"),
+            PositionError::PawnOnBackRank(square) => write!(f, "pawn on the back rank at {square}"),
+            PositionError::OverlappingPieces(square) => write!(f, "more than one piece on {square}"),
+            PositionError::MailboxMismatch(square) => write!(f, "the mailbox and the bitboards disagree on {square}"),
+            PositionError::OpponentInCheck(color) => write!(f, "{color:?} is in check but it is not its turn"),
+            PositionError::InvalidCastling(color, king_side) => {
+                let wing = if *king_side { "king" } else { "queen" };
+                write!(f, "{color:?} can castle {wing} side but its king or rook has moved")
+            },
+            PositionError::InvalidEnPassant(square) => write!(f, "{square} is not a valid en passant square"),
+            PositionError::FullmoveOutOfRange(fullmove) => write!(f, "fullmove number {fullmove} is above {}", crate::fen::MAX_FULLMOVE),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl Game {
+    //Every reason why this position cannot be reached, checked before the move generator sees it
+    pub fn validate(&self) -> Result<(), Vec<PositionError>> {
+        let mut errors = Vec::new();
+        let mut seen = 0u64;
+        for color in [Color::WHITE, Color::BLACK] {
+            for piece in PIECES {
+                let bitboard = self.pieces(color, piece);
+                for square in Bitboard(seen & bitboard) {
+                    errors.push(PositionError::OverlappingPieces(square));
+                }
+                seen |= bitboard;
+            }
+        }
+        for square in Square::all() {
+            let expected = [Color::WHITE, Color::BLACK].into_iter()
+                .flat_map(|color| PIECES.into_iter().map(move |piece| ColoredPiece::new(color, piece)))
+                .find(|p| self.pieces(p.color, p.piece) & square.bit() != 0);
+            if expected != self.piece_at(square) {
+                errors.push(PositionError::MailboxMismatch(square));
+            }
+        }
+        for color in [Color::WHITE, Color::BLACK] {
+            let kings = self.pieces(color, Piece::KING).count_ones();
+            if kings != 1 {
+                errors.push(PositionError::KingCount(color, kings));
+            }
+            if self.pieces(color, Piece::PAWN).count_ones() > 8 {
+                errors.push(PositionError::TooManyPawns(color));
+            }
+            if self.side(color).count_ones() > 16 {
+                errors.push(PositionError::TooManyPieces(color));
+            }
+            for square in Bitboard(self.pieces(color, Piece::PAWN) & (RANK_MASK[0] | RANK_MASK[7])) {
+                errors.push(PositionError::PawnOnBackRank(square));
+            }
+        }
+        for color in [Color::WHITE, Color::BLACK] {
+            let rank = if color == Color::WHITE { 0 } else { 7 };
+            let king = self.king_square(color);
+            for king_side in [true, false] {
+                let Some(rook) = self.castling_rook(color, king_side) else {
+                    continue;
+                };
+                let valid = self.pieces(color, Piece::KING) != 0 && king.rank() == rank && rook.rank() == rank
+                    && self.pieces(color, Piece::ROOK) & rook.bit() != 0
+                    && (rook.file() > king.file()) == king_side;
+                if !valid {
+                    errors.push(PositionError::InvalidCastling(color, king_side));
+                }
+            }
+        }
+        if let Some(square) = Bitboard(self.en_passant).lsb() {
+            //The pawn went from `origin` to `pushed` over `square`
+            let (rank, forward) = if self.side_to_move == Color::WHITE { (5, 8) } else { (2, -8) };
+            let valid = self.en_passant.count_ones() == 1 && square.rank() == rank
+                && self.piece_at(square).is_none()
+                && square.offset(forward).is_some_and(|origin| self.piece_at(origin).is_none())
+                && square.offset(-forward).is_some_and(|pushed| self.piece_at(pushed) == Some(ColoredPiece::new(!self.side_to_move, Piece::PAWN)));
+            if !valid {
+                errors.push(PositionError::InvalidEnPassant(square));
+            }
+        }
+        let waiting = !self.side_to_move;
+        if self.pieces(waiting, Piece::KING).count_ones() == 1 && is_attacked(waiting, self) {
+            errors.push(PositionError::OpponentInCheck(waiting));
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(fen : &str, edit : impl FnOnce(&mut Game)) -> Vec<PositionError> {
+        let mut game = Game::from_fen(fen).unwrap();
+        edit(&mut game);
+        game.validate().err().unwrap_or_default()
+    }
+
+    const KINGS : &str = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+
+    #[test]
+    fn king_count() {
+        assert_eq!(errors(KINGS, |game| { game.remove_piece(Square::E8); }), [PositionError::KingCount(Color::BLACK, 0)]);
+        let white_king = ColoredPiece::new(Color::WHITE, Piece::KING);
+        assert_eq!(errors(KINGS, |game| game.put_piece(Square::A1, white_king)), [PositionError::KingCount(Color::WHITE, 2)]);
+    }
+
+    #[test]
+    fn too_many_pawns_or_pieces() {
+        let pawn = ColoredPiece::new(Color::WHITE, Piece::PAWN);
+        assert_eq!(errors("4k3/8/8/8/8/8/PPPPPPPP/4K3 w - - 0 1", |game| game.put_piece(Square::E4, pawn)), [PositionError::TooManyPawns(Color::WHITE)]);
+        let queen = ColoredPiece::new(Color::WHITE, Piece::QUEEN);
+        assert_eq!(errors("4k3/8/8/8/PPPPPPPP/QQQQQQQ1/8/4K3 w - - 0 1", |game| game.put_piece(Square::A1, queen)), [PositionError::TooManyPieces(Color::WHITE)]);
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        let pawn = ColoredPiece::new(Color::BLACK, Piece::PAWN);
+        assert_eq!(errors(KINGS, |game| game.put_piece(Square::H1, pawn)), [PositionError::PawnOnBackRank(Square::H1)]);
+    }
+
+    #[test]
+    fn overlapping_pieces() {
+        //The mailbox keeps the white king, which comes first in the bitboards
+        assert_eq!(errors(KINGS, |game| game.bitboards[Color::BLACK.index()][Piece::ROOK.index()] |= Square::E1.bit()),
+            [PositionError::OverlappingPieces(Square::E1)]);
+    }
+
+    #[test]
+    fn mailbox_mismatch() {
+        assert_eq!(errors(KINGS, |game| game.mailbox[Square::D4.index()] = Some(ColoredPiece::new(Color::BLACK, Piece::KNIGHT))),
+            [PositionError::MailboxMismatch(Square::D4)]);
+    }
+
+    #[test]
+    fn opponent_in_check() {
+        let rook = ColoredPiece::new(Color::WHITE, Piece::ROOK);
+        assert_eq!(errors(KINGS, |game| game.put_piece(Square::E4, rook)), [PositionError::OpponentInCheck(Color::BLACK)]);
+    }
+
+    #[test]
+    fn invalid_castling() {
+        //No rook on h1, then a rook that is not on the side it castles to
+        assert_eq!(errors(KINGS, |game| game.set_castling_rights((true, false, false, false))), [PositionError::InvalidCastling(Color::WHITE, true)]);
+        assert_eq!(errors("r3k3/8/8/8/8/8/8/4K3 w - - 0 1", |game| game.set_castling_rights((false, false, true, false))),
+            [PositionError::InvalidCastling(Color::BLACK, true)]);
+    }
+
+    #[test]
+    fn invalid_en_passant() {
+        //A square on the wrong rank, then one with no pawn in front of it
+        assert_eq!(errors("4k3/8/8/4p3/8/8/8/4K3 w - - 0 1", |game| game.en_passant = Square::E3.bit()), [PositionError::InvalidEnPassant(Square::E3)]);
+        assert_eq!(errors(KINGS, |game| game.en_passant = Square::D6.bit()), [PositionError::InvalidEnPassant(Square::D6)]);
+    }
+
+    #[test]
+    fn every_error_is_listed() {
+        let errors = errors(KINGS, |game| {
+            game.remove_piece(Square::E8);
+            game.put_piece(Square::A8, ColoredPiece::new(Color::WHITE, Piece::PAWN));
+            game.mailbox[Square::D4.index()] = Some(ColoredPiece::new(Color::BLACK, Piece::KNIGHT));
+            game.set_castling_rights((false, false, false, true));
+            game.en_passant = Square::C6.bit();
+        });
+        assert_eq!(errors, [
+            PositionError::MailboxMismatch(Square::D4),
+            PositionError::PawnOnBackRank(Square::A8),
+            PositionError::KingCount(Color::BLACK, 0),
+            PositionError::InvalidCastling(Color::BLACK, false),
+            PositionError::InvalidEnPassant(Square::C6),
+        ]);
+    }
+}